@@ -0,0 +1,35 @@
+use chrono::{DateTime, Duration, Utc};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use member_loader::config::Config;
+use member_loader::members::Party;
+use member_loader::parse::{merge_parties, parse_addresses_xml};
+
+const COMMONS_FIXTURE: &str = include_str!("../fixtures/commons.xml");
+
+fn parse_benchmark(c: &mut Criterion) {
+    let config = Config::default();
+    let count = parse_addresses_xml(COMMONS_FIXTURE, &config).unwrap().members.len();
+
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Elements(count as u64));
+    group.bench_function("parse_addresses_xml", |b| b.iter(|| parse_addresses_xml(black_box(COMMONS_FIXTURE), &config)));
+    group.finish();
+}
+
+fn merge_parties_benchmark(c: &mut Criterion) {
+    let start: DateTime<Utc> = "1950-01-01T00:00:00Z".parse().unwrap();
+    let parties: Vec<Party> = (0..10_000).map(|i| Party {
+        name: if i % 7 < 4 { "Labour".to_string() } else { "Independent".to_string() },
+        original_name: "".to_string(),
+        start_date: start + Duration::days(i),
+        end_date: Some(start + Duration::days(i + 1)),
+    }).collect();
+
+    let mut group = c.benchmark_group("merge_parties");
+    group.throughput(Throughput::Elements(parties.len() as u64));
+    group.bench_function("merge_parties", |b| b.iter(|| merge_parties(black_box(&parties))));
+    group.finish();
+}
+
+criterion_group!(benches, parse_benchmark, merge_parties_benchmark);
+criterion_main!(benches);
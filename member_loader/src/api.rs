@@ -0,0 +1,477 @@
+use crate::encoding;
+use crate::members::{AdditionalData, House, Members};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::cell::Cell;
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+pub const DEFAULT_MAX_BODY_SIZE: usize = 256 * 1024 * 1024;
+
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+pub const MEMBER_API_BASE: &str = "https://data.parliament.uk/membersdataplatform/services/mnisv1.0/Members/Query/";
+
+#[derive(Debug, Error)]
+pub enum ApiError {
+    #[error("request to {url} failed")]
+    Request {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+    #[error("refusing to use insecure URL {url}")]
+    Insecure {
+        url: String,
+    },
+    #[error("unable to read CA certificate {}", .path.display())]
+    CertificateRead {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("invalid CA certificate {}", .path.display())]
+    Certificate {
+        path: PathBuf,
+        #[source]
+        source: reqwest::Error,
+    },
+    #[error("{url} returned an HTML page instead of member data, the service may be down")]
+    NotXml {
+        url: String,
+    },
+    #[error("unable to read response from {url}")]
+    Read {
+        url: String,
+        #[source]
+        source: io::Error,
+    },
+    #[error("response from {url} is larger than the {limit} byte limit")]
+    TooLarge {
+        url: String,
+        limit: usize,
+    },
+    #[error("invalid request header {name:?}")]
+    InvalidHeader {
+        name: String,
+    },
+    #[error("unable to build HTTP client")]
+    Client(#[source] reqwest::Error),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TlsOptions {
+    /// Refuse plain HTTP, both for the requested URL and for any redirect it leads to.
+    pub require_https: bool,
+    /// An extra CA to trust alongside the system roots. reqwest offers no way to drop the built-in
+    /// roots, so this widens trust rather than restricting it.
+    pub ca_cert: Option<PathBuf>,
+}
+
+pub fn check_url(url: &str, tls: &TlsOptions) -> Result<(), ApiError> {
+    if tls.require_https && !url.starts_with("https://") {
+        return Err(ApiError::Insecure { url: url.to_string() });
+    }
+    Ok(())
+}
+
+pub fn is_secret_header(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    ["authorization", "key", "token", "secret", "cookie", "password"].iter().any(|s| name.contains(s))
+}
+
+pub fn is_html_page(body: &str) -> bool {
+    let start = body.trim_start_matches('\u{feff}').trim_start();
+    let start = start.get(..14).unwrap_or(start).to_ascii_lowercase();
+    (start.starts_with("<!doctype html") || start.starts_with("<html")) && !body.contains("<Members")
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MemberQuery {
+    pub surname: Option<String>,
+    pub name: Option<String>,
+    pub constituency: Option<String>,
+    pub postcode: Option<String>,
+}
+
+impl MemberQuery {
+    pub fn is_empty(&self) -> bool {
+        self.surname.is_none() && self.name.is_none() && self.constituency.is_none() && self.postcode.is_none()
+    }
+
+    fn filters(&self) -> String {
+        let mut filters = String::new();
+        for (filter, value) in &[("surname=", &self.surname), ("name*", &self.name), ("constituency=", &self.constituency),
+                                 ("postcode=", &self.postcode)] {
+            if let Some(value) = value {
+                filters.push('|');
+                filters.push_str(filter);
+                filters.push_str(value);
+            }
+        }
+        filters
+    }
+}
+
+pub fn is_valid_filter_value(value: &str) -> bool {
+    !value.trim().is_empty() && !value.contains(&['/', '|', '=', '*', '?', '#', '&'][..])
+}
+
+pub fn normalize_postcode(value: &str) -> Option<String> {
+    let postcode: String = value.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_ascii_uppercase();
+    if postcode.len() < 5 || postcode.len() > 7 || !postcode.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+
+    let (outward, inward) = postcode.split_at(postcode.len() - 3);
+    let inward: Vec<char> = inward.chars().collect();
+    if !outward.starts_with(|c: char| c.is_ascii_alphabetic()) || !outward.chars().any(|c| c.is_ascii_digit())
+        || !inward[0].is_ascii_digit() || !inward[1..].iter().all(char::is_ascii_alphabetic) {
+        return None;
+    }
+
+    Some(postcode)
+}
+
+pub fn get_api_url(base: &str, house: House, query: &MemberQuery, additional_data: Vec<AdditionalData>) -> String {
+    query_url(format!("{}house={}|isEligible=true{}/", base, house, query.filters()), additional_data)
+}
+
+pub fn get_api_url_as_of(base: &str, house: House, as_of: DateTime<Utc>, query: &MemberQuery, additional_data: Vec<AdditionalData>) -> String {
+    let membership = match house {
+        House::Lords => "lordsmemberon",
+        _ => "commonsmemberon"
+    };
+    query_url(format!("{}house={}|{}={}{}/", base, house, membership, as_of.format("%Y-%m-%d"), query.filters()), additional_data)
+}
+
+fn query_url(mut url: String, additional_data: Vec<AdditionalData>) -> String {
+    let data: Vec<&str> = additional_data.into_iter().map(Into::<&str>::into).collect();
+    url.extend(format!("{}/", data.join("|")).chars());
+
+    url
+}
+
+pub fn get_page_url(url: &str, skip: usize, page_size: Option<usize>) -> String {
+    match (skip, page_size) {
+        (0, None) => url.to_string(),
+        (skip, None) => format!("{}?skip={}", url, skip),
+        (skip, Some(take)) => format!("{}?skip={}&take={}", url, skip, take)
+    }
+}
+
+pub fn get_modified_since_url(url: &str, since: DateTime<Utc>) -> String {
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{}{}modifiedSince={}", url, separator, since.format("%Y-%m-%dT%H:%M:%S"))
+}
+
+/// Follows the API's paging, asking for the page after the members seen so far until a page
+/// reports no more results.
+pub fn fetch_pages<E>(url: &str, page_size: Option<usize>, mut fetch_page: impl FnMut(&str) -> Result<Members, E>) -> Result<Members, E> {
+    let mut members = Members::new();
+    let mut page = 1;
+    loop {
+        let page_members = fetch_page(&get_page_url(url, members.seen, page_size))?;
+
+        let more = page_members.more_results && page_members.seen > 0;
+        members.extend(page_members);
+        if !more {
+            return Ok(members);
+        }
+
+        page += 1;
+        log!("Getting page {} of results...", page);
+    }
+}
+
+pub struct ApiClient {
+    client: reqwest::Client,
+    min_delay: Duration,
+    last_request: Cell<Option<Instant>>,
+    tls: TlsOptions,
+    max_body_size: usize,
+}
+
+impl ApiClient {
+    pub fn new(min_delay: Duration, tls: TlsOptions, headers: &[(String, String)]) -> Result<Self, ApiError> {
+        let mut builder = reqwest::Client::builder();
+        if !headers.is_empty() {
+            let mut map = reqwest::header::HeaderMap::new();
+            for (name, value) in headers {
+                let invalid = || ApiError::InvalidHeader { name: name.clone() };
+                let header_name = reqwest::header::HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid())?;
+                let mut header_value = reqwest::header::HeaderValue::from_str(value).map_err(|_| invalid())?;
+                header_value.set_sensitive(is_secret_header(name));
+                map.append(header_name, header_value);
+            }
+            builder = builder.default_headers(map);
+        }
+        if let Some(path) = &tls.ca_cert {
+            let pem = fs::read(path).map_err(|e| ApiError::CertificateRead { path: path.clone(), source: e })?;
+            let cert = reqwest::Certificate::from_pem(&pem)
+                .map_err(|e| ApiError::Certificate { path: path.clone(), source: e })?;
+            builder = builder.add_root_certificate(cert);
+        }
+        if tls.require_https {
+            builder = builder.redirect(reqwest::RedirectPolicy::custom(|attempt| {
+                if attempt.url().scheme() == "https" {
+                    reqwest::RedirectPolicy::default().redirect(attempt)
+                } else {
+                    attempt.stop()
+                }
+            }));
+        }
+
+        Ok(Self {
+            client: builder.build().map_err(ApiError::Client)?,
+            min_delay,
+            last_request: Cell::new(None),
+            tls,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        })
+    }
+
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.max_body_size = limit;
+        self
+    }
+
+    fn wait_at(&self, now: Instant) -> Option<Duration> {
+        let elapsed = now.saturating_duration_since(self.last_request.get()?);
+        self.min_delay.checked_sub(elapsed).filter(|wait| *wait > Duration::from_secs(0))
+    }
+
+    pub fn get(&self, url: &str) -> Result<String, ApiError> {
+        self.request(url, None)
+    }
+
+    pub fn get_json(&self, url: &str) -> Result<String, ApiError> {
+        self.request(url, Some("application/json"))
+    }
+
+    fn request(&self, url: &str, accept: Option<&str>) -> Result<String, ApiError> {
+        check_url(url, &self.tls)?;
+
+        if let Some(wait) = self.wait_at(Instant::now()) {
+            log!("Waiting {}ms before next API request...", wait.as_millis());
+            thread::sleep(wait);
+        }
+
+        let mut request = self.client.get(url);
+        if let Some(accept) = accept {
+            request = request.header(reqwest::header::ACCEPT, accept);
+        }
+        let response = request.send();
+        self.last_request.set(Some(Instant::now()));
+
+        let mut response = response.and_then(|r| r.error_for_status()).map_err(|e| ApiError::Request {
+            url: url.to_string(),
+            source: e,
+        })?;
+
+        // The redirect policy only stops at a redirect when it leads off HTTPS.
+        if response.status().is_redirection() {
+            let location = response.headers().get(reqwest::header::LOCATION).and_then(|l| l.to_str().ok());
+            return Err(ApiError::Insecure { url: location.unwrap_or(url).to_string() });
+        }
+
+        let charset = response.headers().get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(encoding::charset_from_content_type)
+            .map(str::to_string);
+        let too_large = || ApiError::TooLarge { url: url.to_string(), limit: self.max_body_size };
+        if response.content_length().is_some_and(|l| l > self.max_body_size as u64) {
+            return Err(too_large());
+        }
+        let mut body = vec![];
+        (&mut response).take(self.max_body_size as u64 + 1).read_to_end(&mut body).map_err(|e| ApiError::Read {
+            url: url.to_string(),
+            source: e,
+        })?;
+        if body.len() > self.max_body_size {
+            return Err(too_large());
+        }
+
+        let body = encoding::decode(&body, charset.as_deref());
+        if is_html_page(&body) {
+            return Err(ApiError::NotXml { url: url.to_string() });
+        }
+
+        Ok(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::parse::parse_addresses_xml;
+    use crate::test_support::{document, MockResponse, MockServer};
+
+    #[test]
+    fn requests_wait_out_the_minimum_delay() {
+        let client = ApiClient::new(Duration::from_millis(500), TlsOptions::default(), &[]).unwrap();
+        let start = Instant::now();
+        assert_eq!(client.wait_at(start), None);
+
+        client.last_request.set(Some(start));
+        assert_eq!(client.wait_at(start), Some(Duration::from_millis(500)));
+        assert_eq!(client.wait_at(start + Duration::from_millis(200)), Some(Duration::from_millis(300)));
+        assert_eq!(client.wait_at(start + Duration::from_millis(500)), None);
+        assert_eq!(client.wait_at(start + Duration::from_secs(2)), None);
+    }
+
+    #[test]
+    fn plain_http_is_refused_when_https_is_required() {
+        let required = TlsOptions { require_https: true, ..TlsOptions::default() };
+        assert!(check_url("https://example.com/Members/", &required).is_ok());
+        assert!(check_url("http://example.com/Members/", &TlsOptions::default()).is_ok());
+
+        let client = ApiClient::new(Duration::from_millis(0), required, &[]).unwrap();
+        match client.get("http://example.com/Members/") {
+            Err(ApiError::Insecure { url }) => assert_eq!(url, "http://example.com/Members/"),
+            other => panic!("expected an insecure URL error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn page_urls_carry_skip_and_take() {
+        let url = "https://example.com/Members/Query/house=Commons/";
+        assert_eq!(get_page_url(url, 0, None), url);
+        assert_eq!(get_page_url(url, 0, Some(100)), format!("{}?skip=0&take=100", url));
+        assert_eq!(get_page_url(url, 250, None), format!("{}?skip=250", url));
+        assert_eq!(get_page_url(url, 250, Some(50)), format!("{}?skip=250&take=50", url));
+    }
+
+    #[test]
+    fn pages_are_followed_until_no_more_results() {
+        let page = |more: bool, ids: &[u32]| {
+            let members: String = ids.iter().map(|id| format!("<Member Member_Id=\"{}\"><DisplayAs>Member {}</DisplayAs></Member>", id, id)).collect();
+            MockResponse::ok(format!("<Members MoreResults=\"{}\">{}</Members>", more, members))
+        };
+        let server = MockServer::new(vec![page(true, &[172, 4212]), page(false, &[1524])]);
+        let client = ApiClient::new(Duration::from_millis(0), TlsOptions::default(), &[]).unwrap();
+
+        let url = format!("{}/Members/Query/house=Commons/", server.url());
+        let members = fetch_pages(&url, Some(2), |page_url| {
+            let body = client.get(page_url).unwrap();
+            parse_addresses_xml(&body, &Config::default())
+        }).unwrap();
+
+        let ids: Vec<u32> = members.members.iter().map(|m| m.id).collect();
+        assert_eq!(ids, [172, 4212, 1524]);
+        assert!(!members.more_results);
+        assert_eq!(server.paths(), ["/Members/Query/house=Commons/?skip=0&take=2", "/Members/Query/house=Commons/?skip=2&take=2"]);
+    }
+
+    #[test]
+    fn pages_filtered_down_to_nothing_are_still_followed() {
+        let page = |more: bool, ids: &[u32]| {
+            let members: String = ids.iter().map(|id| format!("<Member Member_Id=\"{}\"><DisplayAs>Member {}</DisplayAs></Member>", id, id)).collect();
+            MockResponse::ok(format!("<Members MoreResults=\"{}\">{}</Members>", more, members))
+        };
+        let server = MockServer::new(vec![page(true, &[172, 4212]), page(false, &[1524])]);
+        let client = ApiClient::new(Duration::from_millis(0), TlsOptions::default(), &[]).unwrap();
+        let config = Config { member_ids: Some("1000-2000".parse().unwrap()), ..Config::default() };
+
+        let url = format!("{}/Members/Query/house=Commons/", server.url());
+        let members = fetch_pages(&url, Some(2), |page_url| {
+            let body = client.get(page_url).unwrap();
+            parse_addresses_xml(&body, &config)
+        }).unwrap();
+
+        assert_eq!(members.members.iter().map(|m| m.id).collect::<Vec<_>>(), [1524]);
+        assert_eq!(members.seen, 3);
+        assert_eq!(server.paths(), ["/Members/Query/house=Commons/?skip=0&take=2", "/Members/Query/house=Commons/?skip=2&take=2"]);
+    }
+
+    #[test]
+    fn html_maintenance_pages_are_reported() {
+        let page = "<!DOCTYPE html>\n<html><head><title>Service unavailable</title></head><body>Down for maintenance</body></html>";
+        let server = MockServer::new(vec![MockResponse::ok(page), MockResponse::ok(document(&[(172, "")]))]);
+        let client = ApiClient::new(Duration::from_millis(0), TlsOptions::default(), &[]).unwrap();
+
+        let url = format!("{}/Members/Query/", server.url());
+        match client.get(&url) {
+            Err(ApiError::NotXml { url: failed }) => assert_eq!(failed, url),
+            other => panic!("expected an HTML page error, got {:?}", other),
+        }
+        assert!(client.get(&url).unwrap().contains("<Members"));
+
+        assert!(is_html_page("\u{feff}  <HTML lang=\"en\">"));
+        assert!(!is_html_page("<html><Members></Members></html>"));
+        assert!(!is_html_page("<?xml version=\"1.0\"?><Members/>"));
+    }
+
+    #[test]
+    fn query_filters_are_added_to_the_url() {
+        let url = |query: MemberQuery| get_api_url(MEMBER_API_BASE, House::Commons, &query, vec![AdditionalData::Addresses]);
+        let base = format!("{}house=Commons|isEligible=true", MEMBER_API_BASE);
+
+        assert_eq!(url(MemberQuery::default()), format!("{}/Addresses/", base));
+        assert_eq!(url(MemberQuery { surname: Some("Abbott".to_string()), ..MemberQuery::default() }),
+                   format!("{}|surname=Abbott/Addresses/", base));
+        assert_eq!(url(MemberQuery { name: Some("Diane".to_string()), ..MemberQuery::default() }),
+                   format!("{}|name*Diane/Addresses/", base));
+        assert_eq!(url(MemberQuery { constituency: Some("Broxtowe".to_string()), ..MemberQuery::default() }),
+                   format!("{}|constituency=Broxtowe/Addresses/", base));
+        assert_eq!(url(MemberQuery { postcode: Some("SW1A0AA".to_string()), ..MemberQuery::default() }),
+                   format!("{}|postcode=SW1A0AA/Addresses/", base));
+        assert_eq!(url(MemberQuery { surname: Some("Abbott".to_string()), postcode: Some("E84DG".to_string()), ..MemberQuery::default() }),
+                   format!("{}|surname=Abbott|postcode=E84DG/Addresses/", base));
+    }
+
+    #[test]
+    fn past_memberships_are_queried_by_date() {
+        let as_of = crate::parse::parse_date("2010-06-01").unwrap();
+        let surname = MemberQuery { surname: Some("Bercow".to_string()), ..MemberQuery::default() };
+
+        assert_eq!(get_api_url_as_of(MEMBER_API_BASE, House::Commons, as_of, &surname, vec![AdditionalData::Addresses, AdditionalData::Parties]),
+                   format!("{}house=Commons|commonsmemberon=2010-06-01|surname=Bercow/Addresses|Parties/", MEMBER_API_BASE));
+        assert_eq!(get_api_url_as_of(MEMBER_API_BASE, House::Lords, as_of, &MemberQuery::default(), vec![AdditionalData::Addresses]),
+                   format!("{}house=Lords|lordsmemberon=2010-06-01/Addresses/", MEMBER_API_BASE));
+    }
+
+    #[test]
+    fn query_values_are_validated() {
+        assert_eq!(normalize_postcode("sw1a 0aa"), Some("SW1A0AA".to_string()));
+        assert_eq!(normalize_postcode(" E8 4DG "), Some("E84DG".to_string()));
+        assert_eq!(normalize_postcode("SW1A"), None);
+        assert_eq!(normalize_postcode("1234 5AB"), None);
+        assert_eq!(normalize_postcode("SW1A 0A1"), None);
+        assert!(is_valid_filter_value("Hackney North"));
+        assert!(!is_valid_filter_value("  "));
+        assert!(!is_valid_filter_value("Abbott|isEligible=false"));
+    }
+
+    #[test]
+    fn oversized_bodies_are_refused() {
+        let body = document(&[(172, ""), (4212, "")]);
+        let limit = body.len() - 1;
+        let server = MockServer::new(vec![
+            MockResponse::ok(body.clone()),
+            MockResponse::ok(body.clone()).without_content_length(),
+            MockResponse::ok(body.clone()).without_content_length(),
+        ]);
+        let url = format!("{}/Members/Query/", server.url());
+        let client = ApiClient::new(Duration::from_millis(0), TlsOptions::default(), &[]).unwrap().max_body_size(limit);
+
+        // Refused up front from the Content-Length...
+        match client.get(&url) {
+            Err(ApiError::TooLarge { url: u, limit: l }) => assert_eq!((u.as_str(), l), (url.as_str(), limit)),
+            other => panic!("expected a too large error, got {:?}", other),
+        }
+        // ...and while streaming when there isn't one.
+        match client.get(&url) {
+            Err(ApiError::TooLarge { limit: l, .. }) => assert_eq!(l, limit),
+            other => panic!("expected a too large error, got {:?}", other),
+        }
+
+        let client = client.max_body_size(body.len());
+        assert!(client.get(&url).unwrap().ends_with("<DisplayAs>Member 4212</DisplayAs></Member></Members>"));
+    }
+}
@@ -0,0 +1,195 @@
+use crate::members::House;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use flate2::read::GzDecoder;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+pub fn archive_response(dir: &Path, house: &House, body: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+
+    let stem = format!("{}-{}", house, Utc::now().format("%Y%m%dT%H%M%SZ"));
+    let mut n = 0;
+    loop {
+        let path = match n {
+            0 => dir.join(format!("{}.xml", stem)),
+            n => dir.join(format!("{}-{}.xml", stem, n)),
+        };
+
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut f) => {
+                f.write_all(body.as_bytes())?;
+                return Ok(path);
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(e)
+        }
+    }
+}
+
+pub fn read_body(path: &Path) -> io::Result<Vec<u8>> {
+    let bytes = fs::read(path)?;
+    if bytes.starts_with(&[0x1f, 0x8b]) || path.extension() == Some("gz".as_ref()) {
+        let mut body = vec![];
+        GzDecoder::new(&bytes[..]).read_to_end(&mut body)?;
+        return Ok(body);
+    }
+
+    Ok(bytes)
+}
+
+pub struct ArchivedResponse {
+    pub house: House,
+    pub timestamp: String,
+    pub path: PathBuf,
+}
+
+impl ArchivedResponse {
+    pub fn fetched_at(&self) -> Option<DateTime<Utc>> {
+        let timestamp = self.timestamp.split('-').next()?;
+        NaiveDateTime::parse_from_str(timestamp, "%Y%m%dT%H%M%SZ").ok().map(|t| Utc.from_utc_datetime(&t))
+    }
+}
+
+pub fn archived_responses(dir: &Path) -> io::Result<Vec<ArchivedResponse>> {
+    let mut responses = vec![];
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let stem = match path.file_name().and_then(|s| s.to_str()) {
+            Some(s) if s.ends_with(".xml") => s.trim_end_matches(".xml").to_string(),
+            Some(s) if s.ends_with(".xml.gz") => s.trim_end_matches(".xml.gz").to_string(),
+            _ => continue
+        };
+        let mut parts = stem.splitn(2, '-');
+        let house = parts.next().unwrap_or("").parse().unwrap_or(House::Unknown);
+        let timestamp = parts.next().unwrap_or("").to_string();
+
+        responses.push(ArchivedResponse {
+            house,
+            timestamp,
+            path,
+        });
+    }
+
+    responses.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+
+    Ok(responses)
+}
+
+fn wildcard_match(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => wildcard_match(&pattern[1..], name) || (!name.is_empty() && wildcard_match(pattern, &name[1..])),
+        (Some('?'), Some(_)) => wildcard_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => wildcard_match(&pattern[1..], &name[1..]),
+        _ => false
+    }
+}
+
+pub fn matching_files(pattern: &str) -> io::Result<Vec<PathBuf>> {
+    let pattern = Path::new(pattern);
+    let dir = match pattern.parent() {
+        Some(d) if !d.as_os_str().is_empty() => d,
+        _ => Path::new(".")
+    };
+    let name_pattern = pattern.file_name().and_then(|n| n.to_str()).unwrap_or("").chars().collect::<Vec<_>>();
+
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = match path.file_name().and_then(|n| n.to_str()) {
+            Some(n) => n.chars().collect::<Vec<_>>(),
+            None => continue
+        };
+        if path.is_file() && wildcard_match(&name_pattern, &name) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    Ok(paths)
+}
+
+const SNAPSHOT_DATE_FORMATS: &[(&str, usize)] = &[("%Y-%m-%d", 10), ("%Y%m%d", 8)];
+
+pub fn snapshot_date(path: &Path) -> Option<DateTime<Utc>> {
+    let name = path.file_name()?.to_str()?;
+    for (i, _) in name.char_indices() {
+        let rest = &name[i..];
+        // chrono reads a sign before %Y, so only start at a digit or "-2019-02-20" becomes a negative year.
+        if !rest.starts_with(|c: char| c.is_ascii_digit()) {
+            continue;
+        }
+        if let Some(t) = rest.get(..16).and_then(|s| NaiveDateTime::parse_from_str(s, "%Y%m%dT%H%M%SZ").ok()) {
+            return Some(Utc.from_utc_datetime(&t));
+        }
+        for (format, len) in SNAPSHOT_DATE_FORMATS {
+            if let Some(d) = rest.get(..*len).and_then(|s| NaiveDate::parse_from_str(s, format).ok()) {
+                return d.and_hms_opt(0, 0, 0).map(|t| Utc.from_utc_datetime(&t));
+            }
+        }
+    }
+
+    fs::metadata(path).and_then(|m| m.modified()).ok().map(DateTime::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::parse::parse_addresses_xml;
+    use crate::test_support::{commons, document, TempDir};
+
+    #[test]
+    fn each_fetch_writes_one_new_file() {
+        let dir = TempDir::new("archive");
+        let archive = dir.path().join("archive");
+
+        let first = archive_response(&archive, &House::Commons, "<Members/>").unwrap();
+        assert_eq!(fs::read_dir(&archive).unwrap().count(), 1);
+        assert_eq!(fs::read_to_string(&first).unwrap(), "<Members/>");
+
+        let second = archive_response(&archive, &House::Commons, "<Members></Members>").unwrap();
+        assert_ne!(first, second);
+        assert_eq!(fs::read_dir(&archive).unwrap().count(), 2);
+        assert_eq!(fs::read_to_string(&first).unwrap(), "<Members/>");
+    }
+
+    #[test]
+    fn replays_archived_fixtures_in_order() {
+        let dir = TempDir::new("replay");
+        fs::write(dir.path().join("Commons-20200102T000000Z.xml"), document(&[(172, ""), (4212, "")])).unwrap();
+        fs::write(dir.path().join("Lords-20200101T000000Z.xml"), document(&[(3898, "")])).unwrap();
+        fs::write(dir.path().join("notes.txt"), "not an archive").unwrap();
+
+        let replayed: Vec<(House, Vec<u32>)> = archived_responses(dir.path()).unwrap().into_iter()
+            .map(|r| {
+                let members = parse_addresses_xml(&fs::read_to_string(&r.path).unwrap(), &Config::default()).unwrap();
+                (r.house, members.members.iter().map(|m| m.id).collect())
+            })
+            .collect();
+        assert_eq!(replayed, [(House::Lords, vec![3898]), (House::Commons, vec![172, 4212])]);
+    }
+
+    #[test]
+    fn gzipped_inputs_are_decompressed() {
+        let dir = TempDir::new("gzip");
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(commons().as_bytes()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+        fs::write(dir.path().join("Commons-20200102T000000Z.xml.gz"), &gzipped).unwrap();
+        fs::write(dir.path().join("commons.dump"), &gzipped).unwrap();
+
+        let responses = archived_responses(dir.path()).unwrap();
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].house, House::Commons);
+
+        for path in &[responses[0].path.clone(), dir.path().join("commons.dump")] {
+            let body = String::from_utf8(read_body(path).unwrap()).unwrap();
+            let members = parse_addresses_xml(&body, &Config::default()).unwrap();
+            assert_eq!(members.members.len(), 7);
+        }
+        assert_eq!(read_body(&dir.path().join("commons.dump")).unwrap(), commons().as_bytes());
+    }
+}
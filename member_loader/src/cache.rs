@@ -0,0 +1,138 @@
+use crate::config::Config;
+use crate::manifest::PARSER_VERSION;
+use crate::members::{Member, Members, SkippedMember};
+use fnv::FnvHasher;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CacheError {
+    #[error("unable to write {}", .path.display())]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("unable to encode cached members")]
+    Encode(#[source] bincode::Error),
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedMembers {
+    parser_version: String,
+    members: Vec<Member>,
+    unknown_elements: BTreeSet<String>,
+    empty_social_addresses: usize,
+    skipped: Vec<SkippedMember>,
+    more_results: bool,
+    seen: usize,
+}
+
+pub struct MemberCache<'a> {
+    dir: &'a Path,
+    config: &'a Config,
+}
+
+impl<'a> MemberCache<'a> {
+    pub fn new(dir: &'a Path, config: &'a Config) -> Self {
+        Self { dir, config }
+    }
+
+    fn path(&self, source: &str, body: &str) -> PathBuf {
+        let mut hasher = FnvHasher::default();
+        PARSER_VERSION.hash(&mut hasher);
+        source.hash(&mut hasher);
+        serde_json::to_value(self.config).map(|v| v.to_string()).unwrap_or_default().hash(&mut hasher);
+        body.hash(&mut hasher);
+        self.dir.join(format!("{:016x}.bin", hasher.finish()))
+    }
+
+    pub fn get(&self, source: &str, body: &str) -> Option<Members> {
+        let data = fs::read(self.path(source, body)).ok()?;
+        let cached: CachedMembers = bincode::deserialize(&data).ok()?;
+        if cached.parser_version != PARSER_VERSION {
+            return None;
+        }
+
+        let mut members = Members::new();
+        members.members = cached.members;
+        members.unknown_elements = cached.unknown_elements;
+        members.empty_social_addresses = cached.empty_social_addresses;
+        members.skipped = cached.skipped;
+        members.more_results = cached.more_results;
+        members.seen = cached.seen;
+        Some(members)
+    }
+
+    pub fn put(&self, source: &str, body: &str, members: &Members) -> Result<(), CacheError> {
+        let cached = CachedMembers {
+            parser_version: PARSER_VERSION.to_string(),
+            members: members.members.clone(),
+            unknown_elements: members.unknown_elements.clone(),
+            empty_social_addresses: members.empty_social_addresses,
+            skipped: members.skipped.clone(),
+            more_results: members.more_results,
+            seen: members.seen,
+        };
+        let data = bincode::serialize(&cached).map_err(CacheError::Encode)?;
+
+        let path = self.path(source, body);
+        fs::create_dir_all(self.dir).and_then(|_| fs::write(&path, data))
+            .map_err(|e| CacheError::Write { path, source: e })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse_addresses_xml;
+    use crate::test_support::{commons, TempDir};
+
+    #[test]
+    fn members_round_trip_through_the_cache() {
+        let dir = TempDir::new("cache");
+        let config = Config::default();
+        let cache = MemberCache::new(dir.path(), &config);
+        let members = parse_addresses_xml(commons(), &config).unwrap();
+
+        assert!(cache.get("commons.xml", commons()).is_none());
+        cache.put("commons.xml", commons(), &members).unwrap();
+
+        let cached = cache.get("commons.xml", commons()).unwrap();
+        assert_eq!(serde_json::to_value(&cached.members).unwrap(), serde_json::to_value(&members.members).unwrap());
+        assert_eq!(cached.unknown_elements, members.unknown_elements);
+        assert_eq!(cached.empty_social_addresses, members.empty_social_addresses);
+        assert_eq!(cached.more_results, members.more_results);
+        assert_eq!(cached.seen, members.seen);
+
+        assert!(cache.get("lords.xml", commons()).is_none());
+        assert!(cache.get("commons.xml", "<Members/>").is_none());
+        let mut other = Config::default();
+        other.independent_parties.push("Independent Labour".to_string());
+        assert!(MemberCache::new(dir.path(), &other).get("commons.xml", commons()).is_none());
+    }
+
+    #[test]
+    fn entries_from_another_parser_version_are_ignored() {
+        let dir = TempDir::new("cache");
+        let config = Config::default();
+        let cache = MemberCache::new(dir.path(), &config);
+
+        let stale = CachedMembers {
+            parser_version: "0.0.0".to_string(),
+            members: vec![],
+            unknown_elements: BTreeSet::new(),
+            empty_social_addresses: 0,
+            skipped: vec![],
+            more_results: false,
+            seen: 0,
+        };
+        fs::write(cache.path("commons.xml", commons()), bincode::serialize(&stale).unwrap()).unwrap();
+        assert!(cache.get("commons.xml", commons()).is_none());
+    }
+}
@@ -0,0 +1,123 @@
+use member_loader::config::Config;
+use member_loader::members::Members;
+use member_loader::parse;
+
+struct Expected {
+    id: u32,
+    name: &'static str,
+    party: &'static str,
+    constituency: &'static str,
+    twitter: Option<&'static str>,
+    status: Option<&'static str>,
+}
+
+struct Fixture {
+    name: &'static str,
+    data: &'static str,
+    members: usize,
+    expected: &'static [Expected],
+}
+
+const FIXTURES: &[Fixture] = &[
+    Fixture {
+        name: "commons.xml",
+        data: include_str!("../fixtures/commons.xml"),
+        members: 7,
+        expected: &[
+            Expected { id: 172, name: "Ms Diane Abbott", party: "Labour", constituency: "Hackney North and Stoke Newington", twitter: Some("HackneyAbbott"), status: Some("sitting") },
+            Expected { id: 4212, name: "Anna Soubry", party: "The Independent Group for Change", constituency: "Broxtowe", twitter: Some("Anna_Soubry"), status: Some("sitting") },
+            Expected { id: 1524, name: "Mr John Bercow", party: "Speaker", constituency: "Buckingham", twitter: None, status: Some("sitting") },
+            Expected { id: 4520, name: "Gareth Thomas", party: "Labour", constituency: "Harrow West", twitter: Some("GarethThomasHW"), status: Some("sitting") },
+        ],
+    },
+];
+
+fn check_fixture(fixture: &Fixture, members: &Members) -> Vec<String> {
+    let mut failures = vec![];
+    if members.members.len() != fixture.members {
+        failures.push(format!("expected {} members, got {}", fixture.members, members.members.len()));
+    }
+
+    for expected in fixture.expected {
+        let member = match members.members.iter().find(|m| m.id == expected.id) {
+            Some(m) => m,
+            None => {
+                failures.push(format!("member {} is missing", expected.id));
+                continue;
+            }
+        };
+
+        if member.name != expected.name {
+            failures.push(format!("member {} has name {:?}, expected {:?}", expected.id, member.name, expected.name));
+        }
+        if member.current_party() != expected.party {
+            failures.push(format!("member {} has party {:?}, expected {:?}", expected.id, member.current_party(), expected.party));
+        }
+        if member.constituency != expected.constituency {
+            failures.push(format!("member {} has constituency {:?}, expected {:?}", expected.id, member.constituency,
+                                  expected.constituency));
+        }
+        if member.twitter_handle().as_deref() != expected.twitter {
+            failures.push(format!("member {} has Twitter handle {:?}, expected {:?}", expected.id, member.twitter_handle(),
+                                  expected.twitter));
+        }
+        if member.status.as_deref() != expected.status {
+            failures.push(format!("member {} has status {:?}, expected {:?}", expected.id, member.status, expected.status));
+        }
+    }
+
+    failures
+}
+
+/// Parses every bundled fixture and prints whether it matched, returning how many failed. The
+/// expectations assume the default config, so a local one (aliases, date formats) isn't applied.
+pub fn run() -> usize {
+    let config = Config::default();
+    let mut failed = 0;
+    for fixture in FIXTURES {
+        let failures = match parse::parse_addresses(fixture.data, None, &config) {
+            Ok(members) => check_fixture(fixture, &members),
+            Err(e) => vec![format!("unable to parse: {}", e)]
+        };
+
+        if failures.is_empty() {
+            println!("PASS {}", fixture.name);
+        } else {
+            failed += 1;
+            println!("FAIL {}", fixture.name);
+            for f in &failures {
+                println!("  {}", f);
+            }
+        }
+    }
+
+    println!("{} of {} fixtures passed", FIXTURES.len() - failed, FIXTURES.len());
+    failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_fixtures_pass() {
+        for fixture in FIXTURES {
+            let members = parse::parse_addresses(fixture.data, None, &Config::default()).unwrap();
+            assert_eq!(check_fixture(fixture, &members), Vec::<String>::new(), "{}", fixture.name);
+        }
+    }
+
+    #[test]
+    fn mismatches_are_reported() {
+        let fixture = &FIXTURES[0];
+        let mut members = parse::parse_addresses(fixture.data, None, &Config::default()).unwrap();
+        members.members.retain(|m| m.id != 1524);
+        members.members[0].name = "Diane Abbott".to_string();
+
+        assert_eq!(check_fixture(fixture, &members), [
+            "expected 7 members, got 6",
+            "member 172 has name \"Diane Abbott\", expected \"Ms Diane Abbott\"",
+            "member 1524 is missing",
+        ]);
+    }
+}
@@ -0,0 +1,94 @@
+use fnv::FnvHasher;
+use std::fs;
+use std::hash::Hasher;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub struct Checkpoint {
+    path: PathBuf,
+}
+
+impl Checkpoint {
+    pub fn for_run(name: &str, ids: impl Iterator<Item=u32>) -> Self {
+        Self::in_dir(Path::new("."), name, ids)
+    }
+
+    pub fn in_dir(dir: &Path, name: &str, ids: impl Iterator<Item=u32>) -> Self {
+        // FNV rather than DefaultHasher so the key survives a toolchain upgrade between runs
+        let mut hasher = FnvHasher::default();
+        for id in ids {
+            hasher.write(&id.to_le_bytes());
+        }
+
+        Self {
+            path: dir.join(format!(".member_loader-{}-{:x}.checkpoint", name, hasher.finish())),
+        }
+    }
+
+    pub fn load(&self) -> usize {
+        match fs::read_to_string(&self.path) {
+            Ok(s) => s.trim().parse().unwrap_or(0),
+            Err(_) => 0
+        }
+    }
+
+    /// Splits `items` into how many an earlier run already committed and the ones left to commit.
+    pub fn remaining<'a, T>(&self, items: &'a [T], resume: bool) -> (usize, &'a [T]) {
+        let committed = if resume { self.load().min(items.len()) } else { 0 };
+        (committed, &items[committed..])
+    }
+
+    pub fn save(&self, committed: usize) -> io::Result<()> {
+        fs::write(&self.path, committed.to_string())
+    }
+
+    pub fn clear(&self) -> io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            r => r
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    const IDS: [u32; 5] = [172, 4212, 1524, 4005, 4520];
+
+    #[test]
+    fn run_key_is_stable() {
+        let dir = TempDir::new("checkpoint-key");
+        let checkpoint = Checkpoint::in_dir(dir.path(), "Commons", IDS.iter().copied());
+        assert_eq!(checkpoint.path, dir.path().join(".member_loader-Commons-9002c0d9fd5f0429.checkpoint"));
+
+        assert_ne!(Checkpoint::in_dir(dir.path(), "Commons", IDS.iter().rev().copied()).path, checkpoint.path);
+        assert_ne!(Checkpoint::in_dir(dir.path(), "Lords", IDS.iter().copied()).path, checkpoint.path);
+    }
+
+    #[test]
+    fn resumes_after_a_failed_batch() {
+        let dir = TempDir::new("checkpoint-resume");
+        let checkpoint = Checkpoint::in_dir(dir.path(), "Commons", IDS.iter().copied());
+
+        let (mut committed, pending) = checkpoint.remaining(&IDS, true);
+        assert_eq!((committed, pending), (0, &IDS[..]));
+        for batch in pending.chunks(2) {
+            if batch.contains(&4520) {
+                break; // the transaction for the last batch fails
+            }
+            committed += batch.len();
+            checkpoint.save(committed).unwrap();
+        }
+
+        let checkpoint = Checkpoint::in_dir(dir.path(), "Commons", IDS.iter().copied());
+        assert_eq!(checkpoint.remaining(&IDS, true), (4, &[4520][..]));
+        assert_eq!(checkpoint.remaining(&IDS, false), (0, &IDS[..]));
+        assert_eq!(checkpoint.remaining(&IDS[..3], true), (3, &[][..]));
+
+        checkpoint.clear().unwrap();
+        assert_eq!(checkpoint.remaining(&IDS, true), (0, &IDS[..]));
+        checkpoint.clear().unwrap();
+    }
+}
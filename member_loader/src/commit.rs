@@ -0,0 +1,1315 @@
+use crate::checkpoint::Checkpoint;
+use crate::json::JsonStyle;
+use crate::manifest::RunManifest;
+use crate::members::{self, Member, Members};
+use crate::verify::{LegacyNode, StoredMember};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+const COMMIT_BATCH_SIZE: usize = 100;
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+const SCHEMA: &str = "
+member_id: int @index(int) .
+xid: string @index(exact) @upsert .
+dods_id: string @index(exact) .
+pims_id: string @index(exact) .
+clerks_id: string @index(exact) .
+former_names: [string] @index(term) .
+normalized_constituency: string @index(exact) .
+constituencies: [string] @index(exact) .
+status: string @index(exact) .
+had_whip_removed: bool @index(bool) .
+whip_removed: datetime @index(day) .
+oath_date: datetime @index(day) .
+current_party: string @index(exact) .
+party_name: string @index(exact) @upsert .
+parties: [uid] @reverse .
+";
+
+const PARTY_QUERY: &str = "query party($name: string) {
+    party(func: eq(party_name, $name)) {
+        uid
+    }
+}";
+
+const TWITTER_QUERY: &str = "{
+    members(func: has(twitter)) {
+        uid
+        twitter
+    }
+}";
+
+const FINGERPRINT_QUERY: &str = "{
+    members(func: has(fingerprint)) {
+        member_id
+        fingerprint
+    }
+}";
+
+const ORPHANED_PARTIES_QUERY: &str = "{
+    parties(func: has(party_name)) @filter(NOT has(~parties)) {
+        uid
+        party_name
+    }
+}";
+
+const LEGACY_MEMBERS_QUERY: &str = "{
+    legacy(func: has(name)) @filter(NOT has(xid)) {
+        uid
+        member_id
+        name
+    }
+    linked(func: has(xid)) {
+        member_id
+    }
+}";
+
+const STORED_MEMBERS_QUERY: &str = "{
+    members(func: has(member_id)) {
+        member_id
+        name
+        twitter
+        fingerprint
+    }
+}";
+
+#[derive(Debug, Error)]
+pub enum CommitError {
+    #[error("unable to update schema")]
+    Schema(#[source] failure::Compat<failure::Error>),
+    #[error("unable to serialise member {member_id}")]
+    Serialize {
+        member_id: u32,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("query for party {name:?} failed")]
+    PartyQuery {
+        name: String,
+        #[source]
+        source: failure::Compat<failure::Error>,
+    },
+    #[error("invalid response to query for party {name:?}")]
+    PartyResponse {
+        name: String,
+        #[source]
+        source: serde_json::Error,
+    },
+    #[error("mutation for party {name:?} failed")]
+    PartyMutation {
+        name: String,
+        #[source]
+        source: failure::Compat<failure::Error>,
+    },
+    #[error("no uid assigned to party {name:?}")]
+    MissingPartyUid {
+        name: String,
+    },
+    #[error("mutation for member {member_id} failed")]
+    Mutation {
+        member_id: u32,
+        #[source]
+        source: failure::Compat<failure::Error>,
+    },
+    #[error("query for existing members failed")]
+    ExistingQuery(#[source] failure::Compat<failure::Error>),
+    #[error("invalid response to query for existing members")]
+    ExistingResponse(#[source] serde_json::Error),
+    #[error("query for Twitter handles failed")]
+    TwitterQuery(#[source] failure::Compat<failure::Error>),
+    #[error("invalid response to query for Twitter handles")]
+    TwitterResponse(#[source] serde_json::Error),
+    #[error("mutation for Twitter handle of {uid} failed")]
+    TwitterMutation {
+        uid: String,
+        #[source]
+        source: failure::Compat<failure::Error>,
+    },
+    #[error("query for member fingerprints failed")]
+    FingerprintQuery(#[source] failure::Compat<failure::Error>),
+    #[error("invalid response to query for member fingerprints")]
+    FingerprintResponse(#[source] serde_json::Error),
+    #[error("query for stored members failed")]
+    MembersQuery(#[source] failure::Compat<failure::Error>),
+    #[error("invalid response to query for stored members")]
+    MembersResponse(#[source] serde_json::Error),
+    #[error("query for members without an xid failed")]
+    LegacyQuery(#[source] failure::Compat<failure::Error>),
+    #[error("invalid response to query for members without an xid")]
+    LegacyResponse(#[source] serde_json::Error),
+    #[error("mutation linking {uid} to member {member_id} failed")]
+    RelinkMutation {
+        uid: String,
+        member_id: u32,
+        #[source]
+        source: failure::Compat<failure::Error>,
+    },
+    #[error("query for orphaned parties failed")]
+    OrphanQuery(#[source] failure::Compat<failure::Error>),
+    #[error("invalid response to query for orphaned parties")]
+    OrphanResponse(#[source] serde_json::Error),
+    #[error("deletion of orphaned parties failed")]
+    OrphanMutation(#[source] failure::Compat<failure::Error>),
+    #[error("unable to store run manifest")]
+    Manifest(#[source] failure::Compat<failure::Error>),
+    #[error("failed to commit transaction")]
+    Commit(#[source] failure::Compat<failure::Error>),
+    #[error("failed to update checkpoint")]
+    Checkpoint(#[from] io::Error),
+}
+
+#[derive(Debug, Clone)]
+pub struct Provenance {
+    pub fetched_at: DateTime<Utc>,
+    pub source_url: String,
+}
+
+impl CommitError {
+    pub fn member_id(&self) -> Option<u32> {
+        match self {
+            CommitError::Serialize { member_id, .. } | CommitError::Mutation { member_id, .. } => Some(*member_id),
+            _ => None
+        }
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        let source = match self {
+            CommitError::Mutation { source, .. } | CommitError::PartyMutation { source, .. } => source,
+            CommitError::Commit(source) => source,
+            _ => return false
+        };
+        source.to_string().to_lowercase().contains("aborted")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictStrategy {
+    #[default]
+    Overwrite,
+    KeepExisting,
+    MergeFields,
+}
+
+impl FromStr for ConflictStrategy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "overwrite" => Ok(ConflictStrategy::Overwrite),
+            "keep-existing" => Ok(ConflictStrategy::KeepExisting),
+            "merge-fields" => Ok(ConflictStrategy::MergeFields),
+            _ => Err(())
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CommitOptions {
+    pub resume: bool,
+    pub only_changed: bool,
+    pub batch_delay: Duration,
+    pub max_retries: u32,
+    pub conflict: ConflictStrategy,
+    pub concurrency: usize,
+    pub fingerprint_fields: Vec<String>,
+}
+
+impl Default for CommitOptions {
+    fn default() -> Self {
+        Self {
+            resume: false,
+            only_changed: false,
+            batch_delay: Duration::from_secs(0),
+            max_retries: 3,
+            conflict: ConflictStrategy::default(),
+            concurrency: 2,
+            fingerprint_fields: members::default_fingerprint_fields(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedMember {
+    pub member_id: u32,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+pub struct CommitSummary {
+    pub committed: usize,
+    pub unchanged: usize,
+    pub kept: usize,
+    pub retries: usize,
+    pub failed: Vec<FailedMember>,
+    pub elapsed: Duration,
+}
+
+impl CommitSummary {
+    pub fn rate(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+
+        self.committed as f64 / secs
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct PartyObject {
+    uid: String,
+    party_name: String,
+}
+
+#[derive(Serialize, Debug)]
+struct PartyEdge {
+    uid: String,
+    #[serde(rename = "parties|start_date")]
+    start_date: DateTime<Utc>,
+    #[serde(rename = "parties|end_date", skip_serializing_if = "Option::is_none")]
+    end_date: Option<DateTime<Utc>>,
+}
+
+fn party_uid(name: &str, party_uids: &mut HashMap<String, String>,
+             upsert: impl FnOnce(&str) -> Result<String, CommitError>) -> Result<String, CommitError> {
+    if let Some(uid) = party_uids.get(name) {
+        return Ok(uid.clone());
+    }
+
+    let uid = upsert(name)?;
+    party_uids.insert(name.to_string(), uid.clone());
+    Ok(uid)
+}
+
+fn upsert_party(txn: &mut dgraph::Txn, name: &str) -> Result<String, CommitError> {
+    #[derive(Deserialize)]
+    struct PartyResponse {
+        party: Vec<PartyUid>,
+    }
+
+    #[derive(Deserialize)]
+    struct PartyUid {
+        uid: String,
+    }
+
+    let mut vars = HashMap::new();
+    vars.insert("$name".to_string(), name.to_string());
+    let resp = txn.query_with_vars(PARTY_QUERY, vars)
+        .map_err(|e| CommitError::PartyQuery { name: name.to_string(), source: e.compat() })?;
+    let existing: PartyResponse = serde_json::from_slice(&resp.json)
+        .map_err(|e| CommitError::PartyResponse { name: name.to_string(), source: e })?;
+
+    match existing.party.into_iter().next() {
+        Some(p) => Ok(p.uid),
+        None => {
+            let p = PartyObject {
+                uid: "_:party".to_string(),
+                party_name: name.to_string(),
+            };
+            let mu = dgraph::Mutation {
+                set_json: serde_json::to_vec(&p).expect("Invalid json"),
+                ..Default::default()
+            };
+
+            let assigned = txn.mutate(mu)
+                .map_err(|e| CommitError::PartyMutation { name: name.to_string(), source: e.compat() })?;
+            match assigned.uids.get("party") {
+                Some(uid) => Ok(uid.clone()),
+                None => Err(CommitError::MissingPartyUid { name: name.to_string() })
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct MemberObject {
+    uid: String,
+    member_id: u32,
+    xid: String,
+    #[serde(flatten)]
+    alt_ids: HashMap<String, String>,
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    full_title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    list_as: Option<String>,
+    known_as: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    former_names: Vec<String>,
+    house: String,
+    constituency: String,
+    normalized_constituency: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    constituencies: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    constituency_id: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    constituency_email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parliamentary_email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    twitter: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    twitter_official: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    current_roles: Vec<String>,
+    current_party: String,
+    parties: Vec<PartyEdge>,
+    had_whip_removed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    whip_removed: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    oath_date: Option<DateTime<Utc>>,
+    fetched_at: DateTime<Utc>,
+    source_url: String,
+    fingerprint: String,
+}
+
+impl MemberObject {
+    fn new(member: &Member, provenance: &Provenance, parties: Vec<PartyEdge>, fingerprint: String) -> Self {
+        Self {
+            uid: format!("_:{}", member.id),
+            member_id: member.id,
+            xid: members::member_xid(member),
+            alt_ids: member.alt_ids.iter().map(|(k, v)| (format!("{}_id", k), v.clone())).collect(),
+            name: member.name.clone(),
+            full_title: member.full_title.clone(),
+            list_as: member.list_as.clone(),
+            known_as: member.preferred_name().to_string(),
+            former_names: member.former_names.clone(),
+            house: member.house.to_string(),
+            constituency: member.constituency.clone(),
+            normalized_constituency: member.normalized_constituency.clone(),
+            constituencies: member.current_constituencies().into_iter().map(str::to_string).collect(),
+            constituency_id: member.constituency_id,
+            status: member.status.clone(),
+            constituency_email: member.constituency_email.clone(),
+            parliamentary_email: member.parliamentary_email.clone(),
+            twitter: member.twitter_handle(),
+            twitter_official: member.twitter_official_handle(),
+            current_roles: member.current_roles().into_iter().map(str::to_string).collect(),
+            current_party: member.current_party().to_string(),
+            parties,
+            had_whip_removed: member.had_whip_removed(),
+            whip_removed: member.whip_removed,
+            oath_date: member.oath_date,
+            fetched_at: provenance.fetched_at,
+            source_url: provenance.source_url.clone(),
+            fingerprint,
+        }
+    }
+}
+
+fn stored_fingerprints(dgraph: &dgraph::Dgraph) -> Result<HashMap<u32, String>, CommitError> {
+    #[derive(Deserialize)]
+    struct FingerprintResponse {
+        members: Vec<FingerprintObject>,
+    }
+
+    #[derive(Deserialize)]
+    struct FingerprintObject {
+        member_id: u32,
+        fingerprint: String,
+    }
+
+    let mut txn = dgraph.new_txn();
+    let resp = txn.query(FINGERPRINT_QUERY).map_err(|e| CommitError::FingerprintQuery(e.compat()))?;
+    let stored: FingerprintResponse = serde_json::from_slice(&resp.json).map_err(CommitError::FingerprintResponse)?;
+
+    Ok(stored.members.into_iter().map(|m| (m.member_id, m.fingerprint)).collect())
+}
+
+/// Pairs each member with its fingerprint, dropping those whose stored fingerprint already matches
+/// and counting how many were dropped.
+fn changed_members<'a>(batch: &'a [Member], stored: &HashMap<u32, String>,
+                       fields: &[String]) -> (Vec<(&'a Member, String)>, usize) {
+    let mut changed = vec![];
+    let mut unchanged = 0;
+    for member in batch {
+        let fingerprint = format!("{:016x}", members::fingerprint(member, fields));
+        if stored.get(&member.id) == Some(&fingerprint) {
+            unchanged += 1;
+        } else {
+            changed.push((member, fingerprint));
+        }
+    }
+    (changed, unchanged)
+}
+
+type StoredObject = serde_json::Map<String, serde_json::Value>;
+
+fn existing_query(batch: &[&Member], predicates: &BTreeSet<String>) -> String {
+    let xids = batch.iter().map(|m| format!("{:?}", members::member_xid(m))).collect::<Vec<_>>().join(", ");
+    let ids = batch.iter().map(|m| m.id.to_string()).collect::<Vec<_>>().join(", ");
+    let fields = predicates.iter()
+        .map(|p| if p == "parties" { "        parties { uid }\n".to_string() } else { format!("        {}\n", p) })
+        .collect::<String>();
+    format!("{{\n    members(func: eq(xid, [{xids}])) {{\n        uid\n        member_id\n{fields}    }}\n    \
+             legacy(func: eq(member_id, [{ids}])) @filter(NOT has(xid)) {{\n        uid\n        member_id\n{fields}    }}\n}}",
+            xids = xids, ids = ids, fields = fields)
+}
+
+fn existing_members(txn: &mut dgraph::Txn, batch: &[&Member], predicates: &BTreeSet<String>) -> Result<HashMap<u32, StoredObject>, CommitError> {
+    #[derive(Deserialize)]
+    struct ExistingResponse {
+        members: Vec<StoredObject>,
+        #[serde(default)]
+        legacy: Vec<StoredObject>,
+    }
+
+    if batch.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let resp = txn.query(existing_query(batch, predicates)).map_err(|e| CommitError::ExistingQuery(e.compat()))?;
+    let existing: ExistingResponse = serde_json::from_slice(&resp.json).map_err(CommitError::ExistingResponse)?;
+
+    // Nodes committed before members carried an xid are adopted so they gain one rather than being duplicated.
+    Ok(existing.legacy.into_iter().chain(existing.members)
+        .filter_map(|m| Some((m.get("member_id")?.as_u64()? as u32, m)))
+        .collect())
+}
+
+fn is_empty(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Null => true,
+        serde_json::Value::String(s) => s.is_empty(),
+        serde_json::Value::Array(a) => a.is_empty(),
+        _ => false
+    }
+}
+
+fn resolve_conflict(mut object: StoredObject, stored: &StoredObject, conflict: ConflictStrategy) -> Option<StoredObject> {
+    match conflict {
+        ConflictStrategy::KeepExisting => return None,
+        ConflictStrategy::MergeFields => object.retain(|k, _| stored.get(k).is_none_or(is_empty)),
+        ConflictStrategy::Overwrite => {}
+    }
+
+    object.insert("uid".to_string(), stored["uid"].clone());
+    Some(object)
+}
+
+fn commit_batch(dgraph: &dgraph::Dgraph, batch: &[Member], provenance: &Provenance, fingerprints: &HashMap<u32, String>,
+                party_uids: &Mutex<HashMap<String, String>>, conflict: ConflictStrategy, fingerprint_fields: &[String]) -> Result<CommitSummary, CommitError> {
+    let mut txn = dgraph.new_txn();
+    let mut batch_party_uids = party_uids.lock().expect("party uid lock poisoned").clone();
+    let mut summary = CommitSummary::default();
+
+    let (changed, unchanged) = changed_members(batch, fingerprints, fingerprint_fields);
+    summary.unchanged = unchanged;
+    let lookup: Vec<&Member> = changed.iter().map(|(member, _)| *member).collect();
+    let mut objects = vec![];
+    for (member, fingerprint) in changed {
+        let mut parties = vec![];
+        for party in &member.parties {
+            parties.push(PartyEdge {
+                uid: party_uid(&party.name, &mut batch_party_uids, |name| upsert_party(&mut txn, name))?,
+                start_date: party.start_date,
+                end_date: party.end_date,
+            });
+        }
+
+        let m = MemberObject::new(member, provenance, parties, fingerprint);
+        let object = match serde_json::to_value(&m) {
+            Ok(serde_json::Value::Object(object)) => object,
+            Ok(_) => unreachable!("members serialise to objects"),
+            Err(e) => return Err(CommitError::Serialize { member_id: member.id, source: e })
+        };
+        objects.push((member.id, object));
+    }
+
+    let predicates = match conflict {
+        ConflictStrategy::MergeFields => objects.iter().flat_map(|(_, o)| o.keys()).filter(|k| *k != "uid").cloned().collect(),
+        _ => BTreeSet::new()
+    };
+    let existing = existing_members(&mut txn, &lookup, &predicates)?;
+
+    for (member_id, object) in objects {
+        let object = match existing.get(&member_id) {
+            Some(stored) => match resolve_conflict(object, stored, conflict) {
+                Some(object) => object,
+                None => {
+                    summary.kept += 1;
+                    continue;
+                }
+            },
+            None => object
+        };
+
+        let mb = serde_json::to_vec(&object).map_err(|e| CommitError::Serialize { member_id, source: e })?;
+
+        let mu = dgraph::Mutation {
+            set_json: mb,
+            ..Default::default()
+        };
+
+        txn.mutate(mu).map_err(|e| CommitError::Mutation { member_id, source: e.compat() })?;
+        summary.committed += 1;
+    }
+
+    txn.commit().map_err(|e| CommitError::Commit(e.compat()))?;
+    party_uids.lock().expect("party uid lock poisoned").extend(batch_party_uids);
+
+    Ok(summary)
+}
+
+/// Decides whether a batch that failed with `e` can go on without one member: when the error names a
+/// member of the batch, that member is reported as failed and the rest of the batch is handed back.
+/// Aborted transactions are left for the caller to retry whole.
+fn without_offender(batch: &[Member], e: &CommitError) -> Option<(FailedMember, Vec<Member>)> {
+    if e.is_aborted() {
+        return None;
+    }
+    let member_id = e.member_id().filter(|id| batch.iter().any(|m| m.id == *id))?;
+    let reason = match std::error::Error::source(e) {
+        Some(source) => format!("{}: {}", e, source),
+        None => e.to_string()
+    };
+    Some((FailedMember { member_id, reason }, batch.iter().filter(|m| m.id != member_id).cloned().collect()))
+}
+
+/// Commits `batch`, retrying it without any member whose mutation fails so one bad member doesn't
+/// cost the rest of its batch.
+fn commit_isolating(batch: &[Member], commit: impl Fn(&[Member]) -> Result<CommitSummary, CommitError>) -> Result<CommitSummary, CommitError> {
+    let mut batch = Cow::Borrowed(batch);
+    let mut failed = vec![];
+    loop {
+        match commit(&batch) {
+            Ok(mut s) => {
+                s.failed = failed;
+                return Ok(s);
+            }
+            Err(e) => match without_offender(&batch, &e) {
+                Some((offender, rest)) => {
+                    log!("Skipping member {}: {}", offender.member_id, offender.reason);
+                    failed.push(offender);
+                    batch = Cow::Owned(rest);
+                }
+                None => return Err(e)
+            }
+        }
+    }
+}
+
+/// Hands `items` to `commit` a batch at a time, running up to `concurrency` batches at once on their
+/// own threads. Waves of batches are spaced by `batch_delay` and each aborted batch is retried on its
+/// own with a backoff doubling from `backoff`. `done` is told how many items each finished wave covered.
+fn commit_batches<T: Sync>(items: &[T], options: &CommitOptions, backoff: Duration,
+                           commit: impl Fn(&[T]) -> Result<CommitSummary, CommitError> + Sync,
+                           mut done: impl FnMut(usize) -> Result<(), CommitError>) -> Result<CommitSummary, CommitError> {
+    let retrying = |batch: &[T]| {
+        let mut attempt = 0;
+        loop {
+            match commit(batch) {
+                Ok(mut s) => {
+                    s.retries = attempt as usize;
+                    return Ok(s);
+                }
+                Err(e) if e.is_aborted() && attempt < options.max_retries => {
+                    let wait = backoff * 2u32.pow(attempt);
+                    attempt += 1;
+                    log!("Transaction aborted, retrying batch in {}ms ({} of {})", wait.as_millis(), attempt,
+                         options.max_retries);
+                    thread::sleep(wait);
+                }
+                Err(e) => return Err(e)
+            }
+        }
+    };
+
+    let retrying = &retrying;
+    let mut summary = CommitSummary::default();
+    let batches = items.chunks(COMMIT_BATCH_SIZE).collect::<Vec<_>>();
+    for (i, wave) in batches.chunks(options.concurrency.max(1)).enumerate() {
+        if i > 0 && options.batch_delay > Duration::from_secs(0) {
+            thread::sleep(options.batch_delay);
+        }
+
+        let results = thread::scope(|s| {
+            let handles = wave.iter().map(|batch| s.spawn(move || retrying(batch))).collect::<Vec<_>>();
+            handles.into_iter().map(|h| h.join().expect("commit thread panicked")).collect::<Vec<_>>()
+        });
+
+        for result in results {
+            let batch_summary = result?;
+            summary.committed += batch_summary.committed;
+            summary.unchanged += batch_summary.unchanged;
+            summary.kept += batch_summary.kept;
+            summary.retries += batch_summary.retries;
+            summary.failed.extend(batch_summary.failed);
+        }
+        done(wave.iter().map(|b| b.len()).sum())?;
+    }
+
+    Ok(summary)
+}
+
+pub fn commit_member_data(dgraph: &dgraph::Dgraph, members: &Members, provenance: &Provenance, checkpoint: &Checkpoint,
+                          options: &CommitOptions) -> Result<CommitSummary, CommitError> {
+    dgraph.alter(&dgraph::Operation {
+        schema: SCHEMA.to_string(),
+        ..Default::default()
+    }).map_err(|e| CommitError::Schema(e.compat()))?;
+
+    let (mut committed, pending) = checkpoint.remaining(&members.members, options.resume);
+    if committed > 0 {
+        log!("Resuming after {} already committed members", committed);
+    }
+
+    let fingerprints = if options.only_changed { stored_fingerprints(dgraph)? } else { HashMap::new() };
+    let start = Instant::now();
+
+    let party_uids = Mutex::new(HashMap::new());
+    let mut summary = commit_batches(pending, options, RETRY_BACKOFF,
+                                     |batch| commit_isolating(batch, |batch| commit_batch(dgraph, batch, provenance, &fingerprints,
+                                                                                          &party_uids, options.conflict,
+                                                                                          &options.fingerprint_fields)),
+                                     |n| {
+                                         committed += n;
+                                         Ok(checkpoint.save(committed)?)
+                                     })?;
+
+    checkpoint.clear()?;
+    summary.elapsed = start.elapsed();
+
+    Ok(summary)
+}
+
+#[derive(Debug, Default)]
+pub struct FixedHandles {
+    pub checked: usize,
+    pub updated: usize,
+    pub removed: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct TwitterObject {
+    uid: String,
+    twitter: Option<String>,
+}
+
+/// Normalises stored handles, splitting out the records to rewrite and those whose handle is
+/// unusable and should be deleted.
+fn normalize_stored_handles(records: Vec<TwitterObject>, fixed: &mut FixedHandles) -> (Vec<TwitterObject>, Vec<TwitterObject>) {
+    let mut updates = vec![];
+    let mut removals = vec![];
+    for record in records {
+        fixed.checked += 1;
+        let twitter = match &record.twitter {
+            Some(t) => t,
+            None => continue
+        };
+
+        match members::normalize_twitter_handle(twitter) {
+            Some(handle) if &handle == twitter => {}
+            Some(handle) => {
+                fixed.updated += 1;
+                updates.push(TwitterObject { uid: record.uid, twitter: Some(handle) });
+            }
+            None => {
+                fixed.removed += 1;
+                removals.push(TwitterObject { uid: record.uid, twitter: None });
+            }
+        }
+    }
+
+    (updates, removals)
+}
+
+pub fn fix_twitter_handles(dgraph: &dgraph::Dgraph) -> Result<FixedHandles, CommitError> {
+    #[derive(Deserialize)]
+    struct TwitterResponse {
+        members: Vec<TwitterObject>,
+    }
+
+    let mut txn = dgraph.new_txn();
+    let resp = txn.query(TWITTER_QUERY).map_err(|e| CommitError::TwitterQuery(e.compat()))?;
+    let existing: TwitterResponse = serde_json::from_slice(&resp.json).map_err(CommitError::TwitterResponse)?;
+
+    let mut fixed = FixedHandles::default();
+    let (updates, removals) = normalize_stored_handles(existing.members, &mut fixed);
+    for record in updates {
+        let mu = dgraph::Mutation {
+            set_json: serde_json::to_vec(&record).expect("Invalid json"),
+            ..Default::default()
+        };
+        txn.mutate(mu).map_err(|e| CommitError::TwitterMutation { uid: record.uid, source: e.compat() })?;
+    }
+    for record in removals {
+        let mu = dgraph::Mutation {
+            delete_json: serde_json::to_vec(&record).expect("Invalid json"),
+            ..Default::default()
+        };
+        txn.mutate(mu).map_err(|e| CommitError::TwitterMutation { uid: record.uid, source: e.compat() })?;
+    }
+
+    txn.commit().map_err(|e| CommitError::Commit(e.compat()))?;
+
+    Ok(fixed)
+}
+
+pub fn set_twitter_handles(dgraph: &dgraph::Dgraph, handles: &[(u32, String)]) -> Result<usize, CommitError> {
+    #[derive(Deserialize)]
+    struct TwitterResponse {
+        members: Vec<MemberUid>,
+    }
+
+    #[derive(Deserialize)]
+    struct MemberUid {
+        uid: String,
+        member_id: u32,
+    }
+
+    #[derive(Serialize)]
+    struct TwitterObject<'a> {
+        uid: String,
+        twitter: &'a str,
+    }
+
+    if handles.is_empty() {
+        return Ok(0);
+    }
+
+    let ids = handles.iter().map(|(id, _)| id.to_string()).collect::<Vec<_>>().join(", ");
+    let query = format!("{{\n    members(func: eq(member_id, [{}])) {{\n        uid\n        member_id\n    }}\n}}", ids);
+
+    let mut txn = dgraph.new_txn();
+    let resp = txn.query(query).map_err(|e| CommitError::TwitterQuery(e.compat()))?;
+    let stored: TwitterResponse = serde_json::from_slice(&resp.json).map_err(CommitError::TwitterResponse)?;
+
+    let mut updated = 0;
+    for member in stored.members {
+        let handle = match handles.iter().find(|(id, _)| *id == member.member_id) {
+            Some((_, h)) => h,
+            None => continue
+        };
+        let mu = dgraph::Mutation {
+            set_json: serde_json::to_vec(&TwitterObject { uid: member.uid.clone(), twitter: handle }).expect("Invalid json"),
+            ..Default::default()
+        };
+        txn.mutate(mu).map_err(|e| CommitError::TwitterMutation { uid: member.uid, source: e.compat() })?;
+        updated += 1;
+    }
+
+    txn.commit().map_err(|e| CommitError::Commit(e.compat()))?;
+
+    Ok(updated)
+}
+
+pub fn stored_members(dgraph: &dgraph::Dgraph) -> Result<Vec<StoredMember>, CommitError> {
+    #[derive(Deserialize)]
+    struct MembersResponse {
+        members: Vec<StoredMember>,
+    }
+
+    let mut txn = dgraph.new_txn();
+    let resp = txn.query(STORED_MEMBERS_QUERY).map_err(|e| CommitError::MembersQuery(e.compat()))?;
+    let stored: MembersResponse = serde_json::from_slice(&resp.json).map_err(CommitError::MembersResponse)?;
+
+    Ok(stored.members)
+}
+
+pub fn legacy_members(dgraph: &dgraph::Dgraph) -> Result<(Vec<LegacyNode>, HashSet<u32>), CommitError> {
+    #[derive(Deserialize)]
+    struct LegacyResponse {
+        #[serde(default)]
+        legacy: Vec<LegacyNode>,
+        #[serde(default)]
+        linked: Vec<LinkedObject>,
+    }
+
+    #[derive(Deserialize)]
+    struct LinkedObject {
+        member_id: Option<u32>,
+    }
+
+    let mut txn = dgraph.new_txn();
+    let resp = txn.query(LEGACY_MEMBERS_QUERY).map_err(|e| CommitError::LegacyQuery(e.compat()))?;
+    let stored: LegacyResponse = serde_json::from_slice(&resp.json).map_err(CommitError::LegacyResponse)?;
+
+    Ok((stored.legacy, stored.linked.into_iter().filter_map(|l| l.member_id).collect()))
+}
+
+pub fn relink_members(dgraph: &dgraph::Dgraph, links: &[(String, u32)]) -> Result<usize, CommitError> {
+    #[derive(Serialize)]
+    struct LinkObject<'a> {
+        uid: &'a str,
+        member_id: u32,
+        xid: String,
+    }
+
+    if links.is_empty() {
+        return Ok(0);
+    }
+
+    let mut txn = dgraph.new_txn();
+    for (uid, member_id) in links {
+        let mu = dgraph::Mutation {
+            set_json: serde_json::to_vec(&LinkObject { uid, member_id: *member_id, xid: members::xid_for_id(*member_id) }).expect("Invalid json"),
+            ..Default::default()
+        };
+        txn.mutate(mu).map_err(|e| CommitError::RelinkMutation { uid: uid.clone(), member_id: *member_id, source: e.compat() })?;
+    }
+    txn.commit().map_err(|e| CommitError::Commit(e.compat()))?;
+
+    Ok(links.len())
+}
+
+#[derive(Debug, Default)]
+pub struct Orphans {
+    pub parties: Vec<String>,
+    pub removed: bool,
+}
+
+#[derive(Deserialize)]
+struct OrphanResponse {
+    parties: Vec<OrphanObject>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OrphanObject {
+    uid: String,
+    #[serde(skip_serializing)]
+    party_name: String,
+}
+
+fn parse_orphans(json: &[u8]) -> Result<Vec<OrphanObject>, CommitError> {
+    let orphaned: OrphanResponse = serde_json::from_slice(json).map_err(CommitError::OrphanResponse)?;
+    Ok(orphaned.parties)
+}
+
+fn orphan_deletion(parties: &[OrphanObject]) -> Option<dgraph::Mutation> {
+    if parties.is_empty() {
+        return None;
+    }
+
+    Some(dgraph::Mutation {
+        delete_json: serde_json::to_vec(parties).expect("Invalid json"),
+        ..Default::default()
+    })
+}
+
+pub fn remove_orphans(dgraph: &dgraph::Dgraph, dry_run: bool) -> Result<Orphans, CommitError> {
+    let mut txn = dgraph.new_txn();
+    let resp = txn.query(ORPHANED_PARTIES_QUERY).map_err(|e| CommitError::OrphanQuery(e.compat()))?;
+    let orphaned = parse_orphans(&resp.json)?;
+
+    let mut orphans = Orphans {
+        parties: orphaned.iter().map(|p| p.party_name.clone()).collect(),
+        removed: false,
+    };
+    let mu = match orphan_deletion(&orphaned) {
+        Some(mu) if !dry_run => mu,
+        _ => return Ok(orphans)
+    };
+
+    txn.mutate(mu).map_err(|e| CommitError::OrphanMutation(e.compat()))?;
+    txn.commit().map_err(|e| CommitError::Commit(e.compat()))?;
+    orphans.removed = true;
+
+    Ok(orphans)
+}
+
+pub fn commit_manifest(dgraph: &dgraph::Dgraph, manifest: &RunManifest) -> Result<(), CommitError> {
+    #[derive(Serialize)]
+    struct ManifestObject<'a> {
+        uid: &'static str,
+        #[serde(flatten)]
+        manifest: &'a RunManifest,
+    }
+
+    let mut txn = dgraph.new_txn();
+    let mu = dgraph::Mutation {
+        set_json: serde_json::to_vec(&ManifestObject { uid: "_:manifest", manifest }).expect("Invalid json"),
+        ..Default::default()
+    };
+    txn.mutate(mu).map_err(|e| CommitError::Manifest(e.compat()))?;
+    txn.commit().map_err(|e| CommitError::Commit(e.compat()))?;
+
+    Ok(())
+}
+
+pub fn write_live_json<W: Write>(writer: W, members: &Members, provenance: &Provenance, fingerprint_fields: &[String],
+                                 style: JsonStyle) -> serde_json::Result<()> {
+    #[derive(Serialize)]
+    #[serde(untagged)]
+    enum LiveObject {
+        Party(PartyObject),
+        Member(Box<MemberObject>),
+    }
+
+    let mut objects = vec![];
+    let mut party_uids: HashMap<&str, String> = HashMap::new();
+    for member in &members.members {
+        let mut parties = vec![];
+        for party in &member.parties {
+            let uid = match party_uids.get(party.name.as_str()) {
+                Some(uid) => uid.clone(),
+                None => {
+                    let uid = format!("_:party{}", party_uids.len());
+                    objects.push(LiveObject::Party(PartyObject { uid: uid.clone(), party_name: party.name.clone() }));
+                    party_uids.insert(&party.name, uid.clone());
+                    uid
+                }
+            };
+            parties.push(PartyEdge {
+                uid,
+                start_date: party.start_date,
+                end_date: party.end_date,
+            });
+        }
+
+        let fingerprint = format!("{:016x}", members::fingerprint(member, fingerprint_fields));
+        objects.push(LiveObject::Member(Box::new(MemberObject::new(member, provenance, parties, fingerprint))));
+    }
+
+    style.to_writer(writer, &objects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::parse::parse_addresses_xml;
+    use crate::test_support::{commons, document};
+
+    #[test]
+    fn members_of_one_party_share_its_node() {
+        let data = document(&[
+            (172, "<Parties><Party><Name>Labour</Name><StartDate>1987-06-11T00:00:00</StartDate></Party></Parties>"),
+            (4212, "<Parties><Party><Name>Labour (Co-op)</Name><StartDate>2015-05-07T00:00:00</StartDate></Party></Parties>"),
+            (1524, "<Parties><Party><Name>Conservative</Name><StartDate>2010-05-06T00:00:00</StartDate></Party></Parties>"),
+        ]);
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+
+        let mut party_uids = HashMap::new();
+        let mut upserted = vec![];
+        let uids: Vec<String> = members.members.iter()
+            .map(|m| party_uid(&m.parties[0].name, &mut party_uids, |name| {
+                upserted.push(name.to_string());
+                Ok(format!("0x{}", upserted.len()))
+            }).unwrap())
+            .collect();
+
+        assert_eq!(upserted, ["Labour", "Conservative"]);
+        assert_eq!(uids, ["0x1", "0x1", "0x2"]);
+    }
+
+    #[test]
+    fn stored_handles_are_normalized() {
+        let record = |uid: &str, twitter: Option<&str>| TwitterObject { uid: uid.to_string(), twitter: twitter.map(str::to_string) };
+        let records = vec![
+            record("0x1", Some("HackneyAbbott")),
+            record("0x2", Some("https://twitter.com/IanLaveryMP?lang=en")),
+            record("0x3", Some("Ynys Môn MP")),
+            record("0x4", None),
+            record("0x5", Some("@jeremycorbyn")),
+        ];
+
+        let mut fixed = FixedHandles::default();
+        let (updates, removals) = normalize_stored_handles(records, &mut fixed);
+
+        assert_eq!(updates, [record("0x2", Some("IanLaveryMP")), record("0x5", Some("jeremycorbyn"))]);
+        assert_eq!(removals, [record("0x3", None)]);
+        assert_eq!((fixed.checked, fixed.updated, fixed.removed), (5, 2, 1));
+    }
+
+    #[test]
+    fn committed_members_carry_their_provenance() {
+        let members = parse_addresses_xml(&document(&[(172, "")]), &Config::default()).unwrap();
+        let provenance = Provenance {
+            fetched_at: crate::parse::parse_date("2020-01-07T12:30:00").unwrap(),
+            source_url: "https://data.parliament.uk/membersdataplatform/services/mnisv1.0/Members/Query/house=Commons".to_string(),
+        };
+
+        let object = serde_json::to_value(MemberObject::new(&members.members[0], &provenance, vec![], String::new())).unwrap();
+        assert_eq!(object["fetched_at"], "2020-01-07T12:30:00Z");
+        assert_eq!(object["source_url"], provenance.source_url.as_str());
+    }
+
+    #[test]
+    fn identical_members_are_skipped_on_the_second_commit() {
+        let data = document(&[(172, "<MemberFrom>Hackney North and Stoke Newington</MemberFrom>"),
+                              (4212, "<MemberFrom>Broxtowe</MemberFrom>")]);
+        let mut members = parse_addresses_xml(&data, &Config::default()).unwrap();
+        let fields = members::default_fingerprint_fields();
+
+        let (changed, unchanged) = changed_members(&members.members, &HashMap::new(), &fields);
+        assert_eq!((changed.len(), unchanged), (2, 0));
+        let stored: HashMap<u32, String> = changed.into_iter().map(|(m, fingerprint)| (m.id, fingerprint)).collect();
+
+        let (changed, unchanged) = changed_members(&members.members, &stored, &fields);
+        assert!(changed.is_empty());
+        assert_eq!(unchanged, 2);
+
+        members.members[1].name = "Anna Soubry".to_string();
+        let (changed, unchanged) = changed_members(&members.members, &stored, &fields);
+        let ids: Vec<u32> = changed.iter().map(|(m, _)| m.id).collect();
+        assert_eq!((ids, unchanged), (vec![4212], 1));
+    }
+
+    #[test]
+    fn orphaned_parties_are_deleted_by_uid() {
+        let json = br#"{"parties": [{"uid": "0x1", "party_name": "Change UK"}, {"uid": "0x2", "party_name": "UKIP"}]}"#;
+        let orphaned = parse_orphans(json).unwrap();
+        let names: Vec<&str> = orphaned.iter().map(|p| p.party_name.as_str()).collect();
+        assert_eq!(names, ["Change UK", "UKIP"]);
+
+        let mu = orphan_deletion(&orphaned).unwrap();
+        let deleted: serde_json::Value = serde_json::from_slice(&mu.delete_json).unwrap();
+        assert_eq!(deleted, serde_json::json!([{"uid": "0x1"}, {"uid": "0x2"}]));
+        assert!(mu.set_json.is_empty());
+    }
+
+    #[test]
+    fn no_orphans_means_no_mutation() {
+        let orphaned = parse_orphans(br#"{"parties": []}"#).unwrap();
+        assert!(orphan_deletion(&orphaned).is_none());
+        assert!(matches!(parse_orphans(br#"{"nodes": []}"#), Err(CommitError::OrphanResponse(_))));
+    }
+
+    #[test]
+    fn batches_are_spaced_out_and_aborted_ones_retried() {
+        let options = CommitOptions {
+            batch_delay: Duration::from_millis(30),
+            max_retries: 2,
+            concurrency: 1,
+            ..CommitOptions::default()
+        };
+        let items = vec![0; COMMIT_BATCH_SIZE * 2 + 50];
+        let aborted = || CommitError::Commit(failure::err_msg("Transaction has been aborted. Please retry").compat());
+
+        let calls = Mutex::new(vec![]);
+        let mut done = vec![];
+        let summary = commit_batches(&items, &options, Duration::from_millis(1), |batch| {
+            let mut calls = calls.lock().unwrap();
+            calls.push((batch.len(), Instant::now()));
+            if calls.len() == 2 {
+                return Err(aborted());
+            }
+            Ok(CommitSummary { committed: batch.len(), ..CommitSummary::default() })
+        }, |n| {
+            done.push(n);
+            Ok(())
+        }).unwrap();
+
+        let calls = calls.into_inner().unwrap();
+        let sizes: Vec<usize> = calls.iter().map(|(n, _)| *n).collect();
+        assert_eq!(sizes, [COMMIT_BATCH_SIZE, COMMIT_BATCH_SIZE, COMMIT_BATCH_SIZE, 50]);
+        assert!(calls[1].1 - calls[0].1 >= options.batch_delay);
+        assert!(calls[3].1 - calls[2].1 >= options.batch_delay);
+        assert_eq!(done, [COMMIT_BATCH_SIZE, COMMIT_BATCH_SIZE, 50]);
+        assert_eq!((summary.committed, summary.retries), (items.len(), 1));
+
+        let result = commit_batches(&items, &options, Duration::from_millis(1), |_| Err(aborted()), |_| Ok(()));
+        assert!(matches!(result, Err(CommitError::Commit(_))));
+    }
+
+    #[test]
+    fn only_the_member_named_by_an_error_is_dropped() {
+        let batch: Vec<Member> = [172, 4212, 1524].iter().map(|id| Member { id: *id, ..Member::new() }).collect();
+        let mutation = |member_id, message| CommitError::Mutation { member_id, source: failure::err_msg(message).compat() };
+
+        let (failed, rest) = without_offender(&batch, &mutation(4212, "invalid value")).unwrap();
+        assert_eq!(failed.member_id, 4212);
+        assert_eq!(failed.reason, "mutation for member 4212 failed: invalid value");
+        assert_eq!(rest.iter().map(|m| m.id).collect::<Vec<_>>(), [172, 1524]);
+
+        assert!(without_offender(&batch, &mutation(4005, "invalid value")).is_none());
+        assert!(without_offender(&batch, &mutation(172, "Transaction has been aborted. Please retry")).is_none());
+        assert!(without_offender(&batch, &CommitError::Commit(failure::err_msg("unavailable").compat())).is_none());
+    }
+
+    #[test]
+    fn a_failing_member_is_skipped_and_the_rest_committed() {
+        let batch: Vec<Member> = [172, 4212, 1524].iter().map(|id| Member { id: *id, ..Member::new() }).collect();
+        let committed = Mutex::new(vec![]);
+
+        let summary = commit_isolating(&batch, |batch| {
+            if batch.iter().any(|m| m.id == 4212) {
+                return Err(CommitError::Mutation { member_id: 4212, source: failure::err_msg("invalid value").compat() });
+            }
+            committed.lock().unwrap().extend(batch.iter().map(|m| m.id));
+            Ok(CommitSummary { committed: batch.len(), ..CommitSummary::default() })
+        }).unwrap();
+
+        assert_eq!(*committed.lock().unwrap(), [172, 1524]);
+        assert_eq!(summary.committed, 2);
+        assert_eq!(summary.failed.iter().map(|f| f.member_id).collect::<Vec<_>>(), [4212]);
+
+        let result = commit_isolating(&batch, |_| Err(CommitError::Commit(failure::err_msg("unavailable").compat())));
+        assert!(matches!(result, Err(CommitError::Commit(_))));
+    }
+
+    #[test]
+    fn live_json_links_members_to_party_blank_nodes() {
+        let members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+        let provenance = Provenance {
+            fetched_at: crate::parse::parse_date("2020-01-07T12:30:00").unwrap(),
+            source_url: "fixtures/commons.xml".to_string(),
+        };
+
+        let mut out = vec![];
+        write_live_json(&mut out, &members, &provenance, &members::default_fingerprint_fields(), JsonStyle::Compact).unwrap();
+        let objects: Vec<serde_json::Value> = serde_json::from_slice(&out).unwrap();
+
+        let (parties, written): (Vec<_>, Vec<_>) = objects.iter().partition(|o| o.get("party_name").is_some());
+        let party_uids: HashMap<&str, &str> = parties.iter()
+            .map(|p| (p["uid"].as_str().unwrap(), p["party_name"].as_str().unwrap()))
+            .collect();
+        assert_eq!(party_uids.len(), parties.len());
+        assert!(party_uids.keys().all(|uid| uid.starts_with("_:party")));
+
+        let ids: Vec<u64> = written.iter().map(|m| m["member_id"].as_u64().unwrap()).collect();
+        assert_eq!(ids, [172, 4212, 1524, 4005, 4064, 4520, 1437]);
+        for member in &written {
+            assert_eq!(member["uid"], format!("_:{}", member["member_id"]));
+            assert_eq!(member["source_url"], "fixtures/commons.xml");
+            for edge in member["parties"].as_array().unwrap() {
+                assert!(party_uids.contains_key(edge["uid"].as_str().unwrap()), "{}", edge);
+            }
+        }
+
+        let soubry: Vec<&str> = written[1]["parties"].as_array().unwrap().iter()
+            .map(|edge| party_uids[edge["uid"].as_str().unwrap()])
+            .collect();
+        assert_eq!(soubry, ["The Independent Group for Change", "The Independent Group", "Conservative"]);
+    }
+
+    #[test]
+    fn each_conflict_strategy_treats_stored_members_differently() {
+        let object = |value: serde_json::Value| match value {
+            serde_json::Value::Object(object) => object,
+            _ => unreachable!()
+        };
+        let stored = object(serde_json::json!({
+            "uid": "0x1",
+            "member_id": 172,
+            "name": "Ms Diane Abbott",
+            "twitter_handle": "",
+            "facebook": "curated",
+        }));
+        let loaded = object(serde_json::json!({
+            "member_id": 172,
+            "name": "Diane Abbott",
+            "twitter_handle": "HackneyAbbott",
+            "facebook": "loaded",
+        }));
+
+        let overwritten = resolve_conflict(loaded.clone(), &stored, ConflictStrategy::Overwrite).unwrap();
+        assert_eq!(serde_json::Value::Object(overwritten), serde_json::json!({
+            "uid": "0x1",
+            "member_id": 172,
+            "name": "Diane Abbott",
+            "twitter_handle": "HackneyAbbott",
+            "facebook": "loaded",
+        }));
+
+        assert!(resolve_conflict(loaded.clone(), &stored, ConflictStrategy::KeepExisting).is_none());
+
+        let merged = resolve_conflict(loaded, &stored, ConflictStrategy::MergeFields).unwrap();
+        assert_eq!(serde_json::Value::Object(merged), serde_json::json!({
+            "uid": "0x1",
+            "twitter_handle": "HackneyAbbott",
+        }));
+    }
+
+    #[test]
+    fn members_keep_their_xid_across_commits() {
+        let provenance = |fetched_at| Provenance {
+            fetched_at: crate::parse::parse_date(fetched_at).unwrap(),
+            source_url: "https://data.parliament.uk/membersdataplatform/services/mnisv1.0/Members/Query/house=Commons".to_string(),
+        };
+        let xids = |data: &str, provenance: &Provenance| {
+            let members = parse_addresses_xml(data, &Config::default()).unwrap();
+            members.members.iter()
+                .map(|m| serde_json::to_value(MemberObject::new(m, provenance, vec![], String::new())).unwrap()["xid"].clone())
+                .collect::<Vec<_>>()
+        };
+
+        let first = xids(&document(&[(172, ""), (4212, "")]), &provenance("2020-01-07T12:30:00"));
+        let second = xids(&document(&[(172, "<MemberFrom>Hackney North and Stoke Newington</MemberFrom>"), (4212, "")]),
+                          &provenance("2020-01-08T12:30:00"));
+        assert_eq!(first, ["uk-parliament/member/172", "uk-parliament/member/4212"]);
+        assert_eq!(first, second);
+
+        let members = parse_addresses_xml(&document(&[(172, ""), (4212, "")]), &Config::default()).unwrap();
+        let batch: Vec<&Member> = members.members.iter().collect();
+        let query = existing_query(&batch, &BTreeSet::new());
+        assert!(query.contains(r#"eq(xid, ["uk-parliament/member/172", "uk-parliament/member/4212"])"#), "{}", query);
+        assert!(query.contains("eq(member_id, [172, 4212])) @filter(NOT has(xid))"), "{}", query);
+    }
+
+    #[test]
+    fn concurrent_batches_commit_each_member_once() {
+        let options = CommitOptions {
+            max_retries: 2,
+            concurrency: 3,
+            ..CommitOptions::default()
+        };
+        let items: Vec<u32> = (0..COMMIT_BATCH_SIZE as u32 * 7 + 13).collect();
+        let aborted = || CommitError::Commit(failure::err_msg("Transaction has been aborted. Please retry").compat());
+
+        let committed = Mutex::new(vec![]);
+        let attempts = Mutex::new(HashMap::new());
+        let mut done = vec![];
+        let summary = commit_batches(&items, &options, Duration::from_millis(1), |batch| {
+            let attempt = {
+                let mut attempts = attempts.lock().unwrap();
+                let attempt = attempts.entry(batch[0]).or_insert(0);
+                *attempt += 1;
+                *attempt
+            };
+            // Every other batch conflicts with a concurrent transaction on its first attempt.
+            if attempt == 1 && batch[0] % (COMMIT_BATCH_SIZE as u32 * 2) == 0 {
+                return Err(aborted());
+            }
+            committed.lock().unwrap().extend_from_slice(batch);
+            Ok(CommitSummary { committed: batch.len(), ..CommitSummary::default() })
+        }, |n| {
+            done.push(n);
+            Ok(())
+        }).unwrap();
+
+        let mut committed = committed.into_inner().unwrap();
+        committed.sort();
+        assert_eq!(committed, items);
+        assert_eq!((summary.committed, summary.retries), (items.len(), 4));
+        assert_eq!(done, [COMMIT_BATCH_SIZE * 3, COMMIT_BATCH_SIZE * 3, COMMIT_BATCH_SIZE + 13]);
+    }
+
+    #[test]
+    fn the_standalone_party_is_committed_without_a_party_history() {
+        let members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+        let provenance = Provenance {
+            fetched_at: crate::parse::parse_date("2020-01-07T12:30:00").unwrap(),
+            source_url: "fixtures/commons.xml".to_string(),
+        };
+
+        let hermon = members.by_id(1437).unwrap();
+        assert!(hermon.parties.is_empty());
+        assert_eq!(hermon.current_party(), hermon.party);
+        assert_eq!(hermon.current_party_since(), None);
+        assert!(!hermon.had_whip_removed());
+        assert!(hermon.same_day_switches().is_empty());
+        assert_eq!(hermon.party_history(), "");
+
+        let object = serde_json::to_value(MemberObject::new(hermon, &provenance, vec![], String::new())).unwrap();
+        assert_eq!(object["current_party"], "Independent");
+        assert_eq!(object["parties"], serde_json::json!([]));
+        assert_eq!(object["had_whip_removed"], false);
+    }
+}
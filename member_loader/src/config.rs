@@ -0,0 +1,184 @@
+use crate::members::{self, IdRange};
+use crate::parse;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("unable to read config {path}")]
+    Read {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    #[error("invalid config {path}")]
+    Parse {
+        path: String,
+        #[source]
+        source: toml::de::Error,
+    },
+    #[error("unknown fingerprint field {field} in config {path}")]
+    UnknownFingerprintField {
+        path: String,
+        field: String,
+    },
+    #[error("profile {profile} is not defined in config {path}")]
+    UnknownProfile {
+        path: String,
+        profile: String,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    pub party_aliases: HashMap<String, String>,
+    pub party_colours: HashMap<String, String>,
+    pub constituency_aliases: HashMap<String, String>,
+    pub date_formats: Vec<String>,
+    pub independent_parties: Vec<String>,
+    pub element_aliases: HashMap<String, String>,
+    pub fingerprint_fields: Vec<String>,
+    pub member_ids: Option<IdRange>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let mut party_aliases = HashMap::new();
+        party_aliases.insert("Labour (Co-op)".to_string(), "Labour".to_string());
+        party_aliases.insert("Labour/Co-operative".to_string(), "Labour".to_string());
+
+        let mut party_colours = HashMap::new();
+        for (party, colour) in &[
+            ("Conservative", "#0087DC"),
+            ("Labour", "#DC241F"),
+            ("Liberal Democrat", "#FAA61A"),
+            ("Scottish National Party", "#FDF38E"),
+            ("Plaid Cymru", "#005B54"),
+            ("Green Party", "#6AB023"),
+            ("Democratic Unionist Party", "#D46A4C"),
+            ("Sinn Féin", "#326760"),
+            ("Independent", "#DDDDDD"),
+            ("Speaker", "#FFFFFF"),
+        ] {
+            party_colours.insert(party.to_string(), colour.to_string());
+        }
+
+        let mut constituency_aliases = HashMap::new();
+        for (alias, name) in &[
+            ("Ynys Mon", "Ynys Môn"),
+            ("Anglesey", "Ynys Môn"),
+            ("Western Isles", "Na h-Eileanan an Iar"),
+            ("Na h-Eileanan An Iar", "Na h-Eileanan an Iar"),
+            ("Weston-Super-Mare", "Weston-super-Mare"),
+            ("Montgomery", "Montgomeryshire"),
+        ] {
+            constituency_aliases.insert(alias.to_string(), name.to_string());
+        }
+
+        Self {
+            party_aliases,
+            party_colours,
+            constituency_aliases,
+            date_formats: parse::DEFAULT_DATE_FORMATS.iter().map(|f| f.to_string()).collect(),
+            independent_parties: vec!["Independent".to_string()],
+            element_aliases: HashMap::new(),
+            fingerprint_fields: members::default_fingerprint_fields(),
+            member_ids: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn load(path: &str, profile: Option<&str>) -> Result<Self, ConfigError> {
+        let data = fs::read_to_string(path).map_err(|e| ConfigError::Read { path: path.to_string(), source: e })?;
+        let mut value: toml::Value = toml::from_str(&data).map_err(|e| ConfigError::Parse { path: path.to_string(), source: e })?;
+
+        let profiles = value.as_table_mut().and_then(|t| t.remove("profiles"));
+        if let Some(profile) = profile {
+            let overrides = profiles.as_ref().and_then(|p| p.get(profile))
+                .ok_or_else(|| ConfigError::UnknownProfile { path: path.to_string(), profile: profile.to_string() })?;
+            merge(&mut value, overrides.clone());
+        }
+
+        let config: Config = value.try_into().map_err(|e| ConfigError::Parse { path: path.to_string(), source: e })?;
+        if let Some(field) = config.fingerprint_fields.iter().find(|f| !members::FINGERPRINT_FIELDS.iter().any(|(name, _)| name == f)) {
+            return Err(ConfigError::UnknownFingerprintField { path: path.to_string(), field: field.clone() });
+        }
+        Ok(config)
+    }
+}
+
+fn merge(base: &mut toml::Value, overrides: toml::Value) {
+    match (base, overrides) {
+        (toml::Value::Table(base), toml::Value::Table(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn a_selected_profile_overrides_the_base_config() {
+        let dir = TempDir::new("config-profile");
+        let path = dir.path().join("config.toml");
+        fs::write(&path, r#"
+date_formats = ["%Y-%m-%d"]
+independent_parties = ["Independent"]
+
+[party_aliases]
+"Labour (Co-op)" = "Labour"
+
+[profiles.prod]
+independent_parties = ["Independent", "Speaker"]
+
+[profiles.prod.party_aliases]
+"Lab" = "Labour"
+"#).unwrap();
+        let path = path.to_str().unwrap();
+
+        let base = Config::load(path, None).unwrap();
+        assert_eq!(base.independent_parties, ["Independent"]);
+        assert_eq!(base.party_aliases.len(), 1);
+
+        let prod = Config::load(path, Some("prod")).unwrap();
+        assert_eq!(prod.independent_parties, ["Independent", "Speaker"]);
+        assert_eq!(prod.date_formats, ["%Y-%m-%d"]);
+        assert_eq!(prod.party_aliases.get("Labour (Co-op)").map(String::as_str), Some("Labour"));
+        assert_eq!(prod.party_aliases.get("Lab").map(String::as_str), Some("Labour"));
+
+        match Config::load(path, Some("staging")) {
+            Err(ConfigError::UnknownProfile { profile, .. }) => assert_eq!(profile, "staging"),
+            other => panic!("expected an unknown profile, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_fingerprint_fields_are_rejected() {
+        let dir = TempDir::new("config-fingerprint");
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "fingerprint_fields = [\"name\", \"modified\"]\n").unwrap();
+        assert_eq!(Config::load(path.to_str().unwrap(), None).unwrap().fingerprint_fields, ["name", "modified"]);
+
+        fs::write(&path, "fingerprint_fields = [\"name\", \"fetched_at\"]\n").unwrap();
+        match Config::load(path.to_str().unwrap(), None) {
+            Err(ConfigError::UnknownFingerprintField { field, .. }) => assert_eq!(field, "fetched_at"),
+            other => panic!("expected an unknown fingerprint field, got {:?}", other),
+        }
+    }
+}
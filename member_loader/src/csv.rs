@@ -0,0 +1,63 @@
+use crate::members::{Member, Members};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const HEADER: [&str; 9] = ["member_id", "name", "party", "house", "constituency", "constituency_id", "twitter",
+    "facebook", "email"];
+
+fn field(value: &str) -> String {
+    if value.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+const CONTACT_COLUMNS: &[&str] = &["email"];
+
+fn keep(column: &str, redact_contact: bool) -> bool {
+    !redact_contact || !CONTACT_COLUMNS.contains(&column)
+}
+
+pub fn columns(redact_contact: bool) -> Vec<&'static str> {
+    HEADER.iter().copied().filter(|c| keep(c, redact_contact)).collect()
+}
+
+pub fn row(member: &Member, redact_contact: bool) -> Vec<String> {
+    let row = [
+        member.id.to_string(),
+        member.name.clone(),
+        member.current_party().to_string(),
+        member.house.to_string(),
+        member.constituency.clone(),
+        member.constituency_id.map(|id| id.to_string()).unwrap_or_default(),
+        member.twitter_handle().unwrap_or_default(),
+        member.facebook.clone().unwrap_or_default(),
+        member.email().unwrap_or_default().to_string(),
+    ];
+    HEADER.iter().zip(row).filter(|(c, _)| keep(c, redact_contact)).map(|(_, v)| v).collect()
+}
+
+pub fn to_csv(members: &Members, flatten_parties: bool, redact_contact: bool) -> String {
+    let mut out = columns(redact_contact).join(",");
+    if flatten_parties {
+        out.push_str(",party_history");
+    }
+    out.push('\n');
+
+    for member in &members.members {
+        out.push_str(&row(member, redact_contact).iter().map(|v| field(v)).collect::<Vec<_>>().join(","));
+        if flatten_parties {
+            out.push(',');
+            out.push_str(&field(&member.party_history()));
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+pub fn write_csv(path: &Path, members: &Members, flatten_parties: bool, redact_contact: bool) -> io::Result<()> {
+    fs::write(path, to_csv(members, flatten_parties, redact_contact))
+}
@@ -0,0 +1,311 @@
+use crate::loader::LoadedMembers;
+use crate::members::{normalize_twitter_handle, Member, Members};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("unable to read export {}", .path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("invalid export {} at line {line}", .path.display())]
+    Parse {
+        path: PathBuf,
+        line: usize,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExportedParty {
+    pub name: String,
+    #[serde(default)]
+    pub start_date: Option<String>,
+    #[serde(default)]
+    pub end_date: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExportedMember {
+    pub id: u32,
+    pub name: String,
+    #[serde(default)]
+    pub party: String,
+    #[serde(default)]
+    pub current_party: Option<String>,
+    #[serde(default)]
+    pub parties: Vec<ExportedParty>,
+    #[serde(default)]
+    pub twitter: Option<String>,
+}
+
+impl ExportedMember {
+    fn current_party(&self) -> &str {
+        if let Some(party) = &self.current_party {
+            return party;
+        }
+        match self.parties.iter().filter(|p| p.end_date.is_none()).max_by_key(|p| &p.start_date) {
+            Some(p) => &p.name,
+            None => &self.party
+        }
+    }
+
+    fn twitter_handle(&self) -> Option<String> {
+        self.twitter.as_ref().and_then(|t| normalize_twitter_handle(t))
+    }
+}
+
+impl From<&Member> for ExportedMember {
+    fn from(member: &Member) -> Self {
+        Self {
+            id: member.id,
+            name: member.name.clone(),
+            party: member.party.clone(),
+            current_party: Some(member.current_party().to_string()),
+            parties: vec![],
+            twitter: member.twitter_handle(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MemberDiff {
+    Added {
+        member_id: u32,
+        name: String,
+    },
+    Removed {
+        member_id: u32,
+        name: String,
+    },
+    NameChanged {
+        member_id: u32,
+        before: String,
+        after: String,
+    },
+    PartyChanged {
+        member_id: u32,
+        before: String,
+        after: String,
+    },
+    TwitterChanged {
+        member_id: u32,
+        before: Option<String>,
+        after: Option<String>,
+    },
+}
+
+impl fmt::Display for MemberDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemberDiff::Added { member_id, name } => write!(f, "+ {} ({})", name, member_id),
+            MemberDiff::Removed { member_id, name } => write!(f, "- {} ({})", name, member_id),
+            MemberDiff::NameChanged { member_id, before, after } => write!(f, "~ {} renamed from {:?} to {:?}", member_id, before, after),
+            MemberDiff::PartyChanged { member_id, before, after } => write!(f, "~ {} moved from {} to {}", member_id, before, after),
+            MemberDiff::TwitterChanged { member_id, before, after } => write!(f, "~ {} changed Twitter handle from {:?} to {:?}", member_id, before, after),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ExportDiff {
+    pub before: usize,
+    pub after: usize,
+    pub changes: Vec<MemberDiff>,
+}
+
+pub fn read_export(path: &Path) -> Result<Vec<ExportedMember>, ExportError> {
+    let data = fs::read_to_string(path).map_err(|e| ExportError::Read { path: path.to_path_buf(), source: e })?;
+
+    if data.trim_start().starts_with('[') {
+        return serde_json::from_str(&data).map_err(|e| ExportError::Parse { path: path.to_path_buf(), line: e.line(), source: e });
+    }
+
+    let mut members = vec![];
+    for (i, line) in data.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        members.push(serde_json::from_str(line)
+            .map_err(|e| ExportError::Parse { path: path.to_path_buf(), line: i + 1, source: e })?);
+    }
+    Ok(members)
+}
+
+pub fn diff_exports(before: &[ExportedMember], after: &[ExportedMember]) -> ExportDiff {
+    let mut before_by_id: HashMap<u32, &ExportedMember> = before.iter().map(|m| (m.id, m)).collect();
+
+    let mut diff = ExportDiff {
+        before: before.len(),
+        after: after.len(),
+        changes: vec![],
+    };
+
+    for a in after {
+        let b = match before_by_id.remove(&a.id) {
+            Some(b) => b,
+            None => {
+                diff.changes.push(MemberDiff::Added { member_id: a.id, name: a.name.clone() });
+                continue;
+            }
+        };
+
+        if b.name != a.name {
+            diff.changes.push(MemberDiff::NameChanged { member_id: a.id, before: b.name.clone(), after: a.name.clone() });
+        }
+        if b.current_party() != a.current_party() {
+            diff.changes.push(MemberDiff::PartyChanged {
+                member_id: a.id,
+                before: b.current_party().to_string(),
+                after: a.current_party().to_string(),
+            });
+        }
+        if b.twitter_handle() != a.twitter_handle() {
+            diff.changes.push(MemberDiff::TwitterChanged {
+                member_id: a.id,
+                before: b.twitter_handle(),
+                after: a.twitter_handle(),
+            });
+        }
+    }
+
+    let mut removed = before_by_id.into_values().collect::<Vec<_>>();
+    removed.sort_by_key(|m| m.id);
+    for b in removed {
+        diff.changes.push(MemberDiff::Removed { member_id: b.id, name: b.name.clone() });
+    }
+
+    diff
+}
+
+pub fn changed_since(previous: &[ExportedMember], members: &Members) -> HashSet<u32> {
+    let current = members.members.iter().map(ExportedMember::from).collect::<Vec<_>>();
+    diff_exports(previous, &current).changes.into_iter().filter_map(|c| match c {
+        MemberDiff::Removed { .. } => None,
+        MemberDiff::Added { member_id, .. } | MemberDiff::NameChanged { member_id, .. } |
+        MemberDiff::PartyChanged { member_id, .. } | MemberDiff::TwitterChanged { member_id, .. } => Some(member_id)
+    }).collect()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimelineEvent {
+    pub date: DateTime<Utc>,
+    pub source: String,
+    #[serde(flatten)]
+    pub change: MemberDiff,
+}
+
+pub fn timeline(snapshots: &[LoadedMembers]) -> Vec<TimelineEvent> {
+    let mut events = vec![];
+    for pair in snapshots.windows(2) {
+        let before = pair[0].members.members.iter().map(ExportedMember::from).collect::<Vec<_>>();
+        let after = pair[1].members.members.iter().map(ExportedMember::from).collect::<Vec<_>>();
+        events.extend(diff_exports(&before, &after).changes.into_iter().map(|change| TimelineEvent {
+            date: pair[1].fetched_at,
+            source: pair[1].source.clone(),
+            change,
+        }));
+    }
+
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::{Input, Loader};
+    use crate::test_support::{document, TempDir};
+
+    #[test]
+    fn json_and_jsonl_exports_are_diffed() {
+        let dir = TempDir::new("diff");
+        let before = dir.path().join("before.json");
+        let after = dir.path().join("after.jsonl");
+        fs::write(&before, r#"[
+            {"id": 172, "name": "Ms Diane Abbott", "party": "Labour", "twitter": "https://twitter.com/HackneyAbbott"},
+            {"id": 4212, "name": "Anna Soubry", "parties": [{"name": "Conservative", "end_date": "2019-02-20"},
+                                                            {"name": "The Independent Group for Change"}]},
+            {"id": 1524, "name": "Mr John Bercow", "party": "Speaker"}
+        ]"#).unwrap();
+        fs::write(&after, concat!(
+            r#"{"id": 172, "name": "Diane Abbott", "party": "Labour", "twitter": "@HackneyAbbott"}"#, "\n",
+            "\n",
+            r#"{"id": 4212, "name": "Anna Soubry", "current_party": "Independent", "twitter": "Anna_Soubry"}"#, "\n",
+            r#"{"id": 4520, "name": "Gareth Thomas", "party": "Labour"}"#, "\n",
+        )).unwrap();
+
+        let diff = diff_exports(&read_export(&before).unwrap(), &read_export(&after).unwrap());
+        let changes: Vec<String> = diff.changes.iter().map(|c| c.to_string()).collect();
+
+        assert_eq!((diff.before, diff.after), (3, 3));
+        assert_eq!(changes, [
+            "~ 172 renamed from \"Ms Diane Abbott\" to \"Diane Abbott\"",
+            "~ 4212 moved from The Independent Group for Change to Independent",
+            "~ 4212 changed Twitter handle from None to Some(\"Anna_Soubry\")",
+            "+ Gareth Thomas (4520)",
+            "- Mr John Bercow (1524)",
+        ]);
+        assert_eq!(serde_json::to_value(&diff.changes[3]).unwrap(),
+                   serde_json::json!({"kind": "added", "member_id": 4520, "name": "Gareth Thomas"}));
+    }
+
+    #[test]
+    fn malformed_lines_are_reported_with_their_number() {
+        let dir = TempDir::new("diff");
+        let path = dir.path().join("export.jsonl");
+        fs::write(&path, "{\"id\": 172, \"name\": \"Ms Diane Abbott\"}\n{\"id\": \"x\"}\n").unwrap();
+
+        match read_export(&path) {
+            Err(ExportError::Parse { line, .. }) => assert_eq!(line, 2),
+            other => panic!("expected a parse error, got {:?}", other.map(|m| m.len())),
+        }
+    }
+
+    #[test]
+    fn the_latest_open_exported_party_is_current() {
+        let member: ExportedMember = serde_json::from_str(r#"{"id": 4212, "name": "Anna Soubry", "party": "Conservative", "parties": [
+            {"name": "Change UK", "start_date": "2019-02-18"},
+            {"name": "Independent", "start_date": "2019-06-04"},
+            {"name": "Conservative", "start_date": "2005-05-05"}
+        ]}"#).unwrap();
+        assert_eq!(member.current_party(), "Independent");
+    }
+
+    #[test]
+    fn dated_snapshots_form_a_timeline() {
+        let party = |name: &str| format!("<Parties><Party><Name>{}</Name><StartDate>2015-05-07T00:00:00</StartDate></Party></Parties>", name);
+        let dir = TempDir::new("timeline");
+        fs::write(dir.path().join("commons-20190101.xml"), document(&[(172, &party("Labour")), (4212, &party("Conservative"))])).unwrap();
+        fs::write(dir.path().join("commons-2019-02-20.xml"), document(&[(172, &party("Labour")), (4212, &party("Change UK"))])).unwrap();
+        fs::write(dir.path().join("Commons-20190604T120000Z.xml"), document(&[(4212, &party("Independent")), (4520, &party("Labour"))])).unwrap();
+        fs::write(dir.path().join("notes.txt"), "not a snapshot").unwrap();
+
+        let loader = Loader::new()
+            .input(Input::Files(dir.path().join("*.xml").display().to_string(), None))
+            .min_members(Some(0))
+            .build()
+            .unwrap();
+        let events = timeline(&loader.load().unwrap());
+
+        let events: Vec<(String, String)> = events.iter()
+            .map(|e| (e.date.format("%Y-%m-%dT%H:%M").to_string(), e.change.to_string()))
+            .collect();
+        assert_eq!(events, [
+            ("2019-02-20T00:00".to_string(), "~ 4212 moved from Conservative to Change UK".to_string()),
+            ("2019-06-04T12:00".to_string(), "~ 4212 moved from Change UK to Independent".to_string()),
+            ("2019-06-04T12:00".to_string(), "+ Member 4520 (4520)".to_string()),
+            ("2019-06-04T12:00".to_string(), "- Member 172 (172)".to_string()),
+        ]);
+    }
+}
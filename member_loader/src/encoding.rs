@@ -0,0 +1,90 @@
+use encoding_rs::{Encoding, UTF_8};
+
+pub fn charset_from_content_type(content_type: &str) -> Option<&str> {
+    content_type.split(';')
+        .map(str::trim)
+        .find(|p| p.len() > 8 && p[..8].eq_ignore_ascii_case("charset="))
+        .map(|p| p[8..].trim_matches('"'))
+}
+
+fn declared_label(head: &str) -> Option<(usize, &str)> {
+    if !head.trim_start().starts_with("<?xml") {
+        return None;
+    }
+
+    let decl = &head[..head.find("?>")?];
+    let start = decl.find("encoding=")? + "encoding=".len();
+    let quote = decl[start..].chars().next()?;
+    let label = decl[start + 1..].split(quote).next()?;
+    Some((start + 1, label))
+}
+
+fn declared_encoding(bytes: &[u8]) -> Option<&'static Encoding> {
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(256)]);
+    declared_label(&head).and_then(|(_, label)| Encoding::for_label(label.as_bytes()))
+}
+
+pub fn decode(bytes: &[u8], charset: Option<&str>) -> String {
+    let encoding = charset.and_then(|c| Encoding::for_label(c.as_bytes()))
+        .or_else(|| declared_encoding(bytes))
+        .unwrap_or(UTF_8);
+
+    let (text, _, _) = encoding.decode(bytes);
+    let mut text = text.into_owned();
+
+    if let Some((start, label)) = declared_label(&text) {
+        let end = start + label.len();
+        text.replace_range(start..end, "UTF-8");
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::parse;
+    use crate::test_support::commons;
+
+    // "Siân James" as Windows-1252, where â is the single byte 0xE2.
+    const NAME_1252: &[u8] = b"Si\xe2n James";
+
+    #[test]
+    fn windows_1252_is_decoded_to_utf8() {
+        let mut feed = b"<?xml version=\"1.0\" encoding=\"windows-1252\"?><Members><Member Member_Id=\"1419\"><DisplayAs>".to_vec();
+        feed.extend_from_slice(NAME_1252);
+        feed.extend_from_slice(b"</DisplayAs></Member></Members>");
+
+        let text = decode(&feed, None);
+        assert_eq!(text, "<?xml version=\"1.0\" encoding=\"UTF-8\"?><Members><Member Member_Id=\"1419\"><DisplayAs>Siân James</DisplayAs></Member></Members>");
+
+        let members = crate::parse::parse_addresses_xml(&text, &crate::config::Config::default()).unwrap();
+        assert_eq!(members.members[0].name, "Siân James");
+    }
+
+    #[test]
+    fn response_charset_overrides_the_declaration() {
+        assert_eq!(charset_from_content_type("text/xml; charset=\"Windows-1252\""), Some("Windows-1252"));
+        assert_eq!(charset_from_content_type("application/xml"), None);
+
+        assert_eq!(decode(NAME_1252, Some("windows-1252")), "Siân James");
+        assert_eq!(decode("Siân James".as_bytes(), None), "Siân James");
+    }
+
+    #[test]
+    fn a_bom_and_processing_instructions_are_skipped() {
+        let mut bytes = b"\xef\xbb\xbf".to_vec();
+        bytes.extend_from_slice(commons().as_bytes());
+        let text = decode(&bytes, None);
+        assert!(text.starts_with("<?xml"));
+
+        let with_stylesheet = text.replacen("?>", "?>\n<?xml-stylesheet type=\"text/xsl\" href=\"members.xsl\"?>", 1);
+        let members = parse::parse_addresses(&with_stylesheet, None, &Config::default()).unwrap();
+        assert_eq!(members.members.len(), 7);
+
+        let undecoded = format!("\u{feff}{}", with_stylesheet);
+        let members = parse::parse_addresses(&undecoded, None, &Config::default()).unwrap();
+        assert_eq!(members.members.len(), 7);
+    }
+}
@@ -0,0 +1,90 @@
+use crate::config::Config;
+use crate::members::{Member, Party};
+use chrono::{DateTime, Utc};
+
+fn date(d: DateTime<Utc>) -> String {
+    d.format("%Y-%m-%d").to_string()
+}
+
+fn spell(p: &Party) -> String {
+    format!("{} from {} to {}", p.name, date(p.start_date), p.end_date.map_or("present".to_string(), date))
+}
+
+fn explain_current_party(member: &Member) -> Vec<String> {
+    let open = member.open_parties();
+    match member.current_party_spell() {
+        Some(current) if open.len() > 1 => vec![
+            format!("current_party = {:?}: latest starting of {} open party spells ({})", current.name, open.len(),
+                    open.iter().map(|p| spell(p)).collect::<Vec<_>>().join("; ")),
+            format!("current_party_since = {}: start of the open {} spell", date(current.start_date), current.name),
+        ],
+        Some(current) => vec![
+            format!("current_party = {:?}: the only open party spell ({})", current.name, spell(current)),
+            format!("current_party_since = {}: start of the open {} spell", date(current.start_date), current.name),
+        ],
+        None => vec![
+            format!("current_party = {:?}: no open party spells out of {}, using the Party element", member.party, member.parties.len()),
+            "current_party_since = unknown: no open party spells".to_string(),
+        ]
+    }
+}
+
+fn explain_whip(member: &Member, config: &Config) -> String {
+    let is_independent = |p: &Party| config.independent_parties.iter().any(|i| i.eq_ignore_ascii_case(&p.name));
+    // Spells are newest first, so the later spell of each pair is w[0]
+    match member.parties.windows(2).find(|w| is_independent(&w[0]) && !is_independent(&w[1])) {
+        Some(w) => format!("had_whip_removed = true: moved from {} to {} on {}", w[1].name, w[0].name, date(w[0].start_date)),
+        None => format!("had_whip_removed = false: no move from a party to one of {}", config.independent_parties.join(", "))
+    }
+}
+
+fn explain_tenure(member: &Member, now: DateTime<Utc>) -> String {
+    let days = member.total_tenure_at(now).num_days();
+    if !member.seats.is_empty() {
+        let seats = member.seats.iter()
+            .map(|s| format!("{} from {} to {}", s.name, date(s.start_date), s.end_date.map_or("present".to_string(), date)))
+            .collect::<Vec<_>>();
+        return format!("tenure_days = {}: {} seat spells without counting overlaps ({})", days, seats.len(), seats.join("; "));
+    }
+    match member.house_start_date {
+        Some(start) => format!("tenure_days = {}: House of {} from {} to {}", days, member.house, date(start),
+                               member.house_end_date.map_or("present".to_string(), date)),
+        None => format!("tenure_days = {}: no seat spells or HouseStartDate", days)
+    }
+}
+
+pub fn explain(member: &Member, config: &Config) -> Vec<String> {
+    explain_at(member, config, Utc::now())
+}
+
+pub fn explain_at(member: &Member, config: &Config, now: DateTime<Utc>) -> Vec<String> {
+    let mut lines = explain_current_party(member);
+    lines.push(explain_whip(member, config));
+    lines.push(explain_tenure(member, now));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{parse_addresses_xml, parse_date};
+    use crate::test_support::document;
+
+    #[test]
+    fn a_party_switch_is_explained() {
+        let data = document(&[(4005, "<Parties>\
+            <Party><Name>Labour</Name><StartDate>2010-05-06T00:00:00</StartDate><EndDate>2019-03-01T00:00:00</EndDate></Party>\
+            <Party><Name>Independent</Name><StartDate>2019-03-01T00:00:00</StartDate><EndDate/></Party>\
+            </Parties><Constituencies><Constituency><Name>Wansbeck</Name><StartDate>2010-05-06T00:00:00</StartDate>\
+            </Constituency></Constituencies>")]);
+        let config = Config::default();
+        let members = parse_addresses_xml(&data, &config).unwrap();
+
+        assert_eq!(explain_at(&members.members[0], &config, parse_date("2020-05-06").unwrap()), [
+            "current_party = \"Independent\": the only open party spell (Independent from 2019-03-01 to present)",
+            "current_party_since = 2019-03-01: start of the open Independent spell",
+            "had_whip_removed = true: moved from Labour to Independent on 2019-03-01",
+            "tenure_days = 3653: 1 seat spells without counting overlaps (Wansbeck from 2010-05-06 to present)",
+        ]);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::config::Config;
+use crate::members::Members;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use xml::escape::{escape_str_attribute, escape_str_pcdata};
+
+const KEYS: &[(&str, &str, &str)] = &[
+    ("name", "node", "name"),
+    ("kind", "node", "kind"),
+    ("house", "node", "house"),
+    ("constituency", "node", "constituency"),
+    ("twitter", "node", "twitter"),
+    ("colour", "node", "colour"),
+    ("start_date", "edge", "start_date"),
+    ("end_date", "edge", "end_date"),
+];
+
+fn data(out: &mut String, key: &str, value: &str) {
+    out.push_str(&format!("      <data key=\"{}\">{}</data>\n", key, escape_str_pcdata(value)));
+}
+
+pub fn to_graphml(members: &Members, config: &Config) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+    for (id, target, name) in KEYS {
+        out.push_str(&format!("  <key id=\"{}\" for=\"{}\" attr.name=\"{}\" attr.type=\"string\"/>\n", id, target, name));
+    }
+    out.push_str("  <graph id=\"members\" edgedefault=\"directed\">\n");
+
+    let mut party_ids: HashMap<&str, String> = HashMap::new();
+    for party in members.members.iter().flat_map(|m| m.parties.iter()) {
+        if party_ids.contains_key(party.name.as_str()) {
+            continue;
+        }
+
+        let id = format!("p{}", party_ids.len());
+        out.push_str(&format!("    <node id=\"{}\">\n", id));
+        data(&mut out, "name", &party.name);
+        data(&mut out, "kind", "party");
+        if let Some(colour) = config.party_colours.get(&party.name) {
+            data(&mut out, "colour", colour);
+        }
+        out.push_str("    </node>\n");
+        party_ids.insert(&party.name, id);
+    }
+
+    for member in &members.members {
+        out.push_str(&format!("    <node id=\"m{}\">\n", member.id));
+        data(&mut out, "name", &member.name);
+        data(&mut out, "kind", "member");
+        data(&mut out, "house", &member.house.to_string());
+        data(&mut out, "constituency", &member.constituency);
+        if let Some(handle) = member.twitter_handle() {
+            data(&mut out, "twitter", &handle);
+        }
+        out.push_str("    </node>\n");
+    }
+
+    for member in &members.members {
+        for (i, party) in member.parties.iter().enumerate() {
+            out.push_str(&format!("    <edge id=\"m{}e{}\" source=\"m{}\" target=\"{}\">\n", member.id, i, member.id,
+                                  escape_str_attribute(&party_ids[party.name.as_str()])));
+            data(&mut out, "start_date", &party.start_date.to_rfc3339());
+            if let Some(end_date) = party.end_date {
+                data(&mut out, "end_date", &end_date.to_rfc3339());
+            }
+            out.push_str("    </edge>\n");
+        }
+    }
+
+    out.push_str("  </graph>\n");
+    out.push_str("</graphml>\n");
+    out
+}
+
+pub fn write_graphml(path: &Path, members: &Members, config: &Config) -> io::Result<()> {
+    fs::write(path, to_graphml(members, config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse_addresses_xml;
+    use crate::test_support::document;
+    use xml::reader::{EventReader, XmlEvent};
+
+    #[test]
+    fn graph_has_member_and_party_nodes_joined_by_memberships() {
+        let data = document(&[
+            (172, "<MemberFrom>Hackney North &amp; Stoke Newington</MemberFrom><Parties>\
+                <Party><Name>Labour</Name><StartDate>1987-06-11T00:00:00</StartDate></Party></Parties>"),
+            (4520, "<MemberFrom>Bexhill &amp; Battle</MemberFrom><Parties>\
+                <Party><Name>Independent</Name><StartDate>2019-09-03T00:00:00</StartDate></Party>\
+                <Party><Name>Conservative</Name><StartDate>2015-05-07T00:00:00</StartDate><EndDate>2019-09-03T00:00:00</EndDate></Party>\
+                </Parties>"),
+        ]);
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+        let graph = to_graphml(&members, &Config::default());
+
+        let mut nodes = vec![];
+        let mut edges = vec![];
+        let mut text = vec![];
+        for event in EventReader::from_str(&graph) {
+            match event.unwrap() {
+                XmlEvent::StartElement { name, attributes, .. } => {
+                    let attr = |n: &str| attributes.iter().find(|a| a.name.local_name == n).map(|a| a.value.clone()).unwrap();
+                    match name.local_name.as_str() {
+                        "node" => nodes.push(attr("id")),
+                        "edge" => edges.push((attr("source"), attr("target"))),
+                        _ => {}
+                    }
+                }
+                XmlEvent::Characters(s) => text.push(s),
+                _ => {}
+            }
+        }
+
+        assert_eq!(nodes, ["p0", "p1", "p2", "m172", "m4520"]);
+        assert_eq!(edges, [("m172".to_string(), "p0".to_string()), ("m4520".to_string(), "p1".to_string()),
+                           ("m4520".to_string(), "p2".to_string())]);
+        assert!(text.contains(&"Hackney North & Stoke Newington".to_string()));
+        assert!(text.contains(&"#DC241F".to_string()));
+    }
+}
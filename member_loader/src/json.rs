@@ -0,0 +1,179 @@
+use crate::members::Member;
+use schemars::schema::RootSchema;
+use serde::Serialize;
+use std::io::{self, IsTerminal, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonStyle {
+    #[default]
+    Pretty,
+    Compact,
+}
+
+impl JsonStyle {
+    pub fn detect() -> Self {
+        if io::stdout().is_terminal() {
+            JsonStyle::Pretty
+        } else {
+            JsonStyle::Compact
+        }
+    }
+
+    pub fn to_string<T: Serialize + ?Sized>(self, value: &T) -> serde_json::Result<String> {
+        match self {
+            JsonStyle::Pretty => serde_json::to_string_pretty(value),
+            JsonStyle::Compact => serde_json::to_string(value),
+        }
+    }
+
+    pub fn to_writer<W: Write, T: Serialize + ?Sized>(self, writer: W, value: &T) -> serde_json::Result<()> {
+        match self {
+            JsonStyle::Pretty => serde_json::to_writer_pretty(writer, value),
+            JsonStyle::Compact => serde_json::to_writer(writer, value),
+        }
+    }
+}
+
+pub fn export_schema() -> RootSchema {
+    schemars::schema_for!(Vec<Member>)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::loader::{Input, Loader};
+    use crate::parse::parse_addresses_xml;
+    use crate::test_support::{commons, TempDir};
+    use serde_json::Value;
+
+    #[test]
+    fn styles_differ_only_in_whitespace() {
+        let members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+
+        let pretty = JsonStyle::Pretty.to_string(&members.members).unwrap();
+        let compact = JsonStyle::Compact.to_string(&members.members).unwrap();
+        assert!(pretty.contains("\n  "));
+        assert!(!compact.contains('\n'));
+        assert!(compact.len() < pretty.len());
+
+        let mut written = vec![];
+        JsonStyle::Compact.to_writer(&mut written, &members.members).unwrap();
+        assert_eq!(written, compact.as_bytes());
+
+        let pretty: serde_json::Value = serde_json::from_str(&pretty).unwrap();
+        let compact: serde_json::Value = serde_json::from_str(&compact).unwrap();
+        assert_eq!(pretty, compact);
+    }
+
+    /// Checks the parts of JSON Schema that schemars emits: refs, combinators, types, enums,
+    /// required fields and nested properties/items.
+    fn validate(value: &Value, schema: &Value, root: &Value, at: &str, errors: &mut Vec<String>) {
+        if let Some(reference) = schema["$ref"].as_str() {
+            let name = reference.trim_start_matches("#/definitions/");
+            return validate(value, &root["definitions"][name], root, at, errors);
+        }
+        if let Some(all) = schema["allOf"].as_array() {
+            for s in all {
+                validate(value, s, root, at, errors);
+            }
+        }
+        for key in &["anyOf", "oneOf"] {
+            if let Some(any) = schema[*key].as_array() {
+                let matches = any.iter().filter(|s| {
+                    let mut e = vec![];
+                    validate(value, s, root, at, &mut e);
+                    e.is_empty()
+                }).count();
+                if matches == 0 {
+                    errors.push(format!("{}: matches none of {}", at, key));
+                }
+            }
+        }
+        if let Some(options) = schema["enum"].as_array() {
+            if !options.contains(value) {
+                errors.push(format!("{}: {} is not one of {:?}", at, value, options));
+            }
+        }
+
+        let types: Vec<&str> = match &schema["type"] {
+            Value::String(t) => vec![t.as_str()],
+            Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+            _ => vec![]
+        };
+        let is = |t: &str| match t {
+            "null" => value.is_null(),
+            "boolean" => value.is_boolean(),
+            "integer" => value.is_i64() || value.is_u64(),
+            "number" => value.is_number(),
+            "string" => value.is_string(),
+            "array" => value.is_array(),
+            "object" => value.is_object(),
+            _ => true
+        };
+        if !types.is_empty() && !types.iter().any(|t| is(t)) {
+            errors.push(format!("{}: {} is not {}", at, value, types.join(" or ")));
+            return;
+        }
+        if let Some(minimum) = schema["minimum"].as_f64() {
+            if value.as_f64().is_some_and(|v| v < minimum) {
+                errors.push(format!("{}: {} is below {}", at, value, minimum));
+            }
+        }
+
+        if let Value::Object(fields) = value {
+            for required in schema["required"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+                if !fields.contains_key(required) {
+                    errors.push(format!("{}: missing {}", at, required));
+                }
+            }
+            for (name, field) in fields {
+                let at = format!("{}.{}", at, name);
+                match (&schema["properties"][name], &schema["additionalProperties"]) {
+                    (Value::Null, Value::Bool(false)) => errors.push(format!("{}: not in the schema", at)),
+                    (Value::Null, Value::Object(_)) => validate(field, &schema["additionalProperties"], root, &at, errors),
+                    (Value::Null, _) => {}
+                    (property, _) => validate(field, property, root, &at, errors)
+                }
+            }
+        }
+        if let (Value::Array(items), Some(item_schema)) = (value, schema.get("items")) {
+            for (i, item) in items.iter().enumerate() {
+                validate(item, item_schema, root, &format!("{}[{}]", at, i), errors);
+            }
+        }
+    }
+
+    #[test]
+    fn a_fixture_export_validates_against_the_schema() {
+        let dir = TempDir::new("schema");
+        let input = dir.path().join("commons.xml");
+        let output = dir.path().join("members.json");
+        std::fs::write(&input, commons()).unwrap();
+
+        Loader::new()
+            .input(Input::File(input, None))
+            .min_members(Some(0))
+            .json(&output)
+            .build()
+            .unwrap()
+            .run()
+            .unwrap();
+        let export: Value = serde_json::from_str(&std::fs::read_to_string(&output).unwrap()).unwrap();
+        assert_eq!(export.as_array().map(Vec::len), Some(7));
+
+        let schema = serde_json::to_value(export_schema()).unwrap();
+        let mut errors = vec![];
+        validate(&export, &schema, &schema, "$", &mut errors);
+        assert!(errors.is_empty(), "{:#?}", errors);
+
+        // The validator has to be able to fail for the check above to mean anything.
+        let mut broken = export.clone();
+        broken[0]["id"] = Value::from("172");
+        broken[1].as_object_mut().unwrap().remove("name");
+        let mut errors = vec![];
+        validate(&broken, &schema, &schema, "$", &mut errors);
+        assert_eq!(errors, ["$[0].id: \"172\" is not integer", "$[1]: missing name"]);
+    }
+}
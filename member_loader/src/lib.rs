@@ -0,0 +1,32 @@
+#[macro_use]
+pub mod log;
+
+pub mod api;
+pub mod archive;
+pub mod cache;
+pub mod checkpoint;
+pub mod commit;
+pub mod config;
+pub mod csv;
+pub mod diff;
+pub mod encoding;
+pub mod explain;
+pub mod graphml;
+pub mod json;
+pub mod loader;
+pub mod manifest;
+pub mod markdown;
+pub mod members;
+pub mod parse;
+pub mod report;
+pub mod sink;
+pub mod snapshot;
+pub mod spill;
+pub mod sqlite;
+pub mod twitter;
+pub mod validate;
+pub mod verify;
+pub mod watermark;
+
+#[cfg(test)]
+mod test_support;
@@ -0,0 +1,1360 @@
+use crate::api::{self, ApiClient, ApiError, MemberQuery, TlsOptions};
+use crate::archive;
+use crate::cache::MemberCache;
+use crate::commit::{CommitError, CommitOptions, ConflictStrategy, Provenance};
+use crate::config::Config;
+use crate::diff::{self, ExportError};
+use crate::encoding;
+use crate::explain;
+use crate::json::JsonStyle;
+use crate::members::{AdditionalData, House, Members, SortOrder};
+use crate::parse::{self, InputFormat, ParseError};
+use crate::report::{Report, SourceReport};
+use crate::sink::{Batch, CsvSink, DgraphSink, GraphmlSink, JsonSink, LiveJsonSink, MarkdownSink, PartySplitSink, Sink, SqliteSink};
+use crate::spill;
+use crate::validate::{self, ValidationError};
+use crate::verify::{self, CrossValidation, Discrepancy};
+use crate::watermark::{self, Watermark};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum LoadError {
+    #[error("unable to set up API client")]
+    Client(#[source] ApiError),
+    #[error("unable to fetch {house} members")]
+    Api {
+        house: House,
+        #[source]
+        source: ApiError,
+    },
+    #[error("unable to archive response to {}", .dir.display())]
+    Archive {
+        dir: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("unable to replay archive {}", .dir.display())]
+    Replay {
+        dir: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("unable to read {}", .path.display())]
+    Input {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("unable to parse {}", .path.display())]
+    InputParse {
+        path: PathBuf,
+        #[source]
+        source: ParseError,
+    },
+    #[error("unable to parse {house} members")]
+    Parse {
+        house: House,
+        #[source]
+        source: ParseError,
+    },
+    #[error("unable to read previous snapshot")]
+    PreviousSnapshot(#[source] ExportError),
+    #[error(transparent)]
+    Validation(#[from] ValidationError),
+    #[error("unable to commit {house} members")]
+    Commit {
+        house: House,
+        #[source]
+        source: CommitError,
+    },
+    #[error("unable to commit {house} members, spilled them to {}", .path.display())]
+    Spilled {
+        house: House,
+        path: PathBuf,
+        #[source]
+        source: CommitError,
+    },
+    #[error("{} committed {house} members could not be read back", .discrepancies.len())]
+    WriteVerification {
+        house: House,
+        discrepancies: Vec<Discrepancy>,
+    },
+    #[error("unable to save {house} watermark")]
+    Watermark {
+        house: House,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("unable to write {}", .path.display())]
+    Graphml {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("unable to write {}", .path.display())]
+    Sqlite {
+        path: PathBuf,
+        #[source]
+        source: rusqlite::Error,
+    },
+    #[error("unable to write {}", .path.display())]
+    Json {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("unable to write {}", .path.display())]
+    Csv {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("unable to write {}", .path.display())]
+    Markdown {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("unable to create {}", .dir.display())]
+    Split {
+        dir: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("{failed} of {total} outputs failed")]
+    Outputs {
+        failed: usize,
+        total: usize,
+        #[source]
+        source: Box<LoadError>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    Api,
+    Replay(PathBuf),
+    File(PathBuf, Option<InputFormat>),
+    Files(String, Option<InputFormat>),
+    Spill(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Output {
+    Dgraph,
+    Sqlite,
+    Graphml,
+    Json,
+    Csv,
+    Markdown,
+    LiveJson,
+}
+
+impl Output {
+    pub fn default_target(self) -> &'static str {
+        match self {
+            Output::Dgraph => "localhost:9080",
+            Output::Sqlite => "members.db",
+            Output::Graphml => "members.graphml",
+            Output::Json => "members.json",
+            Output::Csv => "members.csv",
+            Output::Markdown => "members.md",
+            Output::LiveJson => "members.live.json",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        let target = self.default_target();
+        target.split_once('.').map_or(target, |(_, ext)| ext)
+    }
+}
+
+impl FromStr for Output {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dgraph" => Ok(Output::Dgraph),
+            "sqlite" => Ok(Output::Sqlite),
+            "graphml" => Ok(Output::Graphml),
+            "json" => Ok(Output::Json),
+            "csv" => Ok(Output::Csv),
+            "markdown" => Ok(Output::Markdown),
+            "live-json" => Ok(Output::LiveJson),
+            _ => Err(())
+        }
+    }
+}
+
+pub struct LoadedMembers {
+    pub house: House,
+    pub source: String,
+    pub fetched_at: DateTime<Utc>,
+    pub members: Members,
+    pub parse_time: Duration,
+}
+
+#[derive(Debug)]
+pub struct LoadResult {
+    pub started_at: DateTime<Utc>,
+    pub elapsed: Duration,
+    pub sources: Vec<SourceReport>,
+}
+
+impl LoadResult {
+    pub fn report(self) -> Report {
+        let mut report = Report::new(self.started_at);
+        report.elapsed_ms = self.elapsed.as_millis() as u64;
+        report.sources = self.sources;
+        report
+    }
+}
+
+pub const DEFAULT_MAX_PARTIES_PER_MEMBER: usize = 100;
+
+pub type Hook = Box<dyn Fn(&mut Members)>;
+
+pub struct LoaderBuilder {
+    house: House,
+    api_base: String,
+    tls: TlsOptions,
+    headers: Vec<(String, String)>,
+    max_body_size: usize,
+    request_delay: Duration,
+    page_size: Option<usize>,
+    query: MemberQuery,
+    incremental: bool,
+    as_of: Option<DateTime<Utc>>,
+    enrich_roles: bool,
+    archive_dir: Option<PathBuf>,
+    parse_cache_dir: Option<PathBuf>,
+    input: Input,
+    config: Config,
+    active_since: Option<DateTime<Utc>>,
+    keep_house: Option<House>,
+    status: Option<String>,
+    sworn_only: bool,
+    explain: bool,
+    party_history_years: Option<u32>,
+    max_parties_per_member: usize,
+    changes_since: Option<PathBuf>,
+    hooks: Vec<Hook>,
+    include_ids: HashSet<u32>,
+    exclude_ids: HashSet<u32>,
+    limit: Option<usize>,
+    resume_from: Option<u32>,
+    strict_schema: bool,
+    min_twitter_coverage: Option<f64>,
+    min_members: Option<usize>,
+    previous_fill_rates: Option<BTreeMap<String, f64>>,
+    max_fill_drop: f64,
+    fail_on_fill_drop: bool,
+    dgraph: Option<String>,
+    sinks: Vec<Box<dyn Sink>>,
+    commit: CommitOptions,
+    spill_dir: Option<PathBuf>,
+    verify_writes: bool,
+    threads: usize,
+    json_style: JsonStyle,
+    flatten_parties: bool,
+    redact_contact: bool,
+    sort: SortOrder,
+    parse_only: bool,
+    no_dgraph: bool,
+}
+
+impl LoaderBuilder {
+    pub fn house(mut self, house: House) -> Self {
+        self.house = house;
+        self
+    }
+
+    pub fn api_base(mut self, api_base: &str) -> Self {
+        self.api_base = api_base.to_string();
+        self
+    }
+
+    pub fn require_https(mut self, require_https: bool) -> Self {
+        self.tls.require_https = require_https;
+        self
+    }
+
+    pub fn ca_cert(mut self, path: Option<PathBuf>) -> Self {
+        self.tls.ca_cert = path;
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn api_key(self, key: Option<&str>) -> Self {
+        match key {
+            Some(key) => self.header(api::API_KEY_HEADER, key),
+            None => self
+        }
+    }
+
+    pub fn max_body_size(mut self, limit: usize) -> Self {
+        self.max_body_size = limit;
+        self
+    }
+
+    pub fn request_delay(mut self, delay: Duration) -> Self {
+        self.request_delay = delay;
+        self
+    }
+
+    pub fn page_size(mut self, page_size: Option<usize>) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    pub fn query(mut self, query: MemberQuery) -> Self {
+        self.query = query;
+        self
+    }
+
+    pub fn incremental(mut self, incremental: bool) -> Self {
+        self.incremental = incremental;
+        self
+    }
+
+    pub fn archive_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.archive_dir = dir;
+        self
+    }
+
+    pub fn parse_cache_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.parse_cache_dir = dir;
+        self
+    }
+
+    pub fn input(mut self, input: Input) -> Self {
+        self.input = input;
+        self
+    }
+
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn active_since(mut self, date: Option<DateTime<Utc>>) -> Self {
+        self.active_since = date;
+        self
+    }
+
+    pub fn keep_house(mut self, house: Option<House>) -> Self {
+        self.keep_house = house;
+        self
+    }
+
+    pub fn status(mut self, status: Option<String>) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn as_of(mut self, as_of: Option<DateTime<Utc>>) -> Self {
+        self.as_of = as_of;
+        self
+    }
+
+    pub fn enrich_roles(mut self, enrich_roles: bool) -> Self {
+        self.enrich_roles = enrich_roles;
+        self
+    }
+
+    pub fn sworn_only(mut self, sworn_only: bool) -> Self {
+        self.sworn_only = sworn_only;
+        self
+    }
+
+    pub fn explain(mut self, explain: bool) -> Self {
+        self.explain = explain;
+        self
+    }
+
+    pub fn party_history_years(mut self, years: Option<u32>) -> Self {
+        self.party_history_years = years;
+        self
+    }
+
+    pub fn max_parties_per_member(mut self, max: usize) -> Self {
+        self.max_parties_per_member = max;
+        self
+    }
+
+    pub fn changes_since(mut self, snapshot: Option<PathBuf>) -> Self {
+        self.changes_since = snapshot;
+        self
+    }
+
+    pub fn hook(mut self, hook: impl Fn(&mut Members) + 'static) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    pub fn include_ids(mut self, ids: impl IntoIterator<Item=u32>) -> Self {
+        self.include_ids.extend(ids);
+        self
+    }
+
+    pub fn exclude_ids(mut self, ids: impl IntoIterator<Item=u32>) -> Self {
+        self.exclude_ids.extend(ids);
+        self
+    }
+
+    pub fn limit(mut self, limit: Option<usize>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn resume_from(mut self, id: Option<u32>) -> Self {
+        self.resume_from = id;
+        self
+    }
+
+    pub fn strict_schema(mut self, strict: bool) -> Self {
+        self.strict_schema = strict;
+        self
+    }
+
+    pub fn min_twitter_coverage(mut self, coverage: Option<f64>) -> Self {
+        self.min_twitter_coverage = coverage;
+        self
+    }
+
+    pub fn min_members(mut self, min_members: Option<usize>) -> Self {
+        self.min_members = min_members;
+        self
+    }
+
+    pub fn previous_fill_rates(mut self, rates: Option<BTreeMap<String, f64>>) -> Self {
+        self.previous_fill_rates = rates;
+        self
+    }
+
+    pub fn max_fill_drop(mut self, max_drop: f64, fail: bool) -> Self {
+        self.max_fill_drop = max_drop;
+        self.fail_on_fill_drop = fail;
+        self
+    }
+
+    pub fn dgraph(mut self, addr: &str) -> Self {
+        self.dgraph = Some(addr.to_string());
+        self
+    }
+
+    pub fn sqlite(self, path: &Path) -> Self {
+        self.sink(Box::new(SqliteSink { path: path.to_path_buf() }))
+    }
+
+    pub fn graphml(self, path: &Path) -> Self {
+        self.sink(Box::new(GraphmlSink { path: path.to_path_buf() }))
+    }
+
+    pub fn json(self, path: &Path) -> Self {
+        self.sink(Box::new(JsonSink { path: path.to_path_buf() }))
+    }
+
+    pub fn csv(self, path: &Path) -> Self {
+        self.sink(Box::new(CsvSink { path: path.to_path_buf() }))
+    }
+
+    pub fn markdown(self, path: &Path) -> Self {
+        self.sink(Box::new(MarkdownSink { path: path.to_path_buf() }))
+    }
+
+    pub fn split_by_party(self, dir: &Path, kind: Output) -> Self {
+        self.sink(Box::new(PartySplitSink { dir: dir.to_path_buf(), kind }))
+    }
+
+    pub fn live_json(self, path: &Path) -> Self {
+        self.sink(Box::new(LiveJsonSink { path: path.to_path_buf() }))
+    }
+
+    pub fn sink(mut self, sink: Box<dyn Sink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.commit.resume = resume;
+        self
+    }
+
+    pub fn only_changed(mut self, only_changed: bool) -> Self {
+        self.commit.only_changed = only_changed;
+        self
+    }
+
+    pub fn commit_delay(mut self, delay: Duration) -> Self {
+        self.commit.batch_delay = delay;
+        self
+    }
+
+    pub fn commit_retries(mut self, retries: u32) -> Self {
+        self.commit.max_retries = retries;
+        self
+    }
+
+    pub fn commit_concurrency(mut self, concurrency: usize) -> Self {
+        self.commit.concurrency = concurrency;
+        self
+    }
+
+    pub fn on_conflict(mut self, conflict: ConflictStrategy) -> Self {
+        self.commit.conflict = conflict;
+        self
+    }
+
+    pub fn spill_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.spill_dir = dir;
+        self
+    }
+
+    pub fn verify_writes(mut self, verify: bool) -> Self {
+        self.verify_writes = verify;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    pub fn json_style(mut self, style: JsonStyle) -> Self {
+        self.json_style = style;
+        self
+    }
+
+    pub fn flatten_parties(mut self, flatten: bool) -> Self {
+        self.flatten_parties = flatten;
+        self
+    }
+
+    pub fn redact_contact(mut self, redact: bool) -> Self {
+        self.redact_contact = redact;
+        self
+    }
+
+    pub fn sort(mut self, order: SortOrder) -> Self {
+        self.sort = order;
+        self
+    }
+
+    pub fn parse_only(mut self, parse_only: bool) -> Self {
+        self.parse_only = parse_only;
+        self
+    }
+
+    pub fn no_dgraph(mut self, no_dgraph: bool) -> Self {
+        self.no_dgraph = no_dgraph;
+        self
+    }
+
+    pub fn build(mut self) -> Result<Loader, LoadError> {
+        let client = match self.input {
+            Input::Api => {
+                api::check_url(&self.api_base, &self.tls).map_err(LoadError::Client)?;
+                Some(ApiClient::new(self.request_delay, self.tls.clone(), &self.headers).map_err(LoadError::Client)?
+                    .max_body_size(self.max_body_size))
+            }
+            _ => None
+        };
+
+        let mut sinks: Vec<Box<dyn Sink>> = vec![];
+        if self.parse_only {
+            self.sinks.clear();
+            self.archive_dir = None;
+            self.parse_cache_dir = None;
+        } else if self.dgraph.is_some() || (self.sinks.is_empty() && !self.no_dgraph) {
+            sinks.push(Box::new(DgraphSink {
+                addr: self.dgraph.take().unwrap_or_else(|| Output::Dgraph.default_target().to_string()),
+                options: CommitOptions { fingerprint_fields: self.config.fingerprint_fields.clone(), ..self.commit.clone() },
+                spill_dir: self.spill_dir.clone(),
+                verify_writes: self.verify_writes,
+            }));
+        }
+        sinks.append(&mut self.sinks);
+
+        Ok(Loader {
+            settings: self,
+            client,
+            sinks,
+        })
+    }
+}
+
+pub struct Loader {
+    settings: LoaderBuilder,
+    client: Option<ApiClient>,
+    sinks: Vec<Box<dyn Sink>>,
+}
+
+fn report_party_conflicts(members: &Members) {
+    for member in &members.members {
+        for (a, b) in member.same_day_switches() {
+            log!("Warning: {} ({}) switched between {} and {} on {}", member.name, member.id, a.name, b.name,
+                     a.start_date.format("%Y-%m-%d"));
+        }
+        let open = member.open_parties();
+        if open.len() > 1 {
+            log!("Warning: {} ({}) has {} open party spells ({}), using {}", member.name, member.id, open.len(),
+                     open.iter().map(|p| p.name.as_str()).collect::<Vec<_>>().join(", "), member.current_party());
+        }
+    }
+}
+
+fn report_missing_twitter(members: &Members) {
+    let missing = members.without_twitter();
+
+    if !missing.is_empty() {
+        log!("Members without a valid Twitter handle:");
+        for member in &missing {
+            log!("  {} ({})", member.name, member.constituency);
+        }
+    }
+
+    for t in members.twitter_names() {
+        log!("Warning: Twitter address {:?} for member {} ({}) is a name, not a handle", t.value, t.id, t.name);
+    }
+
+    for shared in members.shared_twitter_handles() {
+        log!("Warning: Twitter handle {:?} is shared by {}", shared.handle,
+                 shared.members.iter().map(|t| format!("{} ({})", t.name, t.id)).collect::<Vec<_>>().join(", "));
+    }
+
+    log!("Twitter coverage: {:.1}% ({} of {} members)", members.twitter_coverage(),
+             members.members.len() - missing.len(), members.members.len());
+}
+
+impl Loader {
+    pub fn config(&self) -> &Config {
+        &self.settings.config
+    }
+
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new() -> LoaderBuilder {
+        LoaderBuilder {
+            house: House::Commons,
+            api_base: api::MEMBER_API_BASE.to_string(),
+            tls: TlsOptions::default(),
+            headers: vec![],
+            max_body_size: api::DEFAULT_MAX_BODY_SIZE,
+            request_delay: Duration::from_secs(1),
+            page_size: None,
+            query: MemberQuery::default(),
+            incremental: false,
+            as_of: None,
+            enrich_roles: false,
+            archive_dir: None,
+            parse_cache_dir: None,
+            input: Input::Api,
+            config: Config::default(),
+            active_since: None,
+            keep_house: None,
+            status: None,
+            sworn_only: false,
+            explain: false,
+            party_history_years: None,
+            max_parties_per_member: DEFAULT_MAX_PARTIES_PER_MEMBER,
+            changes_since: None,
+            hooks: vec![],
+            include_ids: HashSet::new(),
+            exclude_ids: HashSet::new(),
+            limit: None,
+            resume_from: None,
+            strict_schema: false,
+            min_twitter_coverage: None,
+            min_members: None,
+            previous_fill_rates: None,
+            max_fill_drop: 10.0,
+            fail_on_fill_drop: false,
+            dgraph: None,
+            sinks: vec![],
+            commit: CommitOptions::default(),
+            spill_dir: None,
+            verify_writes: false,
+            threads: 1,
+            json_style: JsonStyle::default(),
+            flatten_parties: false,
+            redact_contact: false,
+            sort: SortOrder::default(),
+            parse_only: false,
+            no_dgraph: false,
+        }
+    }
+
+    pub fn load(&self) -> Result<Vec<LoadedMembers>, LoadError> {
+        match &self.settings.input {
+            Input::Api => Ok(vec![self.fetch_members(InputFormat::Xml)?]),
+            Input::Replay(dir) => self.replay_members(dir),
+            Input::File(path, format) => Ok(vec![self.read_members(path, *format)?]),
+            Input::Files(pattern, format) => self.read_snapshots(pattern, *format),
+            Input::Spill(path) => Ok(vec![self.read_spill(path)?]),
+        }
+    }
+
+    pub fn cross_validate(&self) -> Result<CrossValidation, LoadError> {
+        let xml = self.fetch_members(InputFormat::Xml)?;
+        let json = self.fetch_members(InputFormat::Json)?;
+
+        Ok(verify::cross_validate(&xml.members, &json.members))
+    }
+
+    fn fetch_members(&self, format: InputFormat) -> Result<LoadedMembers, LoadError> {
+        let s = &self.settings;
+        let house = s.house;
+        let client = match &self.client {
+            Some(c) => c,
+            None => unreachable!()
+        };
+
+        log!("Getting House of {} data...", house);
+        let mut additional_data = vec![AdditionalData::Addresses, AdditionalData::Parties, AdditionalData::Constituencies, AdditionalData::Interests, AdditionalData::GovernmentPosts, AdditionalData::PreferredNames];
+        if s.enrich_roles {
+            additional_data.retain(|d| *d != AdditionalData::GovernmentPosts);
+        }
+        let addresses_url = match s.as_of {
+            Some(as_of) => {
+                log!("Fetching membership as of {}", as_of.format("%Y-%m-%d"));
+                api::get_api_url_as_of(&s.api_base, house, as_of, &s.query, additional_data)
+            }
+            None => api::get_api_url(&s.api_base, house, &s.query, additional_data)
+        };
+
+        let since = if s.incremental { Watermark::for_house(&house).load() } else { None };
+        match since {
+            Some(since) => log!("Only fetching members modified since {}", since),
+            None if s.incremental => log!("No watermark found, fetching all members"),
+            None => {}
+        }
+
+        let fetched_at = Utc::now();
+        let mut parse_time = Duration::default();
+        let mut members = api::fetch_pages(&addresses_url, s.page_size, |page_url| -> Result<Members, LoadError> {
+            let page_url = match since {
+                Some(since) => api::get_modified_since_url(page_url, since),
+                None => page_url.to_string()
+            };
+            let addresses_body = match format {
+                InputFormat::Xml => client.get(&page_url),
+                InputFormat::Json => client.get_json(&page_url),
+            }.map_err(|e| LoadError::Api { house, source: e })?;
+
+            if let (Some(dir), InputFormat::Xml) = (&s.archive_dir, format) {
+                let path = archive::archive_response(dir, &house, &addresses_body)
+                    .map_err(|e| LoadError::Archive { dir: dir.clone(), source: e })?;
+                log!("Archived response to {}", path.display());
+            }
+
+            log!("Parsing House of {} data...", house);
+            let start = Instant::now();
+            let page_members = self.parse_cached(&page_url, &addresses_body, || match format {
+                InputFormat::Xml => parse::parse_addresses_parallel(&addresses_body, &s.config, s.threads).map(|(m, _)| m),
+                InputFormat::Json => parse::parse_addresses(&addresses_body, Some(format), &s.config),
+            }).map_err(|e| LoadError::Parse { house, source: e })?;
+            parse_time += start.elapsed();
+
+            Ok(page_members)
+        })?;
+
+        if s.enrich_roles && format == InputFormat::Xml {
+            let roles = self.fetch_roles(client)?;
+            let merged = members.merge_roles(&roles);
+            log!("Merged government and opposition roles onto {} of {} members", merged, members.members.len());
+        }
+
+        Ok(LoadedMembers {
+            house,
+            source: addresses_url,
+            fetched_at,
+            members,
+            parse_time,
+        })
+    }
+
+    fn fetch_roles(&self, client: &ApiClient) -> Result<Members, LoadError> {
+        let s = &self.settings;
+        let house = s.house;
+
+        log!("Getting House of {} roles...", house);
+        let additional_data = vec![AdditionalData::GovernmentPosts, AdditionalData::OppositionPosts];
+        let roles_url = match s.as_of {
+            Some(as_of) => api::get_api_url_as_of(&s.api_base, house, as_of, &s.query, additional_data),
+            None => api::get_api_url(&s.api_base, house, &s.query, additional_data)
+        };
+        api::fetch_pages(&roles_url, s.page_size, |page_url| {
+            let body = client.get(page_url).map_err(|e| LoadError::Api { house, source: e })?;
+            parse::parse_addresses_xml(&body, &s.config).map_err(|e| LoadError::Parse { house, source: e })
+        })
+    }
+
+    fn parse_cached<F>(&self, source: &str, body: &str, parse: F) -> Result<Members, ParseError>
+        where F: FnOnce() -> Result<Members, ParseError> {
+        let s = &self.settings;
+        let cache = match &s.parse_cache_dir {
+            Some(dir) => MemberCache::new(dir, &s.config),
+            None => return parse()
+        };
+
+        if let Some(members) = cache.get(source, body) {
+            log!("Using cached parse of {}", source);
+            return Ok(members);
+        }
+        let members = parse()?;
+        if let Err(e) = cache.put(source, body, &members) {
+            log!("Unable to cache parsed members: {}", e);
+        }
+        Ok(members)
+    }
+
+    fn replay_members(&self, dir: &Path) -> Result<Vec<LoadedMembers>, LoadError> {
+        let responses = archive::archived_responses(dir)
+            .map_err(|e| LoadError::Replay { dir: dir.to_path_buf(), source: e })?;
+
+        let mut replayed = vec![];
+        for response in responses {
+            log!("Replaying {}...", response.path.display());
+            let body = match archive::read_body(&response.path) {
+                Ok(b) => encoding::decode(&b, None),
+                Err(e) => {
+                    log!("Skipping {}: {}", response.path.display(), e);
+                    continue;
+                }
+            };
+
+            let start = Instant::now();
+            let source = response.path.display().to_string();
+            match self.parse_cached(&source, &body, || {
+                parse::parse_addresses_parallel(&body, &self.settings.config, self.settings.threads).map(|(m, _)| m)
+            }) {
+                Ok(members) => replayed.push(LoadedMembers {
+                    house: response.house,
+                    source,
+                    fetched_at: response.fetched_at().unwrap_or_else(Utc::now),
+                    members,
+                    parse_time: start.elapsed(),
+                }),
+                Err(e) => log!("Skipping {}: {}", response.path.display(), e)
+            }
+        }
+
+        Ok(replayed)
+    }
+
+    fn read_members(&self, path: &Path, format: Option<InputFormat>) -> Result<LoadedMembers, LoadError> {
+        log!("Reading {}...", path.display());
+        let fetched_at = Utc::now();
+        let body = archive::read_body(path).map_err(|e| LoadError::Input { path: path.to_path_buf(), source: e })?;
+        let body = encoding::decode(&body, None);
+
+        let start = Instant::now();
+        let source = path.display().to_string();
+        let members = self.parse_cached(&source, &body, || parse::parse_addresses(&body, format, &self.settings.config))
+            .map_err(|e| LoadError::InputParse { path: path.to_path_buf(), source: e })?;
+
+        Ok(LoadedMembers {
+            house: members.members.first().map_or(House::Unknown, |m| m.house),
+            source,
+            fetched_at,
+            members,
+            parse_time: start.elapsed(),
+        })
+    }
+
+    fn read_snapshots(&self, pattern: &str, format: Option<InputFormat>) -> Result<Vec<LoadedMembers>, LoadError> {
+        let paths = archive::matching_files(pattern)
+            .map_err(|e| LoadError::Input { path: PathBuf::from(pattern), source: e })?;
+        log!("Found {} snapshots matching {}", paths.len(), pattern);
+
+        let mut snapshots = vec![];
+        for path in paths {
+            let mut loaded = self.read_members(&path, format)?;
+            if let Some(date) = archive::snapshot_date(&path) {
+                loaded.fetched_at = date;
+            }
+            snapshots.push(loaded);
+        }
+        snapshots.sort_by_key(|l| l.fetched_at);
+
+        Ok(snapshots)
+    }
+
+    fn read_spill(&self, path: &Path) -> Result<LoadedMembers, LoadError> {
+        log!("Replaying spill {}...", path.display());
+        let start = Instant::now();
+        let spill = spill::read_spill(path).map_err(|e| LoadError::Input { path: path.to_path_buf(), source: e })?;
+
+        let mut members = Members::new();
+        members.members = spill.members;
+        Ok(LoadedMembers {
+            house: spill.house,
+            source: spill.source,
+            fetched_at: spill.fetched_at,
+            members,
+            parse_time: start.elapsed(),
+        })
+    }
+
+    pub fn process(&self, loaded: LoadedMembers) -> Result<SourceReport, LoadError> {
+        let s = &self.settings;
+        let LoadedMembers { house, source, fetched_at, mut members, parse_time } = loaded;
+
+        let min_members = match s.min_members {
+            Some(n) => n,
+            None if s.incremental => 0,
+            None => validate::default_min_members(house)
+        };
+        validate::check_member_count(&members, min_members)?;
+
+        if !s.include_ids.is_empty() || !s.exclude_ids.is_empty() {
+            let removed = members.retain_ids(&s.include_ids, &s.exclude_ids);
+            log!("Filtered out {} members by id, {} remaining", removed, members.members.len());
+        }
+        if let Some(date) = s.active_since {
+            let removed = members.retain_active_since(date);
+            log!("Filtered out {} members not active since {}, {} remaining", removed,
+                     date.format("%Y-%m-%d"), members.members.len());
+        }
+        if let Some(keep) = s.keep_house {
+            let removed = members.retain_house(keep);
+            log!("Filtered out {} members not in the House of {}, {} remaining", removed, keep, members.members.len());
+        }
+        if let Some(status) = &s.status {
+            let removed = members.retain_status(status);
+            log!("Filtered out {} members without status {}, {} remaining", removed, status, members.members.len());
+        }
+        if s.sworn_only {
+            let removed = members.retain_sworn();
+            log!("Filtered out {} members who have not taken the oath, {} remaining", removed, members.members.len());
+        }
+        if let Some(id) = s.resume_from {
+            match members.skip_until(id) {
+                Some(skipped) => log!("Resuming from member {}, skipped {} members", id, skipped),
+                None => log!("Warning: member {} not found, not skipping any members", id)
+            }
+        }
+        if let Some(path) = &s.changes_since {
+            let previous = diff::read_export(path).map_err(LoadError::PreviousSnapshot)?;
+            let changed = diff::changed_since(&previous, &members);
+            let removed = members.retain_changed(&changed);
+            log!("Filtered out {} members unchanged since {}, {} remaining", removed, path.display(), members.members.len());
+        }
+        if let Some(years) = s.party_history_years {
+            let cutoff = Utc::now() - chrono::Duration::days(365 * i64::from(years));
+            let removed = members.trim_party_history(cutoff);
+            log!("Dropped {} party spells that ended more than {} years ago", removed, years);
+        }
+        for (id, spells) in members.cap_party_history(s.max_parties_per_member) {
+            log!("Warning: member {} has {} party spells, keeping the latest {}", id, spells, s.max_parties_per_member);
+        }
+        if let Some(limit) = s.limit {
+            let total = members.members.len();
+            if members.truncate(limit) {
+                log!("Limiting to the first {} of {} members", limit, total);
+            }
+        }
+        for hook in &s.hooks {
+            hook(&mut members);
+        }
+        crate::debug!("{:#?}", members);
+
+        if let Err(e) = validate::check_schema(&members) {
+            log!("{}", e);
+            if s.strict_schema {
+                return Err(e.into());
+            }
+        }
+
+        if !members.skipped.is_empty() {
+            log!("Skipped {} malformed members:", members.skipped.len());
+            for skipped in &members.skipped {
+                log!("  {}: {}", skipped.id.map_or("without id".to_string(), |id| id.to_string()), skipped.reason);
+            }
+        }
+        if members.empty_social_addresses > 0 {
+            log!("Ignored {} empty Twitter/Facebook addresses", members.empty_social_addresses);
+        }
+        report_party_conflicts(&members);
+        report_missing_twitter(&members);
+        if s.explain {
+            for member in &members.members {
+                log!("{} ({}):", member.name, member.id);
+                for line in explain::explain(member, &s.config) {
+                    log!("  {}", line);
+                }
+            }
+        }
+        if let Some(required) = s.min_twitter_coverage {
+            validate::check_twitter_coverage(&members, required)?;
+        }
+
+        // Only the members actually written may move the watermark, or a filtered-out member's
+        // change would be skipped by every later incremental run.
+        let watermark = if s.incremental && !s.parse_only {
+            let w = Watermark::for_house(&house);
+            watermark::advance(w.load(), &members).map(|t| (w, t))
+        } else {
+            None
+        };
+
+        let mut report = SourceReport::new(house, &source, &members, parse_time);
+        if let Some(previous) = &s.previous_fill_rates {
+            let drops = validate::fill_rate_drops(previous, &report.fill_rates, s.max_fill_drop);
+            for drop in &drops {
+                log!("Warning: {} since the previous run", drop);
+            }
+            if s.fail_on_fill_drop && !drops.is_empty() {
+                return Err(ValidationError::FillRateDrops(drops).into());
+            }
+        }
+
+        members.sort(s.sort);
+        let provenance = Provenance { fetched_at, source_url: source };
+        let batch = Batch {
+            house,
+            members: &members,
+            provenance: &provenance,
+            config: &s.config,
+            json_style: s.json_style,
+            flatten_parties: s.flatten_parties,
+            redact_contact: s.redact_contact,
+        };
+
+        let start = Instant::now();
+        let mut errors = vec![];
+        for sink in &self.sinks {
+            if let Err(e) = sink.write(&batch, &mut report) {
+                log!("Unable to write to {}: {}", sink.name(), e);
+                errors.push(e);
+            }
+        }
+        report.output_ms = start.elapsed().as_millis() as u64;
+
+        if !errors.is_empty() {
+            let failed = errors.len();
+            let first = errors.remove(0);
+            if self.sinks.len() == 1 {
+                return Err(first);
+            }
+            return Err(LoadError::Outputs {
+                failed,
+                total: self.sinks.len(),
+                source: Box::new(first),
+            });
+        }
+
+        if let Some((w, t)) = watermark {
+            w.save(t).map_err(|e| LoadError::Watermark { house, source: e })?;
+            log!("Advanced {} watermark to {}", house, t);
+        }
+
+        Ok(report)
+    }
+
+    pub fn run(&self) -> Result<LoadResult, LoadError> {
+        let started_at = Utc::now();
+        let start = Instant::now();
+
+        let mut sources = vec![];
+        for l in self.load()? {
+            sources.push(self.process(l)?);
+        }
+
+        Ok(LoadResult {
+            started_at,
+            elapsed: start.elapsed(),
+            sources,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{document, MockResponse, MockServer, TempDir};
+    use std::sync::{Arc, Mutex};
+
+    struct MemorySink {
+        written: Arc<Mutex<Vec<u32>>>,
+        fail: bool,
+    }
+
+    impl Sink for MemorySink {
+        fn name(&self) -> String {
+            "memory".to_string()
+        }
+
+        fn write(&self, batch: &Batch, _report: &mut SourceReport) -> Result<(), LoadError> {
+            self.written.lock().unwrap().extend(batch.members.members.iter().map(|m| m.id));
+            if self.fail {
+                return Err(LoadError::Json { path: "memory".into(), source: std::io::Error::other("full") });
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn loader_fetches_and_writes_members() {
+        let server = MockServer::new(vec![MockResponse::ok(document(&[
+            (172, "<Addresses><Address><Type>Twitter</Type><Address1>https://twitter.com/HackneyAbbott</Address1></Address></Addresses>"),
+            (4212, ""),
+        ]))]);
+        let dir = TempDir::new("loader");
+        let db = dir.path().join("members.db");
+
+        let loader = Loader::new()
+            .api_base(&format!("{}/Members/Query/", server.url()))
+            .request_delay(Duration::from_millis(0))
+            .min_members(Some(0))
+            .sqlite(&db)
+            .build()
+            .unwrap();
+        let result = loader.run().unwrap();
+
+        assert_eq!(result.sources.len(), 1);
+        let source = &result.sources[0];
+        assert_eq!(source.house, "Commons");
+        assert_eq!(source.source, format!("{}/Members/Query/house=Commons|isEligible=true/Addresses|Parties|Constituencies|Interests|GovernmentPosts|PreferredNames/", server.url()));
+        assert_eq!(source.members, 2);
+        assert_eq!(source.twitter_coverage, 50.0);
+        assert!(db.exists());
+
+        let report = result.report();
+        assert_eq!(report.sources.len(), 1);
+    }
+
+    #[test]
+    fn roles_from_a_second_query_are_merged_by_id() {
+        let server = MockServer::new(vec![
+            MockResponse::ok(document(&[(172, ""), (4212, ""), (1524, "")])),
+            MockResponse::ok(include_str!("../fixtures/roles.xml")),
+        ]);
+
+        let loader = Loader::new()
+            .api_base(&format!("{}/Members/Query/", server.url()))
+            .request_delay(Duration::from_millis(0))
+            .enrich_roles(true)
+            .build()
+            .unwrap();
+        let loaded = loader.load().unwrap();
+
+        let members = &loaded[0].members;
+        let posts = |id| members.by_id(id).unwrap().government_posts.iter()
+            .map(|p| (p.name.clone(), p.opposition)).collect::<Vec<_>>();
+        assert_eq!(posts(172), [("Shadow Home Secretary".to_string(), true)]);
+        assert_eq!(posts(4212).len(), 1);
+        assert!(!posts(4212)[0].1);
+        assert!(posts(1524).is_empty());
+        assert!(members.by_id(99999).is_none());
+        assert!(!members.by_id(172).unwrap().is_minister());
+        assert_eq!(server.paths(), [
+            "/Members/Query/house=Commons|isEligible=true/Addresses|Parties|Constituencies|Interests|PreferredNames/",
+            "/Members/Query/house=Commons|isEligible=true/GovernmentPosts|OppositionPosts/",
+        ]);
+    }
+
+    #[test]
+    fn a_past_membership_is_fetched_and_parsed() {
+        let server = MockServer::new(vec![MockResponse::ok(document(&[
+            (1524, "<HouseStartDate>1997-05-01T00:00:00</HouseStartDate><HouseEndDate>2019-11-04T00:00:00</HouseEndDate>\
+                <Parties><Party><Name>Speaker</Name><StartDate>2009-06-22T00:00:00</StartDate><EndDate>2019-11-04T00:00:00</EndDate></Party>\
+                <Party><Name>Conservative</Name><StartDate>1997-05-01T00:00:00</StartDate><EndDate>2009-06-22T00:00:00</EndDate></Party></Parties>"),
+            (172, "<HouseStartDate>1987-06-11T00:00:00</HouseStartDate>"),
+        ]))]);
+
+        let loader = Loader::new()
+            .api_base(&format!("{}/Members/Query/", server.url()))
+            .request_delay(Duration::from_millis(0))
+            .as_of(Some(parse::parse_date("2010-06-01").unwrap()))
+            .build()
+            .unwrap();
+        let loaded = loader.load().unwrap();
+
+        let members = &loaded[0].members;
+        assert_eq!(members.iter().map(|m| m.id).collect::<Vec<_>>(), [1524, 172]);
+        let bercow = members.by_id(1524).unwrap();
+        assert_eq!(bercow.house_end_date, Some(parse::parse_date("2019-11-04").unwrap()));
+        assert_eq!(bercow.parties.len(), 2);
+        assert_eq!(server.paths(), [
+            "/Members/Query/house=Commons|commonsmemberon=2010-06-01/Addresses|Parties|Constituencies|Interests|GovernmentPosts|PreferredNames/",
+        ]);
+    }
+
+    #[test]
+    fn hooks_run_in_order_after_parsing() {
+        let server = MockServer::new(vec![MockResponse::ok(document(&[(172, ""), (4212, "")]))]);
+        let written = Arc::new(Mutex::new(vec![]));
+
+        let loader = Loader::new()
+            .api_base(&format!("{}/Members/Query/", server.url()))
+            .request_delay(Duration::from_millis(0))
+            .min_members(Some(0))
+            .hook(|members| members.members.retain(|m| m.id != 4212))
+            .hook(|members| for member in &mut members.members {
+                member.twitter.get_or_insert_with(|| "@HackneyAbbott".to_string());
+            })
+            .sink(Box::new(MemorySink { written: written.clone(), fail: false }))
+            .build()
+            .unwrap();
+        let result = loader.run().unwrap();
+
+        assert_eq!(*written.lock().unwrap(), [172]);
+        assert_eq!(result.sources[0].members, 1);
+        assert_eq!(result.sources[0].twitter_coverage, 100.0);
+    }
+
+    #[test]
+    fn only_members_changed_since_a_snapshot_are_written() {
+        let labour = "<Parties><Party><Name>Labour</Name><StartDate>2015-05-07T00:00:00</StartDate></Party></Parties>";
+        let dir = TempDir::new("changes");
+        let previous = dir.path().join("previous.json");
+        let before = parse::parse_addresses_xml(&document(&[(172, labour), (4212, labour), (4005, labour)]), &Config::default()).unwrap();
+        crate::snapshot::write_snapshot(&previous, &before).unwrap();
+
+        let server = MockServer::new(vec![MockResponse::ok(document(&[
+            (172, labour),
+            (4212, "<Parties><Party><Name>Change UK</Name><StartDate>2019-02-18T00:00:00</StartDate></Party></Parties>"),
+            (1524, labour),
+        ]))]);
+        let written = Arc::new(Mutex::new(vec![]));
+        let loader = Loader::new()
+            .api_base(&format!("{}/Members/Query/", server.url()))
+            .request_delay(Duration::from_millis(0))
+            .min_members(Some(0))
+            .changes_since(Some(previous))
+            .sink(Box::new(MemorySink { written: written.clone(), fail: false }))
+            .build()
+            .unwrap();
+        loader.run().unwrap();
+
+        assert_eq!(*written.lock().unwrap(), [1524, 4212]);
+    }
+
+    #[test]
+    fn too_few_members_abort_before_committing() {
+        let server = MockServer::new(vec![MockResponse::ok(document(&[(172, ""), (4212, "")]))]);
+        let dir = TempDir::new("loader");
+        let db = dir.path().join("members.db");
+
+        let loader = Loader::new()
+            .api_base(&format!("{}/Members/Query/", server.url()))
+            .request_delay(Duration::from_millis(0))
+            .min_members(Some(3))
+            .sqlite(&db)
+            .build()
+            .unwrap();
+
+        match loader.run() {
+            Err(LoadError::Validation(ValidationError::TooFewMembers { count: 2, required: 3 })) => {}
+            Err(e) => panic!("expected too few members, got {}", e),
+            Ok(_) => panic!("expected too few members to abort the load")
+        }
+        assert!(!db.exists());
+    }
+
+    #[test]
+    fn members_are_fanned_out_to_every_sink() {
+        let server = MockServer::new(vec![MockResponse::ok(document(&[(172, ""), (4212, "")]))]);
+        let failing = Arc::new(Mutex::new(vec![]));
+        let working = Arc::new(Mutex::new(vec![]));
+
+        let loader = Loader::new()
+            .api_base(&format!("{}/Members/Query/", server.url()))
+            .request_delay(Duration::from_millis(0))
+            .min_members(Some(0))
+            .sink(Box::new(MemorySink { written: failing.clone(), fail: true }))
+            .sink(Box::new(MemorySink { written: working.clone(), fail: false }))
+            .build()
+            .unwrap();
+
+        match loader.run() {
+            Err(LoadError::Outputs { failed: 1, total: 2, .. }) => {}
+            Err(e) => panic!("expected one failed output, got {}", e),
+            Ok(_) => panic!("expected the failing output to be reported")
+        }
+        assert_eq!(*failing.lock().unwrap(), [172, 4212]);
+        assert_eq!(*working.lock().unwrap(), [172, 4212]);
+    }
+
+    #[test]
+    fn members_before_the_resume_id_are_skipped() {
+        let load = |resume_from| {
+            let server = MockServer::new(vec![MockResponse::ok(document(&[(172, ""), (4212, ""), (1524, "")]))]);
+            let written = Arc::new(Mutex::new(vec![]));
+            Loader::new()
+                .api_base(&format!("{}/Members/Query/", server.url()))
+                .request_delay(Duration::from_millis(0))
+                .min_members(Some(0))
+                .resume_from(Some(resume_from))
+                .sink(Box::new(MemorySink { written: written.clone(), fail: false }))
+                .build()
+                .unwrap()
+                .run()
+                .unwrap();
+            let written = written.lock().unwrap().clone();
+            written
+        };
+
+        assert_eq!(load(4212), [1524, 4212]);
+        assert_eq!(load(9999), [172, 1524, 4212]);
+    }
+
+    #[test]
+    fn headers_are_attached_to_api_requests() {
+        let server = MockServer::new(vec![MockResponse::ok(document(&[(172, "")]))]);
+
+        Loader::new()
+            .api_base(&format!("{}/Members/Query/", server.url()))
+            .request_delay(Duration::from_millis(0))
+            .min_members(Some(0))
+            .header("X-Mirror", "eu-west")
+            .api_key(Some("hunter2"))
+            .sink(Box::new(MemorySink { written: Arc::new(Mutex::new(vec![])), fail: false }))
+            .build()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        let headers = server.headers();
+        assert_eq!(headers.len(), 1);
+        assert!(headers[0].contains(&("x-mirror".to_string(), "eu-west".to_string())), "{:?}", headers[0]);
+        assert!(headers[0].contains(&("x-api-key".to_string(), "hunter2".to_string())), "{:?}", headers[0]);
+
+        match Loader::new().header("Bad Header", "x").build() {
+            Err(LoadError::Client(ApiError::InvalidHeader { name })) => assert_eq!(name, "Bad Header"),
+            other => panic!("expected an invalid header, got {:?}", other.err())
+        }
+    }
+
+    #[test]
+    fn exports_run_without_dgraph() {
+        let server = MockServer::new(vec![MockResponse::ok(document(&[(172, ""), (4212, "")]))]);
+        let dir = TempDir::new("loader");
+        let json = dir.path().join("members.json");
+        let builder = || Loader::new()
+            .api_base(&format!("{}/Members/Query/", server.url()))
+            .request_delay(Duration::from_millis(0))
+            .min_members(Some(0));
+
+        let names = |loader: &Loader| loader.sinks.iter().map(|s| s.name()).collect::<Vec<_>>();
+        assert_eq!(names(&builder().build().unwrap()), ["dgraph at localhost:9080"]);
+        assert!(names(&builder().no_dgraph(true).build().unwrap()).is_empty());
+
+        let loader = builder().no_dgraph(true).json(&json).build().unwrap();
+        assert_eq!(names(&loader), [json.display().to_string()]);
+        loader.run().unwrap();
+        assert!(json.exists());
+    }
+}
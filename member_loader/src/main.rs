@@ -1,453 +1,689 @@
-use xml::reader::{EventReader, XmlEvent};
-use chrono::{DateTime, Utc};
-use serde::{Serialize};
-
-const MEMBER_API_BASE: &str = "https://data.parliament.uk/membersdataplatform/services/mnisv1.0/Members/Query/";
-
-#[derive(Debug, Clone)]
-enum House {
-    Commons,
-    Lords,
-    Unknown,
+use member_loader::api::{ApiClient, TlsOptions};
+use member_loader::commit::{self, CommitError};
+use member_loader::config::{Config, ConfigError};
+use member_loader::diff::{self, ExportError};
+use member_loader::json;
+use member_loader::loader::{Input, LoadError, LoadedMembers, Loader, Output};
+use member_loader::log;
+use member_loader::manifest::RunManifest;
+use member_loader::members::{House, Members};
+use member_loader::report::Report;
+use member_loader::snapshot;
+use member_loader::twitter::{self, HandleStatus, TwitterError};
+use member_loader::verify;
+use std::error::Error as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+mod check;
+mod options;
+
+use options::{Command, Options};
+
+#[derive(Debug, Error)]
+enum Error {
+    #[error("{0}")]
+    Options(String),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Load(#[from] LoadError),
+    #[error("unable to fix Twitter handles")]
+    FixHandles(#[source] CommitError),
+    #[error("unable to remove orphaned nodes")]
+    Gc(#[source] CommitError),
+    #[error("unable to link members to their xid")]
+    Relink(#[source] CommitError),
+    #[error("unable to store run manifest")]
+    Manifest(#[source] CommitError),
+    #[error("unable to write manifest {}", .path.display())]
+    ManifestFile {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("unable to check Twitter handles")]
+    Twitter(#[from] TwitterError),
+    #[error("unable to update Twitter handles")]
+    UpdateHandles(#[source] CommitError),
+    #[error("unable to read stored members")]
+    Stored(#[source] CommitError),
+    #[error("found {count} discrepancies, more than the {max} allowed")]
+    Discrepancies {
+        count: usize,
+        max: usize,
+    },
+    #[error("{0} warnings with --warn-as-error")]
+    Warnings(usize),
+    #[error("{0} members would change")]
+    Planned(usize),
+    #[error("{0} parser self-test fixtures failed")]
+    Check(usize),
+    #[error(transparent)]
+    Export(#[from] ExportError),
+    #[error("unable to write snapshot {}", .path.display())]
+    Snapshot {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("unable to write report {}", .path.display())]
+    Report {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
 }
 
-impl Into<&str> for House {
-    fn into(self) -> &'static str {
-        match self {
-            House::Commons => "Commons",
-            House::Lords => "Lords",
-            House::Unknown => unimplemented!()
+fn build_loader(options: &Options, config: Config) -> Result<Loader, Error> {
+    let input = match (&options.input_files, &options.input_file, &options.replay_dir, &options.replay_spill) {
+        (Some(pattern), _, _, _) => Input::Files(pattern.clone(), options.input_format),
+        (None, Some(path), _, _) => Input::File(path.clone(), options.input_format),
+        (None, None, Some(dir), _) => Input::Replay(dir.clone()),
+        (None, None, None, Some(path)) => Input::Spill(path.clone()),
+        (None, None, None, None) => Input::Api
+    };
+
+    let mut loader = Loader::new()
+        .house(House::Commons)
+        .api_base(&options.api_base)
+        .require_https(options.require_https)
+        .ca_cert(options.ca_cert.clone())
+        .api_key(options.api_key.as_deref())
+        .max_body_size(options.max_body_size)
+        .request_delay(options.request_delay)
+        .page_size(options.page_size)
+        .query(options.query.clone())
+        .incremental(options.incremental)
+        .archive_dir(options.archive_dir.clone())
+        .parse_cache_dir(options.parse_cache_dir.clone())
+        .input(input)
+        .config(config)
+        .active_since(options.active_since)
+        .as_of(options.as_of)
+        .keep_house(options.keep_house)
+        .status(options.status.clone())
+        .sworn_only(options.sworn_only)
+        .enrich_roles(options.enrich_roles)
+        .explain(options.explain)
+        .party_history_years(options.party_history_years)
+        .max_parties_per_member(options.max_parties_per_member)
+        .changes_since(options.changes_since.clone())
+        .include_ids(options.include_ids.iter().copied())
+        .exclude_ids(options.exclude_ids.iter().copied())
+        .limit(options.limit)
+        .resume_from(options.resume_from)
+        .strict_schema(options.strict_schema || options.parse_only)
+        .min_twitter_coverage(if options.fail_on_missing_twitter { Some(options.min_twitter_coverage) } else { None })
+        .min_members(options.min_members)
+        .previous_fill_rates(options.manifest_file.as_deref().and_then(RunManifest::load).map(|m| m.fill_rates))
+        .max_fill_drop(options.max_fill_drop, options.fail_on_fill_drop)
+        .resume(options.resume)
+        .only_changed(options.only_changed)
+        .commit_delay(options.commit_delay)
+        .commit_retries(options.commit_retries)
+        .commit_concurrency(options.commit_concurrency)
+        .on_conflict(options.on_conflict)
+        .spill_dir(options.spill_dir.clone())
+        .verify_writes(options.verify_writes)
+        .threads(options.threads)
+        .json_style(options.json_style)
+        .flatten_parties(options.flatten_parties)
+        .redact_contact(options.redact_contact)
+        .sort(options.sort)
+        .parse_only(options.parse_only)
+        .no_dgraph(options.no_dgraph);
+
+    for (name, value) in &options.headers {
+        loader = loader.header(name, value);
+    }
+    for output in &options.outputs {
+        if let Some(dir) = options.split_by_party.as_ref().filter(|_| output.kind != Output::Dgraph) {
+            loader = loader.split_by_party(dir, output.kind);
+            continue;
         }
+        let target = match (&output.target, &options.output_file) {
+            (Some(t), _) => t.clone(),
+            (None, Some(f)) if output.kind != Output::Dgraph => f.display().to_string(),
+            (None, _) => output.kind.default_target().to_string()
+        };
+        let path = Path::new(&target);
+        loader = match output.kind {
+            Output::Dgraph => loader.dgraph(&target),
+            Output::Sqlite => loader.sqlite(path),
+            Output::Graphml => loader.graphml(path),
+            Output::Json => loader.json(path),
+            Output::Csv => loader.csv(path),
+            Output::Markdown => loader.markdown(path),
+            Output::LiveJson => loader.live_json(path),
+        };
     }
+
+    Ok(loader.build()?)
 }
 
-enum AdditionalData {
-    Addresses,
-    Parties,
+fn fix_handles(options: &Options) -> Result<(), Error> {
+    println!("Connecting to dgraph...");
+    let dgraph = dgraph::make_dgraph!(dgraph::new_dgraph_client(options.dgraph_addr()));
+
+    println!("Fixing Twitter handles...");
+    let fixed = commit::fix_twitter_handles(&dgraph).map_err(Error::FixHandles)?;
+    println!("Checked {} Twitter handles: {} updated, {} removed", fixed.checked, fixed.updated, fixed.removed);
+
+    Ok(())
 }
 
-impl Into<&str> for AdditionalData {
-    fn into(self) -> &'static str {
-        match self {
-            AdditionalData::Addresses => "Addresses",
-            AdditionalData::Parties => "Parties",
-        }
+fn gc(options: &Options) -> Result<(), Error> {
+    println!("Connecting to dgraph...");
+    let dgraph = dgraph::make_dgraph!(dgraph::new_dgraph_client(options.dgraph_addr()));
+
+    let orphans = commit::remove_orphans(&dgraph, !options.apply).map_err(Error::Gc)?;
+    for party in &orphans.parties {
+        println!("  {}", party);
+    }
+    if orphans.parties.is_empty() {
+        println!("No orphaned parties found");
+    } else if orphans.removed {
+        println!("Removed {} orphaned parties", orphans.parties.len());
+    } else {
+        println!("Found {} orphaned parties, run with --apply to remove them", orphans.parties.len());
     }
-}
 
-#[derive(Debug)]
-struct Members {
-    members: Vec<Member>
+    Ok(())
 }
 
-impl Members {
-    fn new() -> Self {
-        Members {
-            members: vec![]
-        }
+fn relink(options: &Options, loader: &Loader) -> Result<(), Error> {
+    let mut members = Members::new();
+    for l in loader.load()? {
+        members.extend(l.members);
+    }
+
+    println!("Connecting to dgraph...");
+    let dgraph = dgraph::make_dgraph!(dgraph::new_dgraph_client(options.dgraph_addr()));
+    let (legacy, linked) = commit::legacy_members(&dgraph).map_err(Error::Relink)?;
+
+    let relinks = verify::plan_relink(&members, &linked, &legacy);
+    let links: Vec<(String, u32)> = relinks.iter().filter_map(|r| match r {
+        verify::Relink::Link { uid, member_id } => Some((uid.clone(), *member_id)),
+        verify::Relink::Unresolved { .. } => None
+    }).collect();
+    for relink in &relinks {
+        println!("  {}", relink);
     }
+
+    let unresolved = relinks.len() - links.len();
+    if relinks.is_empty() {
+        println!("All member nodes are already linked");
+    } else if options.apply {
+        let linked = commit::relink_members(&dgraph, &links).map_err(Error::Relink)?;
+        println!("Linked {} member nodes, {} unresolved", linked, unresolved);
+    } else {
+        println!("Found {} member nodes to link and {} unresolved, run with --apply to link them", links.len(), unresolved);
+    }
+
+    Ok(())
 }
 
-#[derive(Debug, Clone)]
-struct Member {
-    id: u32,
-    name: String,
-    party: String,
-    house: House,
-    constituency: String,
-    twitter: Option<String>,
-    facebook: Option<String>,
-    parties: Vec<Party>,
+fn verify(options: &Options, loader: &Loader) -> Result<(), Error> {
+    let mut members = Members::new();
+    for l in loader.load()? {
+        members.extend(l.members);
+    }
+
+    println!("Connecting to dgraph...");
+    let dgraph = dgraph::make_dgraph!(dgraph::new_dgraph_client(options.dgraph_addr()));
+    let stored = commit::stored_members(&dgraph).map_err(Error::Stored)?;
+
+    let verification = verify::compare(&members, &stored);
+    for d in &verification.discrepancies {
+        println!("{}", d);
+    }
+    println!("Checked {} fetched against {} stored members: {} discrepancies", verification.checked,
+             verification.stored, verification.discrepancies.len());
+
+    if let Some(path) = &options.report_file {
+        let file = fs::File::create(path).map_err(|e| Error::Report { path: path.clone(), source: e })?;
+        options.json_style.to_writer(file, &verification)
+            .map_err(|e| Error::Report { path: path.clone(), source: e.into() })?;
+    }
+
+    if verification.discrepancies.len() > options.max_discrepancies {
+        return Err(Error::Discrepancies { count: verification.discrepancies.len(), max: options.max_discrepancies });
+    }
+
+    Ok(())
 }
 
-impl Member {
-    fn new() -> Self {
-        Self {
-            id: 0,
-            name: "".to_string(),
-            party: "".to_string(),
-            house: House::Unknown,
-            constituency: "".to_string(),
-            twitter: None,
-            facebook: None,
-            parties: vec![],
+fn plan(options: &Options, loader: &Loader) -> Result<(), Error> {
+    let mut members = Members::new();
+    for l in loader.load()? {
+        members.extend(l.members);
+    }
+
+    println!("Connecting to dgraph...");
+    let dgraph = dgraph::make_dgraph!(dgraph::new_dgraph_client(options.dgraph_addr()));
+    let stored = commit::stored_members(&dgraph).map_err(Error::Stored)?;
+
+    let plan = verify::plan(&members, &stored, &loader.config().fingerprint_fields);
+    if options.json {
+        println!("{}", options.json_style.to_string(&plan).expect("Invalid json"));
+    } else {
+        for change in &plan.changes {
+            println!("{}", change);
         }
+        println!("Planned {} changes for {} fetched members against {} stored, {} unchanged", plan.pending(),
+                 plan.fetched, plan.stored, plan.unchanged);
+    }
+
+    if let Some(path) = &options.report_file {
+        let file = fs::File::create(path).map_err(|e| Error::Report { path: path.clone(), source: e })?;
+        options.json_style.to_writer(file, &plan)
+            .map_err(|e| Error::Report { path: path.clone(), source: e.into() })?;
+    }
+
+    match plan.pending() {
+        0 => Ok(()),
+        pending => Err(Error::Planned(pending))
+    }
+}
+
+fn cross_validate(options: &Options, loader: &Loader) -> Result<(), Error> {
+    if options.has_local_input() {
+        return Err(Error::Options("cross-validate fetches from the API and cannot be used with --input-file, --input-files, --replay-dir or --replay-spill".to_string()));
+    }
+
+    let validation = loader.cross_validate()?;
+    for d in &validation.discrepancies {
+        println!("{}", d);
+    }
+    println!("Compared {} XML against {} JSON members: {} discrepancies", validation.xml_members,
+             validation.json_members, validation.discrepancies.len());
+
+    if let Some(path) = &options.report_file {
+        let file = fs::File::create(path).map_err(|e| Error::Report { path: path.clone(), source: e })?;
+        options.json_style.to_writer(file, &validation)
+            .map_err(|e| Error::Report { path: path.clone(), source: e.into() })?;
+    }
+
+    if validation.discrepancies.len() > options.max_discrepancies {
+        return Err(Error::Discrepancies { count: validation.discrepancies.len(), max: options.max_discrepancies });
     }
+
+    Ok(())
 }
 
+fn check_twitter(options: &Options, loader: &Loader) -> Result<(), Error> {
+    let mut members = Members::new();
+    for l in loader.load()? {
+        members.extend(l.members);
+    }
+
+    let token = options.twitter_token.as_deref().unwrap_or_default();
+    let tls = TlsOptions { require_https: true, ca_cert: options.ca_cert.clone() };
+    let client = ApiClient::new(options.request_delay, tls, &[("Authorization".to_string(), format!("Bearer {}", token))])
+        .map_err(TwitterError::from)?;
+    let checks = twitter::check_handles(&client, twitter::TWITTER_API_BASE, &members)?;
+
+    if options.json {
+        println!("{}", options.json_style.to_string(&checks).expect("Invalid json"));
+    }
+    let mut renamed = vec![];
+    for check in &checks {
+        let problem = match &check.status {
+            HandleStatus::Ok => continue,
+            HandleStatus::Renamed { username } => {
+                renamed.push((check.member_id, username.clone()));
+                format!("is now @{}", username)
+            }
+            HandleStatus::Missing => "does not exist".to_string(),
+            HandleStatus::Suspended => "is suspended".to_string(),
+        };
+        if !options.json {
+            println!("  {} ({}): @{} {}", check.name, check.member_id, check.handle, problem);
+        }
+    }
+    let problems = checks.iter().filter(|c| c.status != HandleStatus::Ok).count();
+    println!("Checked {} Twitter handles: {} need attention", checks.len(), problems);
+
+    if !renamed.is_empty() {
+        if options.apply && !options.no_dgraph {
+            println!("Connecting to dgraph...");
+            let dgraph = dgraph::make_dgraph!(dgraph::new_dgraph_client(options.dgraph_addr()));
+            let updated = commit::set_twitter_handles(&dgraph, &renamed).map_err(Error::UpdateHandles)?;
+            println!("Updated {} stored Twitter handles", updated);
+        } else {
+            println!("Run with --apply to update {} renamed handles", renamed.len());
+        }
+    }
 
-#[derive(Debug, Clone)]
-struct Party {
-    name: String,
-    start_date: DateTime<Utc>,
-    end_date: Option<DateTime<Utc>>,
+    Ok(())
 }
 
-impl Party {
-    fn new() -> Self {
-        Self {
-            name: "".to_string(),
-            start_date: Utc::now(),
-            end_date: None,
+fn write_manifest(options: &Options, manifest: &RunManifest) -> Result<(), Error> {
+    match &options.manifest_file {
+        Some(path) => manifest.write(path, options.json_style)
+            .map_err(|e| Error::ManifestFile { path: path.clone(), source: e }),
+        None if options.uses_dgraph() => {
+            let dgraph = dgraph::make_dgraph!(dgraph::new_dgraph_client(options.dgraph_addr()));
+            commit::commit_manifest(&dgraph, manifest).map_err(Error::Manifest)
         }
+        None => Ok(())
     }
 }
 
-fn get_api_url(house: House, additional_data: Vec<AdditionalData>) -> String {
-    let mut url = format!("{}house={}|isEligible=true/", MEMBER_API_BASE, Into::<&str>::into(house));
+fn diff_exports(options: &Options) -> Result<(), Error> {
+    let before = diff::read_export(&options.diff_files[0])?;
+    let after = diff::read_export(&options.diff_files[1])?;
+    let diff = diff::diff_exports(&before, &after);
+
+    if options.json {
+        println!("{}", options.json_style.to_string(&diff).expect("Invalid json"));
+    } else {
+        for change in &diff.changes {
+            println!("{}", change);
+        }
+        println!("Compared {} against {} members: {} changes", diff.before, diff.after, diff.changes.len());
+    }
 
-    let data: Vec<&str> = additional_data.into_iter().map(|d| Into::<&str>::into(d)).collect();
-    url.extend(format!("{}/", data.join("|")).chars());
+    if let Some(path) = &options.report_file {
+        let file = fs::File::create(path).map_err(|e| Error::Report { path: path.clone(), source: e })?;
+        options.json_style.to_writer(file, &diff)
+            .map_err(|e| Error::Report { path: path.clone(), source: e.into() })?;
+    }
 
-    url
+    Ok(())
 }
 
-fn merge_parties(parties: &Vec<Party>) -> Vec<Party> {
-    let mut parties = parties.clone();
-    if parties.len() < 2 {
-        return parties;
-    }
-
-    let mut out: Vec<Party> = vec![];
-    loop {
-        let a = parties.pop();
-        let b = parties.pop();
-        match (a, b) {
-            (Some(a), Some(b)) => {
-                if a.name == b.name {
-                    parties.push(Party {
-                        name: a.name,
-                        start_date: b.start_date,
-                        end_date: a.end_date,
-                    });
-                } else {
-                    out.push(a);
-                    parties.push(b);
-                }
+fn print_parties(options: &Options, loaded: &[LoadedMembers]) {
+    for l in loaded {
+        let counts = l.members.party_counts();
+        if options.json {
+            println!("{}", options.json_style.to_string(&counts).expect("Invalid json"));
+        } else {
+            println!("{} ({} members):", l.source, l.members.members.len());
+            for c in &counts {
+                println!("  {:>4}  {}", c.members, c.party);
             }
-            (Some(a), None) => {
-                out.push(a);
-                return out;
-            }
-            (None, None) => return out,
-            (None, Some(_)) => unreachable!()
         }
     }
 }
 
-fn parse_addresses_xml(data: &str) -> Option<Members> {
-    let parser = EventReader::from_str(data);
-
-    #[derive(Debug, PartialEq)]
-    enum Element {
-        None,
-        Members,
-        Member,
-        Addresses,
-        Address,
-        AddrType,
-        AddressLine1,
-        Parties,
-        Party,
-        PartyPartyName,
-        PartyStartDate,
-        PartyEndDate,
-        Name,
-        PartyName,
-        House,
-        Constituency,
-        Other,
-    }
-
-    #[derive(Debug)]
-    struct Address {
-        addr_type: String,
-        address: String,
-    }
-
-    impl Address {
-        fn new() -> Self {
-            Self {
-                addr_type: "".to_string(),
-                address: "".to_string(),
-            }
+fn print_query(options: &Options, loaded: &[LoadedMembers]) {
+    for l in loaded {
+        if options.json {
+            println!("{}", options.json_style.to_string(&l.members.members).expect("Invalid json"));
+            continue;
         }
+        for m in &l.members.members {
+            println!("{:>5}  {} ({}, {}) {}", m.id, m.name, m.current_party(), m.constituency,
+                     m.twitter_handle().map_or(String::new(), |t| format!("@{}", t)));
+        }
+        println!("{} matching members", l.members.members.len());
     }
+}
 
-    let mut members: Option<Members> = None;
-    let mut member: Option<Member> = None;
-    let mut address: Option<Address> = None;
-    let mut party: Option<Party> = None;
-
-    let mut current_element = Element::None;
-    let mut previous_elements: Vec<Element> = vec![];
-
-    for e in parser {
-        match e {
-            Ok(XmlEvent::StartElement { name, attributes, .. }) => {
-                if current_element == Element::None {
-                    if name.local_name == "Members" {
-                        members = Some(Members::new());
-                        previous_elements.push(current_element);
-                        current_element = Element::Members;
-                        continue;
-                    }
-                } else if current_element == Element::Members {
-                    if name.local_name == "Member" {
-                        let mut id = None;
-                        for a in attributes {
-                            if a.name.local_name == "Member_Id" {
-                                id = Some(a.value.parse::<u32>().unwrap())
-                            }
-                        }
-
-                        let mut m = Member::new();
-                        m.id = id.unwrap();
-                        member = Some(m);
-                        previous_elements.push(current_element);
-                        current_element = Element::Member;
-                        continue;
-                    }
-                } else if current_element == Element::Member {
-                    if name.local_name == "Addresses" {
-                        previous_elements.push(current_element);
-                        current_element = Element::Addresses;
-                        continue;
-                    } else if name.local_name == "Parties" {
-                        previous_elements.push(current_element);
-                        current_element = Element::Parties;
-                        continue;
-                    } else if name.local_name == "DisplayAs" {
-                        previous_elements.push(current_element);
-                        current_element = Element::Name;
-                        continue;
-                    } else if name.local_name == "Party" {
-                        previous_elements.push(current_element);
-                        current_element = Element::PartyName;
-                        continue;
-                    } else if name.local_name == "House" {
-                        previous_elements.push(current_element);
-                        current_element = Element::House;
-                        continue;
-                    } else if name.local_name == "MemberFrom" {
-                        previous_elements.push(current_element);
-                        current_element = Element::Constituency;
-                        continue;
-                    }
-                } else if current_element == Element::Addresses {
-                    if name.local_name == "Address" {
-                        address = Some(Address::new());
-                        previous_elements.push(current_element);
-                        current_element = Element::Address;
-                        continue;
-                    }
-                } else if current_element == Element::Address {
-                    if name.local_name == "Type" {
-                        previous_elements.push(current_element);
-                        current_element = Element::AddrType;
-                        continue;
-                    } else if name.local_name == "Address1" {
-                        previous_elements.push(current_element);
-                        current_element = Element::AddressLine1;
-                        continue;
-                    }
-                } else if current_element == Element::Parties {
-                    if name.local_name == "Party" {
-                        party = Some(Party::new());
-                        previous_elements.push(current_element);
-                        current_element = Element::Party;
-                        continue;
-                    }
-                } else if current_element == Element::Party {
-                    if name.local_name == "Name" {
-                        previous_elements.push(current_element);
-                        current_element = Element::PartyPartyName;
-                        continue;
-                    } else if name.local_name == "StartDate" {
-                        previous_elements.push(current_element);
-                        current_element = Element::PartyStartDate;
-                        continue;
-                    } else if name.local_name == "EndDate" {
-                        for a in attributes {
-                            if a.name.local_name == "nil" && a.value == "true" {
-                                continue;
-                            }
-                        }
-
-                        previous_elements.push(current_element);
-                        current_element = Element::PartyEndDate;
-                        continue;
-                    }
-                }
-                previous_elements.push(current_element);
-                current_element = Element::Other;
+fn write_snapshot(options: &Options, loader: &Loader) -> Result<(), Error> {
+    let mut members = Members::new();
+    for l in loader.load()? {
+        members.extend(l.members);
+    }
+
+    let path = options.output_file.clone().unwrap_or_else(|| PathBuf::from(snapshot::DEFAULT_SNAPSHOT_FILE));
+    snapshot::write_snapshot(&path, &members).map_err(|e| Error::Snapshot { path: path.clone(), source: e })?;
+    println!("Wrote snapshot of {} members to {}", members.members.len(), path.display());
+    Ok(())
+}
+
+fn print_config(options: &Options, config: &Config) {
+    let effective = serde_json::json!({
+        "options": options,
+        "config": config,
+    });
+    println!("{}", options.json_style.to_string(&effective).expect("Invalid json"));
+}
+
+fn run() -> Result<(), Error> {
+    let options = Options::from_args().map_err(Error::Options)?;
+    log::set_debug(options.debug);
+    log::to_stderr(options.summary_json);
+    match options.command {
+        Command::FixHandles => return fix_handles(&options),
+        Command::Gc => return gc(&options),
+        Command::Check => return match check::run() {
+            0 => Ok(()),
+            failed => Err(Error::Check(failed))
+        },
+        Command::Diff => return diff_exports(&options),
+        Command::Schema => {
+            println!("{}", options.json_style.to_string(&json::export_schema()).expect("Invalid json"));
+            return Ok(());
+        }
+        _ => {}
+    }
+    let mut config = match &options.config {
+        Some(path) => Config::load(path, options.profile.as_deref())?,
+        None => Config::default()
+    };
+    if options.member_ids.is_some() {
+        config.member_ids = options.member_ids;
+    }
+    if options.command == Command::Config {
+        print_config(&options, &config);
+        return Ok(());
+    }
+    let loader = build_loader(&options, config)?;
+
+    match options.command {
+        Command::Verify => verify(&options, &loader),
+        Command::Relink => relink(&options, &loader),
+        Command::CrossValidate => cross_validate(&options, &loader),
+        Command::Parties => {
+            print_parties(&options, &loader.load()?);
+            Ok(())
+        }
+        Command::Query => {
+            if options.has_local_input() {
+                return Err(Error::Options("query fetches from the API and cannot be used with --input-file, --input-files, --replay-dir or --replay-spill".to_string()));
             }
-            Ok(XmlEvent::EndElement { name }) => {
-                if name.local_name == "Member" {
-                    match &mut members {
-                        Some(members) => members.members.push(member.clone().unwrap().clone()),
-                        None => unreachable!()
-                    }
-                } else if name.local_name == "Address" {
-                    match &mut member {
-                        None => unreachable!(),
-                        Some(member) => {
-                            match &address {
-                                Some(address) => match address.addr_type.as_str() {
-                                    "Twitter" => member.twitter = Some(address.address.clone()),
-                                    "Facebook" => member.facebook = Some(address.address.clone()),
-                                    _ => {}
-                                },
-                                None => unreachable!()
-                            }
-                        }
-                    }
-                } else if name.local_name == "Party" && current_element == Element::Party {
-                    match &mut member {
-                        None => unreachable!(),
-                        Some(member) => {
-                            match &party {
-                                Some(party) => member.parties.push(party.clone()),
-                                None => unreachable!()
-                            }
-                        }
-                    }
-                } else if name.local_name == "Parties" && current_element == Element::Parties {
-                    match &mut member {
-                        None => unreachable!(),
-                        Some(member) => {
-                            member.parties.sort_by(|a, b| a.start_date.timestamp().partial_cmp(&b.start_date.timestamp()).unwrap());
-                            member.parties = merge_parties(&member.parties);
-                        }
-                    }
-                }
-                current_element = match previous_elements.pop() {
-                    Some(e) => e,
-                    None => Element::None
-                };
+            print_query(&options, &loader.load()?);
+            Ok(())
+        }
+        Command::CheckTwitter => check_twitter(&options, &loader),
+        Command::Timeline => {
+            let events = diff::timeline(&loader.load()?);
+            println!("{}", options.json_style.to_string(&events).expect("Invalid json"));
+            Ok(())
+        }
+        Command::Snapshot => write_snapshot(&options, &loader),
+        Command::Load if options.plan => plan(&options, &loader),
+        Command::Load | Command::FixHandles | Command::Config | Command::Gc | Command::Check | Command::Diff | Command::Schema => {
+            let result = loader.run()?;
+            if !options.parse_only {
+                write_manifest(&options, &RunManifest::new(&result))?;
             }
-            Ok(XmlEvent::Characters(data)) => {
-                match current_element {
-                    Element::None | Element::Other | Element::Members | Element::Member |
-                    Element::Addresses | Element::Address |
-                    Element::Parties | Element::Party => {}
-                    Element::Name => {
-                        match &mut member {
-                            None => unreachable!(),
-                            Some(member) => member.name = data.clone()
-                        }
-                    }
-                    Element::PartyName => {
-                        match &mut member {
-                            None => unreachable!(),
-                            Some(member) => member.party = data.clone()
-                        }
-                    }
-                    Element::Constituency => {
-                        match &mut member {
-                            None => unreachable!(),
-                            Some(member) => member.constituency = data.clone()
-                        }
-                    }
-                    Element::House => {
-                        match &mut member {
-                            None => unreachable!(),
-                            Some(member) => member.house = match data.as_str() {
-                                "Commons" => House::Commons,
-                                "Lords" => House::Lords,
-                                _ => House::Unknown
-                            }
-                        }
-                    }
-                    Element::AddrType => {
-                        match &mut address {
-                            None => unreachable!(),
-                            Some(address) => address.addr_type = data.clone()
-                        }
-                    }
-                    Element::AddressLine1 => {
-                        match &mut address {
-                            None => unreachable!(),
-                            Some(address) => address.address = data.clone()
-                        }
-                    }
-                    Element::PartyPartyName => {
-                        match &mut party {
-                            None => unreachable!(),
-                            Some(party) => party.name = data.clone()
-                        }
-                    }
-                    Element::PartyStartDate => {
-                        match &mut party {
-                            None => unreachable!(),
-                            Some(party) => party.start_date = match format!("{}Z", data.clone()).parse::<DateTime<Utc>>() {
-                                Ok(d) => d,
-                                Err(_) => panic!("{}", data),
-                            }
-                        }
-                    }
-                    Element::PartyEndDate => {
-                        match &mut party {
-                            None => unreachable!(),
-                            Some(party) => party.end_date = Some(match format!("{}Z", data.clone()).parse::<DateTime<Utc>>() {
-                                Ok(d) => d,
-                                Err(_) => panic!("{}", data),
-                            })
-                        }
-                    }
-                }
+
+            let report = result.report();
+            if let Some(path) = &options.report_file {
+                report.write(path, options.json_style).map_err(|e| Error::Report { path: path.clone(), source: e })?;
             }
-            Err(e) => {
-                println!("Error: {}", e);
-                break;
+            if options.summary_json {
+                println!("{}", options.json_style.to_string(&report.summary()).expect("Invalid json"));
             }
-            _ => {}
+            check_warnings(&options, &report)
         }
     }
+}
 
-    members
+fn check_warnings(options: &Options, report: &Report) -> Result<(), Error> {
+    let warnings: usize = report.sources.iter().map(|s| s.warnings()).sum();
+    if options.warn_as_error && warnings > 0 {
+        return Err(Error::Warnings(warnings));
+    }
+    Ok(())
 }
 
-fn commit_member_data(dgraph: dgraph::Dgraph, members: Members) {
-    let mut txn = dgraph.new_txn();
+fn error_report(e: &Error) -> Vec<String> {
+    let mut lines = vec![format!("Error: {}", e)];
+    let mut source = e.source();
+    while let Some(s) = source {
+        lines.push(format!("  caused by: {}", s));
+        source = s.source();
+    }
+    lines
+}
 
-    #[derive(Serialize, Debug)]
-    struct MemberObject {
-        uid: String,
-        name: String,
+// Exit codes are part of the command line contract, so scripts can tell a bad invocation from a
+// network, feed or dgraph failure. Don't renumber them.
+const EXIT_FAILURE: i32 = 1;
+const EXIT_CONFIG: i32 = 2;
+const EXIT_API: i32 = 3;
+const EXIT_PARSE: i32 = 4;
+const EXIT_COMMIT: i32 = 5;
+const EXIT_SPILLED: i32 = 6;
+const EXIT_CHANGES: i32 = 7;
+
+fn load_exit_code(e: &LoadError) -> i32 {
+    match e {
+        LoadError::Client(_) => EXIT_CONFIG,
+        LoadError::Api { .. } => EXIT_API,
+        LoadError::Parse { .. } | LoadError::InputParse { .. } | LoadError::Validation(_) => EXIT_PARSE,
+        LoadError::Commit { .. } | LoadError::WriteVerification { .. } => EXIT_COMMIT,
+        LoadError::Spilled { .. } => EXIT_SPILLED,
+        LoadError::Outputs { source, .. } => load_exit_code(source),
+        _ => EXIT_FAILURE
     }
+}
 
-    for member in members.members {
-        let m = MemberObject {
-            uid: format!("_:{}", member.id),
-            name: member.name,
-        };
+fn exit_code(e: &Error) -> i32 {
+    match e {
+        Error::Options(_) | Error::Config(_) => EXIT_CONFIG,
+        Error::Load(e) => load_exit_code(e),
+        Error::Twitter(_) => EXIT_API,
+        Error::FixHandles(_) | Error::Gc(_) | Error::Relink(_) | Error::Manifest(_) | Error::Stored(_) | Error::UpdateHandles(_) => EXIT_COMMIT,
+        Error::Check(_) | Error::Export(_) | Error::Warnings(_) => EXIT_PARSE,
+        Error::Planned(_) => EXIT_CHANGES,
+        Error::Discrepancies { .. } | Error::Snapshot { .. } | Error::Report { .. } | Error::ManifestFile { .. } => EXIT_FAILURE,
+    }
+}
 
-        let mb = serde_json::to_vec(&m).expect("Invalid json");
+fn main() {
+    if let Err(e) = run() {
+        for line in error_report(&e) {
+            eprintln!("{}", line);
+        }
 
-        let mu = dgraph::Mutation {
-            set_json: mb,
-            ..Default::default()
-        };
+        std::process::exit(exit_code(&e));
+    }
+}
 
-        txn.mutate(mu).expect("failed to create member");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use member_loader::api::ApiError;
+    use member_loader::parse::ParseError;
+    use member_loader::validate::ValidationError;
+    use std::io;
+
+    #[test]
+    fn errors_report_their_causes() {
+        let e = Error::Config(ConfigError::Read {
+            path: "missing.toml".to_string(),
+            source: io::Error::new(io::ErrorKind::NotFound, "no such file"),
+        });
+        assert_eq!(error_report(&e), ["Error: unable to read config missing.toml", "  caused by: no such file"]);
+
+        let e = Error::Load(LoadError::Parse { house: House::Commons, source: ParseError::MissingMembers });
+        assert_eq!(error_report(&e), ["Error: unable to parse Commons members", "  caused by: feed has no Members element"]);
     }
 
-    txn.commit().expect("Failed to commit txn");
-}
+    #[test]
+    fn errors_map_to_their_exit_codes() {
+        let io_error = || io::Error::other("failed");
+        let insecure = || ApiError::Insecure { url: "http://example.com".to_string() };
+        let commit_error = || CommitError::MissingPartyUid { name: "Labour".to_string() };
+
+        let cases = vec![
+            (Error::Options("Unknown argument: --nope".to_string()), EXIT_CONFIG),
+            (Error::Config(ConfigError::Read { path: "missing.toml".to_string(), source: io_error() }), EXIT_CONFIG),
+            (Error::Load(LoadError::Client(insecure())), EXIT_CONFIG),
+            (Error::Load(LoadError::Api { house: House::Commons, source: insecure() }), EXIT_API),
+            (Error::Load(LoadError::Parse { house: House::Commons, source: ParseError::MissingMembers }), EXIT_PARSE),
+            (Error::Load(LoadError::InputParse { path: "members.json".into(), source: ParseError::UnknownFormat }), EXIT_PARSE),
+            (Error::Load(LoadError::Validation(ValidationError::UnknownElements(vec!["Pronouns".to_string()]))), EXIT_PARSE),
+            (Error::Load(LoadError::Commit { house: House::Commons, source: commit_error() }), EXIT_COMMIT),
+            (Error::Load(LoadError::Archive { dir: "archive".into(), source: io_error() }), EXIT_FAILURE),
+            (Error::FixHandles(commit_error()), EXIT_COMMIT),
+            (Error::Gc(commit_error()), EXIT_COMMIT),
+            (Error::Manifest(commit_error()), EXIT_COMMIT),
+            (Error::Stored(commit_error()), EXIT_COMMIT),
+            (Error::Check(1), EXIT_PARSE),
+            (Error::Warnings(1), EXIT_PARSE),
+            (Error::Discrepancies { count: 3, max: 0 }, EXIT_FAILURE),
+            (Error::Report { path: "report.json".into(), source: io_error() }, EXIT_FAILURE),
+            (Error::ManifestFile { path: "manifest.json".into(), source: io_error() }, EXIT_FAILURE),
+        ];
+        for (e, code) in cases {
+            assert_eq!(exit_code(&e), code, "{}", e);
+        }
+    }
 
-fn main() {
-    println!("Connecting to dgraph...");
-    let dgraph = dgraph::make_dgraph!(dgraph::new_dgraph_client("localhost:9080"));
+    #[test]
+    fn parse_only_reports_on_input_quality() {
+        let dir = std::env::temp_dir().join(format!("member_loader-parse-only-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let good = dir.join("good.xml");
+        let bad = dir.join("bad.xml");
+        let commons = include_str!("../fixtures/commons.xml");
+        fs::write(&good, commons).unwrap();
+        fs::write(&bad, commons.replacen("<DisplayAs>", "<Pronouns>they</Pronouns><DisplayAs>", 1)).unwrap();
+
+        let run = |path: &Path| {
+            let options = Options {
+                input_file: Some(path.to_path_buf()),
+                min_members: Some(0),
+                parse_only: true,
+                ..Options::default()
+            };
+            build_loader(&options, Config::default()).unwrap().run()
+        };
 
-    println!("Getting House of Commons data...");
-    let commons_addresses_url = get_api_url(House::Commons, vec![AdditionalData::Addresses, AdditionalData::Parties]);
-    let commons_addresses_body = reqwest::get(commons_addresses_url.as_str()).unwrap().text().unwrap();
+        let result = run(&good).unwrap();
+        assert_eq!(result.sources[0].members, 7);
+        assert_eq!(result.report().sources.len(), 1);
 
-    println!("Parsing House of Commons data...");
-    let commons_members = parse_addresses_xml(&commons_addresses_body).unwrap();
-    println!("{:#?}", commons_members);
+        let e = Error::Load(run(&bad).err().unwrap());
+        assert_eq!(exit_code(&e), EXIT_PARSE);
 
-    println!("Commiting data...");
-    commit_member_data(dgraph, commons_members);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn warnings_fail_the_run_only_with_warn_as_error() {
+        let dir = std::env::temp_dir().join(format!("member_loader-warn-as-error-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("members.xml");
+        let commons = include_str!("../fixtures/commons.xml");
+        fs::write(&path, commons.replacen("<StartDate>", "<StartDate>soon", 1)).unwrap();
+
+        let run = |warn_as_error: bool| {
+            let options = Options {
+                input_file: Some(path.clone()),
+                min_members: Some(0),
+                parse_only: true,
+                warn_as_error,
+                ..Options::default()
+            };
+            let report = build_loader(&options, Config::default()).unwrap().run().unwrap().report();
+            check_warnings(&options, &report)
+        };
+
+        assert!(run(false).is_ok());
+        match run(true) {
+            Err(e @ Error::Warnings(1)) => assert_eq!(exit_code(&e), EXIT_PARSE),
+            other => panic!("expected one warning, got {:?}", other),
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
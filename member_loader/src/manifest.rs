@@ -0,0 +1,102 @@
+use crate::json::JsonStyle;
+use crate::loader::LoadResult;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+pub const PARSER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HouseCount {
+    pub house: String,
+    pub members: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunManifest {
+    pub started_at: DateTime<Utc>,
+    pub elapsed_ms: u64,
+    pub parser_version: String,
+    pub sources: Vec<String>,
+    pub members: usize,
+    pub houses: Vec<HouseCount>,
+    #[serde(default)]
+    pub fill_rates: BTreeMap<String, f64>,
+}
+
+impl RunManifest {
+    pub fn new(result: &LoadResult) -> Self {
+        let mut houses = BTreeMap::new();
+        let mut filled: BTreeMap<String, f64> = BTreeMap::new();
+        for s in &result.sources {
+            *houses.entry(s.house.clone()).or_insert(0) += s.members;
+            for (field, rate) in &s.fill_rates {
+                *filled.entry(field.clone()).or_insert(0.0) += rate * s.members as f64;
+            }
+        }
+        let members: usize = result.sources.iter().map(|s| s.members).sum();
+
+        Self {
+            started_at: result.started_at,
+            elapsed_ms: result.elapsed.as_millis() as u64,
+            parser_version: PARSER_VERSION.to_string(),
+            sources: result.sources.iter().map(|s| s.source.clone()).collect(),
+            members,
+            houses: houses.into_iter().map(|(house, members)| HouseCount { house, members }).collect(),
+            fill_rates: filled.into_iter().map(|(field, total)| (field, total / members.max(1) as f64)).collect(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Option<Self> {
+        let data = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&data).ok()
+    }
+
+    pub fn write(&self, path: &Path, style: JsonStyle) -> io::Result<()> {
+        let file = File::create(path)?;
+        style.to_writer(file, self)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::Loader;
+    use crate::test_support::{commons, MockResponse, MockServer, TempDir};
+    use std::time::Duration;
+
+    #[test]
+    fn manifest_summarises_the_run() {
+        let server = MockServer::new(vec![MockResponse::ok(commons())]);
+        let dir = TempDir::new("manifest");
+
+        let result = Loader::new()
+            .api_base(&format!("{}/Members/Query/", server.url()))
+            .request_delay(Duration::from_millis(0))
+            .min_members(Some(0))
+            .sqlite(&dir.path().join("members.db"))
+            .build()
+            .unwrap()
+            .run()
+            .unwrap();
+        let manifest = RunManifest::new(&result);
+
+        assert_eq!(manifest.started_at, result.started_at);
+        assert_eq!(manifest.parser_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(manifest.sources, [result.sources[0].source.clone()]);
+        assert!(manifest.sources[0].starts_with(server.url()));
+        assert_eq!(manifest.members, 7);
+        let houses: Vec<(&str, usize)> = manifest.houses.iter().map(|h| (h.house.as_str(), h.members)).collect();
+        assert_eq!(houses, [("Commons", 7)]);
+
+        let path = dir.path().join("manifest.json");
+        manifest.write(&path, JsonStyle::Compact).unwrap();
+        let written: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["members"], 7);
+        assert_eq!(written["houses"], serde_json::json!([{"house": "Commons", "members": 7}]));
+    }
+}
@@ -0,0 +1,50 @@
+use crate::csv;
+use crate::members::Members;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+fn cell(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|").replace(&['\r', '\n'][..], " ")
+}
+
+fn table_row(cells: &[String]) -> String {
+    format!("| {} |\n", cells.join(" | "))
+}
+
+pub fn to_markdown(members: &Members, redact_contact: bool) -> String {
+    let columns = csv::columns(redact_contact);
+    let mut out = table_row(&columns.iter().map(|c| c.to_string()).collect::<Vec<_>>());
+    out.push_str(&table_row(&columns.iter().map(|_| "---".to_string()).collect::<Vec<_>>()));
+
+    for member in &members.members {
+        out.push_str(&table_row(&csv::row(member, redact_contact).iter().map(|v| cell(v)).collect::<Vec<_>>()));
+    }
+
+    out
+}
+
+pub fn write_markdown(path: &Path, members: &Members, redact_contact: bool) -> io::Result<()> {
+    fs::write(path, to_markdown(members, redact_contact))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::parse::parse_addresses_xml;
+    use crate::test_support::document;
+
+    #[test]
+    fn members_render_to_an_escaped_table() {
+        let mut members = parse_addresses_xml(&document(&[(172, ""), (4212, "")]), &Config::default()).unwrap();
+        members.members[1].name = "Smith | Jones".to_string();
+
+        assert_eq!(to_markdown(&members, true), concat!(
+            "| member_id | name | party | house | constituency | constituency_id | twitter | facebook |\n",
+            "| --- | --- | --- | --- | --- | --- | --- | --- |\n",
+            "| 172 | Member 172 |  | Unknown |  |  |  |  |\n",
+            "| 4212 | Smith \\| Jones |  | Unknown |  |  |  |  |\n",
+        ));
+    }
+}
@@ -0,0 +1,1330 @@
+use chrono::{DateTime, Duration, Utc};
+use fnv::FnvHasher;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::convert::Infallible;
+use std::fmt;
+use std::hash::Hasher;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub enum House {
+    Commons,
+    Lords,
+    Unknown,
+}
+
+impl FromStr for House {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "Commons" => House::Commons,
+            "Lords" => House::Lords,
+            _ => House::Unknown
+        })
+    }
+}
+
+impl fmt::Display for House {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            House::Commons => "Commons",
+            House::Lords => "Lords",
+            House::Unknown => "Unknown",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdRange {
+    pub from: u32,
+    pub to: u32,
+}
+
+impl IdRange {
+    pub fn contains(&self, id: u32) -> bool {
+        self.from <= id && id <= self.to
+    }
+}
+
+impl FromStr for IdRange {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (from, to) = s.split_once('-').unwrap_or((s, s));
+        let from = if from.is_empty() { 0 } else { from.trim().parse().map_err(|_| ())? };
+        let to = if to.is_empty() { u32::MAX } else { to.trim().parse().map_err(|_| ())? };
+        if from > to {
+            return Err(());
+        }
+        Ok(Self { from, to })
+    }
+}
+
+impl fmt::Display for IdRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.from, self.to)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortOrder {
+    #[default]
+    ById,
+    ByName,
+    ByParty,
+}
+
+impl FromStr for SortOrder {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "by-id" => Ok(SortOrder::ById),
+            "by-name" => Ok(SortOrder::ByName),
+            "by-party" => Ok(SortOrder::ByParty),
+            _ => Err(())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdditionalData {
+    Addresses,
+    Parties,
+    Constituencies,
+    Interests,
+    GovernmentPosts,
+    OppositionPosts,
+    PreferredNames,
+}
+
+impl From<AdditionalData> for &str {
+    fn from(data: AdditionalData) -> Self {
+        match data {
+            AdditionalData::Addresses => "Addresses",
+            AdditionalData::Parties => "Parties",
+            AdditionalData::Constituencies => "Constituencies",
+            AdditionalData::Interests => "Interests",
+            AdditionalData::GovernmentPosts => "GovernmentPosts",
+            AdditionalData::OppositionPosts => "OppositionPosts",
+            AdditionalData::PreferredNames => "PreferredNames",
+        }
+    }
+}
+
+type FieldCheck = fn(&Member) -> bool;
+
+const FILL_RATE_FIELDS: &[(&str, FieldCheck)] = &[
+    ("name", |m| !m.name.is_empty()),
+    ("full_title", |m| m.full_title.is_some()),
+    ("list_as", |m| m.list_as.is_some()),
+    ("known_as", |m| m.known_as.is_some()),
+    ("party", |m| !m.party.is_empty()),
+    ("constituency", |m| !m.constituency.is_empty()),
+    ("constituency_id", |m| m.constituency_id.is_some()),
+    ("twitter", |m| m.twitter_handle().is_some()),
+    ("facebook", |m| m.facebook.is_some()),
+    ("email", |m| m.email().is_some()),
+    ("parties", |m| !m.parties.is_empty()),
+];
+
+const CONTACT_FIELDS: &[&str] = &["constituency_email", "parliamentary_email"];
+
+pub fn redact_contact(value: &mut serde_json::Value) {
+    if let Some(object) = value.as_object_mut() {
+        for field in CONTACT_FIELDS {
+            object.remove(*field);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Members {
+    pub members: Vec<Member>,
+    pub unknown_elements: BTreeSet<String>,
+    pub empty_social_addresses: usize,
+    pub skipped: Vec<SkippedMember>,
+    pub more_results: bool,
+    /// Every `<Member>` read, including those skipped or outside `Config::member_ids`.
+    pub seen: usize,
+    index: RefCell<Option<MemberIndex>>,
+}
+
+/// Positions of members by id, along with the length of `members` when it was built so pushes
+/// straight onto the Vec are noticed.
+#[derive(Debug)]
+struct MemberIndex {
+    len: usize,
+    positions: HashMap<u32, usize>,
+}
+
+impl MemberIndex {
+    fn build(members: &[Member]) -> Self {
+        MemberIndex {
+            len: members.len(),
+            positions: members.iter().enumerate().map(|(i, m)| (m.id, i)).collect(),
+        }
+    }
+
+    /// `None` if the index no longer matches `members` and has to be rebuilt.
+    fn lookup(&self, members: &[Member], id: u32) -> Option<Option<usize>> {
+        if self.len != members.len() {
+            return None;
+        }
+        match self.positions.get(&id) {
+            Some(&i) if members[i].id == id => Some(Some(i)),
+            Some(_) => None,
+            None => Some(None)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PartyCount {
+    pub party: String,
+    pub members: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedMember {
+    pub id: Option<u32>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TwitterName {
+    pub id: u32,
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SharedTwitterHandle {
+    pub handle: String,
+    pub members: Vec<TwitterName>,
+}
+
+impl IntoIterator for Members {
+    type Item = Member;
+    type IntoIter = std::vec::IntoIter<Member>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.members.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Members {
+    type Item = &'a Member;
+    type IntoIter = std::slice::Iter<'a, Member>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.members.iter()
+    }
+}
+
+impl Default for Members {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Members {
+    pub fn new() -> Self {
+        Members {
+            members: vec![],
+            unknown_elements: BTreeSet::new(),
+            empty_social_addresses: 0,
+            skipped: vec![],
+            more_results: false,
+            seen: 0,
+            index: RefCell::new(None),
+        }
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Member> {
+        self.members.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub fn by_id(&self, id: u32) -> Option<&Member> {
+        let cached = self.index.borrow().as_ref().and_then(|index| index.lookup(&self.members, id));
+        let position = match cached {
+            Some(position) => position,
+            None => {
+                let index = MemberIndex::build(&self.members);
+                let position = index.positions.get(&id).copied();
+                *self.index.borrow_mut() = Some(index);
+                position
+            }
+        };
+        position.map(|i| &self.members[i])
+    }
+
+    pub fn truncate(&mut self, limit: usize) -> bool {
+        let truncated = self.members.len() > limit;
+        self.members.truncate(limit);
+        truncated
+    }
+
+    pub fn extend(&mut self, other: Members) {
+        self.members.extend(other.members);
+        self.index.get_mut().take();
+        self.unknown_elements.extend(other.unknown_elements);
+        self.empty_social_addresses += other.empty_social_addresses;
+        self.skipped.extend(other.skipped);
+        self.more_results = other.more_results;
+        self.seen += other.seen;
+    }
+
+    pub fn merge_roles(&mut self, roles: &Members) -> usize {
+        let mut merged = 0;
+        for member in &mut self.members {
+            if let Some(roles) = roles.by_id(member.id) {
+                member.government_posts = roles.government_posts.clone();
+                merged += 1;
+            }
+        }
+        merged
+    }
+
+    pub fn without_twitter(&self) -> Vec<&Member> {
+        self.members.iter().filter(|m| m.twitter_handle().is_none()).collect()
+    }
+
+    pub fn twitter_names(&self) -> Vec<TwitterName> {
+        self.members.iter().filter(|m| m.twitter_is_name()).map(|m| TwitterName {
+            id: m.id,
+            name: m.name.clone(),
+            value: m.twitter.clone().unwrap_or_default(),
+        }).collect()
+    }
+
+    pub fn shared_twitter_handles(&self) -> Vec<SharedTwitterHandle> {
+        let mut by_handle: BTreeMap<String, SharedTwitterHandle> = BTreeMap::new();
+        for member in &self.members {
+            let handle = match member.twitter_handle() {
+                Some(handle) => handle,
+                None => continue
+            };
+            by_handle.entry(handle.to_lowercase()).or_insert_with(|| SharedTwitterHandle {
+                handle,
+                members: vec![],
+            }).members.push(TwitterName {
+                id: member.id,
+                name: member.name.clone(),
+                value: member.twitter.clone().unwrap_or_default(),
+            });
+        }
+        by_handle.into_values().filter(|s| s.members.len() > 1).collect()
+    }
+
+    pub fn retain_active_since(&mut self, date: DateTime<Utc>) -> usize {
+        let before = self.members.len();
+        self.members.retain(|m| m.active_since(date));
+        self.index.get_mut().take();
+        before - self.members.len()
+    }
+
+    pub fn sort(&mut self, order: SortOrder) {
+        match order {
+            SortOrder::ById => self.members.sort_by_key(|m| m.id),
+            SortOrder::ByName => self.members.sort_by(|a, b| a.sort_name().cmp(b.sort_name()).then(a.id.cmp(&b.id))),
+            SortOrder::ByParty => self.members.sort_by(|a, b| a.current_party().cmp(b.current_party())
+                .then_with(|| a.sort_name().cmp(b.sort_name())).then(a.id.cmp(&b.id))),
+        }
+        self.index.get_mut().take();
+    }
+
+    pub fn retain_house(&mut self, house: House) -> usize {
+        let before = self.members.len();
+        self.members.retain(|m| m.house == house);
+        self.index.get_mut().take();
+        before - self.members.len()
+    }
+
+    pub fn retain_status(&mut self, status: &str) -> usize {
+        let before = self.members.len();
+        self.members.retain(|m| m.status.as_deref().is_some_and(|s| s.eq_ignore_ascii_case(status)));
+        self.index.get_mut().take();
+        before - self.members.len()
+    }
+
+    pub fn trim_party_history(&mut self, cutoff: DateTime<Utc>) -> usize {
+        let mut removed = 0;
+        for member in &mut self.members {
+            let before = member.parties.len();
+            member.parties.retain(|p| p.end_date.is_none_or(|end| end >= cutoff));
+            removed += before - member.parties.len();
+        }
+        removed
+    }
+
+    pub fn cap_party_history(&mut self, max: usize) -> Vec<(u32, usize)> {
+        let mut capped = vec![];
+        for member in self.members.iter_mut().filter(|m| m.parties.len() > max) {
+            capped.push((member.id, member.parties.len()));
+            // Merged party histories are newest first, so keep the head
+            member.parties.truncate(max);
+        }
+        capped
+    }
+
+    pub fn retain_sworn(&mut self) -> usize {
+        let before = self.members.len();
+        self.members.retain(|m| m.oath_date.is_some());
+        self.index.get_mut().take();
+        before - self.members.len()
+    }
+
+    pub fn retain_ids(&mut self, include: &HashSet<u32>, exclude: &HashSet<u32>) -> usize {
+        let before = self.members.len();
+        self.members.retain(|m| (include.is_empty() || include.contains(&m.id)) && !exclude.contains(&m.id));
+        self.index.get_mut().take();
+        before - self.members.len()
+    }
+
+    pub fn retain_changed(&mut self, changed: &HashSet<u32>) -> usize {
+        let before = self.members.len();
+        self.members.retain(|m| changed.contains(&m.id));
+        self.index.get_mut().take();
+        before - self.members.len()
+    }
+
+    pub fn skip_until(&mut self, id: u32) -> Option<usize> {
+        let position = self.members.iter().position(|m| m.id == id)?;
+        self.members.drain(..position);
+        self.index.get_mut().take();
+        Some(position)
+    }
+
+    pub fn find(&self, query: &str) -> Vec<&Member> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return vec![];
+        }
+
+        let mut matches = self.members.iter()
+            .filter_map(|m| match_rank(m, &query).map(|r| (r, m)))
+            .collect::<Vec<_>>();
+        matches.sort_by(|(a, m), (b, n)| a.cmp(b).then_with(|| m.name.cmp(&n.name)));
+        matches.into_iter().map(|(_, m)| m).collect()
+    }
+
+    pub fn party_counts(&self) -> Vec<PartyCount> {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for member in &self.members {
+            *counts.entry(member.current_party()).or_insert(0) += 1;
+        }
+
+        let mut counts = counts.into_iter()
+            .map(|(party, members)| PartyCount { party: party.to_string(), members })
+            .collect::<Vec<_>>();
+        counts.sort_by(|a, b| b.members.cmp(&a.members).then_with(|| a.party.cmp(&b.party)));
+        counts
+    }
+
+    pub fn by_party(&self) -> BTreeMap<String, Members> {
+        let mut parties: BTreeMap<String, Members> = BTreeMap::new();
+        for member in &self.members {
+            parties.entry(member.current_party().to_string()).or_default().members.push(member.clone());
+        }
+        parties
+    }
+
+    pub fn twitter_coverage(&self) -> f64 {
+        if self.members.is_empty() {
+            return 100.0;
+        }
+
+        100.0 * (self.members.len() - self.without_twitter().len()) as f64 / self.members.len() as f64
+    }
+
+    pub fn fill_rates(&self) -> BTreeMap<String, f64> {
+        let mut rates = BTreeMap::new();
+        if self.members.is_empty() {
+            return rates;
+        }
+        for (field, filled) in FILL_RATE_FIELDS {
+            let count = self.members.iter().filter(|m| filled(m)).count();
+            rates.insert(field.to_string(), 100.0 * count as f64 / self.members.len() as f64);
+        }
+        rates
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Member {
+    pub id: u32,
+    pub alt_ids: HashMap<String, String>,
+    pub name: String,
+    pub full_title: Option<String>,
+    pub list_as: Option<String>,
+    pub known_as: Option<String>,
+    pub former_names: Vec<String>,
+    pub party: String,
+    pub house: House,
+    pub constituency: String,
+    pub normalized_constituency: String,
+    pub constituency_id: Option<u32>,
+    pub status: Option<String>,
+    pub twitter: Option<String>,
+    pub twitter_official: Option<String>,
+    pub facebook: Option<String>,
+    pub constituency_email: Option<String>,
+    pub parliamentary_email: Option<String>,
+    pub parties: Vec<Party>,
+    pub whip_removed: Option<DateTime<Utc>>,
+    pub house_start_date: Option<DateTime<Utc>>,
+    pub house_end_date: Option<DateTime<Utc>>,
+    pub oath_date: Option<DateTime<Utc>>,
+    pub seats: Vec<SeatSpell>,
+    pub interests: Vec<Interest>,
+    pub government_posts: Vec<GovernmentPost>,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+impl Default for Member {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Member {
+    pub fn new() -> Self {
+        Self {
+            id: 0,
+            alt_ids: HashMap::new(),
+            name: "".to_string(),
+            full_title: None,
+            list_as: None,
+            known_as: None,
+            former_names: vec![],
+            party: "".to_string(),
+            house: House::Unknown,
+            constituency: "".to_string(),
+            normalized_constituency: "".to_string(),
+            constituency_id: None,
+            status: None,
+            twitter: None,
+            twitter_official: None,
+            facebook: None,
+            constituency_email: None,
+            parliamentary_email: None,
+            parties: vec![],
+            whip_removed: None,
+            house_start_date: None,
+            house_end_date: None,
+            oath_date: None,
+            seats: vec![],
+            interests: vec![],
+            government_posts: vec![],
+            modified: None,
+        }
+    }
+
+    /// Whether any party or seat spell was still open on or after `date`. A member without any
+    /// spells gives no evidence of sitting then, so counts as inactive.
+    pub fn active_since(&self, date: DateTime<Utc>) -> bool {
+        if let Some(end) = self.house_end_date {
+            return end >= date;
+        }
+        self.parties.iter().map(|p| p.end_date)
+            .chain(self.seats.iter().map(|s| s.end_date))
+            .any(|end| end.is_none_or(|end| end >= date))
+    }
+
+    pub fn current_party(&self) -> &str {
+        self.current_party_at(Utc::now())
+    }
+
+    pub fn current_party_at(&self, as_of: DateTime<Utc>) -> &str {
+        match self.current_party_spell_at(as_of) {
+            Some(p) => &p.name,
+            None => &self.party
+        }
+    }
+
+    pub fn current_party_spell(&self) -> Option<&Party> {
+        self.current_party_spell_at(Utc::now())
+    }
+
+    pub fn current_party_spell_at(&self, as_of: DateTime<Utc>) -> Option<&Party> {
+        self.parties.iter()
+            .filter(|p| p.start_date <= as_of && p.end_date.is_none_or(|end| end > as_of))
+            .max_by_key(|p| p.start_date)
+    }
+
+    pub fn current_party_since(&self) -> Option<DateTime<Utc>> {
+        self.current_party_since_at(Utc::now())
+    }
+
+    pub fn current_party_since_at(&self, as_of: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.current_party_spell_at(as_of).map(|p| p.start_date)
+    }
+
+    pub fn sort_name(&self) -> &str {
+        self.list_as.as_deref().unwrap_or(&self.name)
+    }
+
+    pub fn preferred_name(&self) -> &str {
+        self.known_as.as_deref().unwrap_or(&self.name)
+    }
+
+    pub fn open_parties(&self) -> Vec<&Party> {
+        self.parties.iter().filter(|p| p.end_date.is_none()).collect()
+    }
+
+    pub fn tenure_in_house(&self, house: House) -> Duration {
+        self.tenure_in_house_at(house, Utc::now())
+    }
+
+    pub fn tenure_in_house_at(&self, house: House, now: DateTime<Utc>) -> Duration {
+        let span = |start: DateTime<Utc>, end: Option<DateTime<Utc>>| (end.unwrap_or(now).min(now) - start).max(Duration::zero());
+
+        if house == House::Commons && !self.seats.is_empty() {
+            // Seats can overlap during boundary changes, so only count each day once
+            let mut seats = self.seats.iter().collect::<Vec<_>>();
+            seats.sort_by_key(|s| s.start_date);
+            let mut total = Duration::zero();
+            let mut covered: Option<DateTime<Utc>> = None;
+            for s in seats {
+                let start = covered.map_or(s.start_date, |c| c.max(s.start_date));
+                total += span(start, s.end_date);
+                let end = s.end_date.unwrap_or(now).min(now);
+                covered = Some(covered.map_or(end, |c| c.max(end)));
+            }
+            return total;
+        }
+        match self.house_start_date {
+            Some(start) if self.house == house => span(start, self.house_end_date),
+            _ => Duration::zero()
+        }
+    }
+
+    pub fn total_tenure(&self) -> Duration {
+        self.total_tenure_at(Utc::now())
+    }
+
+    pub fn total_tenure_at(&self, now: DateTime<Utc>) -> Duration {
+        self.tenure_in_house_at(House::Commons, now) + self.tenure_in_house_at(House::Lords, now)
+    }
+
+    pub fn current_seats(&self) -> Vec<&SeatSpell> {
+        let is_member_from = |s: &SeatSpell| (s.id.is_some() && s.id == self.constituency_id) || s.name == self.constituency;
+        let mut seats = self.seats.iter().filter(|s| s.end_date.is_none()).collect::<Vec<_>>();
+        seats.sort_by(|a, b| is_member_from(b).cmp(&is_member_from(a))
+            .then(b.start_date.cmp(&a.start_date))
+            .then(a.name.cmp(&b.name)));
+        seats
+    }
+
+    pub fn current_constituencies(&self) -> Vec<&str> {
+        self.current_seats().into_iter().map(|s| s.name.as_str()).collect()
+    }
+
+    pub fn same_day_switches(&self) -> Vec<(&Party, &Party)> {
+        let mut switches = vec![];
+        for (i, a) in self.parties.iter().enumerate() {
+            for b in &self.parties[i + 1..] {
+                if a.start_date == b.start_date && a.name != b.name {
+                    switches.push((a, b));
+                }
+            }
+        }
+        switches
+    }
+
+    pub fn had_whip_removed(&self) -> bool {
+        self.whip_removed.is_some()
+    }
+
+    pub fn party_history(&self) -> String {
+        self.parties.iter().map(|p| format!("{} ({} to {})", p.name, p.start_date.format("%Y-%m-%d"),
+                                             p.end_date.map_or("present".to_string(), |d| d.format("%Y-%m-%d").to_string())))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    pub fn flattened(&self) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).expect("Invalid json");
+        if let Some(object) = value.as_object_mut() {
+            object.remove("parties");
+            object.insert("current_party".to_string(), self.current_party().into());
+            object.insert("party_history".to_string(), self.party_history().into());
+        }
+        value
+    }
+
+    pub fn current_roles(&self) -> Vec<&str> {
+        self.government_posts.iter().filter(|p| p.end_date.is_none()).map(|p| p.name.as_str()).collect()
+    }
+
+    pub fn is_minister(&self) -> bool {
+        self.government_posts.iter().any(|p| p.end_date.is_none() && !p.opposition)
+    }
+
+    pub fn email(&self) -> Option<&str> {
+        self.parliamentary_email.as_deref().or(self.constituency_email.as_deref())
+    }
+
+    pub fn twitter_handle(&self) -> Option<String> {
+        self.twitter.as_ref().and_then(|t| normalize_twitter_handle(t))
+    }
+
+    pub fn twitter_official_handle(&self) -> Option<String> {
+        self.twitter_official.as_ref().and_then(|t| normalize_twitter_handle(t))
+    }
+
+    pub fn twitter_is_name(&self) -> bool {
+        match &self.twitter {
+            Some(t) if self.twitter_handle().is_none() => looks_like_name(t, &self.name),
+            _ => false
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Party {
+    pub name: String,
+    pub original_name: String,
+    pub start_date: DateTime<Utc>,
+    pub end_date: Option<DateTime<Utc>>,
+}
+
+impl Default for Party {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Party {
+    pub fn new() -> Self {
+        Self {
+            name: "".to_string(),
+            original_name: "".to_string(),
+            start_date: Utc::now(),
+            end_date: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SeatSpell {
+    pub id: Option<u32>,
+    pub name: String,
+    pub start_date: DateTime<Utc>,
+    pub end_date: Option<DateTime<Utc>>,
+}
+
+impl Default for SeatSpell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SeatSpell {
+    pub fn new() -> Self {
+        Self {
+            id: None,
+            name: "".to_string(),
+            start_date: Utc::now(),
+            end_date: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Interest {
+    pub category: String,
+    pub description: String,
+    pub created: Option<DateTime<Utc>>,
+}
+
+impl Default for Interest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interest {
+    pub fn new() -> Self {
+        Self {
+            category: "".to_string(),
+            description: "".to_string(),
+            created: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct GovernmentPost {
+    pub name: String,
+    pub start_date: DateTime<Utc>,
+    pub end_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub opposition: bool,
+}
+
+impl Default for GovernmentPost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GovernmentPost {
+    pub fn new() -> Self {
+        Self {
+            name: "".to_string(),
+            start_date: Utc::now(),
+            end_date: None,
+            opposition: false,
+        }
+    }
+}
+
+fn match_rank(member: &Member, query: &str) -> Option<u8> {
+    let name = member.name.to_lowercase();
+    let constituency = member.constituency.to_lowercase();
+
+    if name == query || constituency == query {
+        Some(0)
+    } else if name.starts_with(query) || name.split_whitespace().any(|w| w.starts_with(query)) {
+        Some(1)
+    } else if name.contains(query) {
+        Some(2)
+    } else if constituency.contains(query) {
+        Some(3)
+    } else if query.split_whitespace().all(|w| name.contains(w) || constituency.contains(w)) {
+        Some(4)
+    } else if member.former_names.iter().any(|n| n.to_lowercase().contains(query)) {
+        Some(5)
+    } else {
+        None
+    }
+}
+
+const XID_PREFIX: &str = "uk-parliament/member/";
+
+pub fn member_xid(member: &Member) -> String {
+    xid_for_id(member.id)
+}
+
+pub fn xid_for_id(id: u32) -> String {
+    format!("{}{}", XID_PREFIX, id)
+}
+
+fn hash_field(hasher: &mut FnvHasher, value: &impl Serialize) {
+    hasher.write(&serde_json::to_vec(value).expect("field is serializable"));
+    hasher.write_u8(0);
+}
+
+type FingerprintField = fn(&Member, &mut FnvHasher);
+
+// Hashed in this order whatever order the config lists them in
+pub const FINGERPRINT_FIELDS: &[(&str, FingerprintField)] = &[
+    ("id", |m, h| hash_field(h, &m.id)),
+    ("alt_ids", |m, h| hash_field(h, &m.alt_ids.iter().collect::<BTreeMap<_, _>>())),
+    ("name", |m, h| hash_field(h, &m.name)),
+    ("full_title", |m, h| hash_field(h, &m.full_title)),
+    ("list_as", |m, h| hash_field(h, &m.list_as)),
+    ("known_as", |m, h| hash_field(h, &m.known_as)),
+    ("former_names", |m, h| hash_field(h, &m.former_names)),
+    ("house", |m, h| hash_field(h, &m.house.to_string())),
+    ("constituency", |m, h| hash_field(h, &m.constituency)),
+    ("normalized_constituency", |m, h| hash_field(h, &m.normalized_constituency)),
+    ("constituency_id", |m, h| hash_field(h, &m.constituency_id)),
+    ("constituencies", |m, h| hash_field(h, &m.current_constituencies())),
+    ("oath_date", |m, h| hash_field(h, &m.oath_date)),
+    ("status", |m, h| hash_field(h, &m.status)),
+    ("twitter", |m, h| hash_field(h, &m.twitter_handle())),
+    ("twitter_official", |m, h| hash_field(h, &m.twitter_official_handle())),
+    ("whip_removed", |m, h| hash_field(h, &m.whip_removed)),
+    ("constituency_email", |m, h| hash_field(h, &m.constituency_email)),
+    ("parliamentary_email", |m, h| hash_field(h, &m.parliamentary_email)),
+    ("current_party", |m, h| hash_field(h, &m.current_party())),
+    ("parties", |m, h| {
+        for party in &m.parties {
+            hash_field(h, &(&party.name, party.start_date, party.end_date));
+        }
+    }),
+    ("facebook", |m, h| hash_field(h, &m.facebook)),
+    ("modified", |m, h| hash_field(h, &m.modified)),
+];
+
+const VOLATILE_FINGERPRINT_FIELDS: &[&str] = &["modified"];
+
+pub fn default_fingerprint_fields() -> Vec<String> {
+    FINGERPRINT_FIELDS.iter()
+        .map(|(name, _)| *name)
+        .filter(|name| !VOLATILE_FINGERPRINT_FIELDS.contains(name))
+        .map(str::to_string)
+        .collect()
+}
+
+/// A hash of the chosen `fields`, stored alongside each member so unchanged ones can be skipped.
+/// FNV over the fields' JSON so the value doesn't shift between toolchains or runs.
+pub fn fingerprint(member: &Member, fields: &[String]) -> u64 {
+    let mut hasher = FnvHasher::default();
+    for (name, hash) in FINGERPRINT_FIELDS {
+        if fields.iter().any(|f| f == name) {
+            hash(member, &mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+fn looks_like_name(value: &str, name: &str) -> bool {
+    let value = value.trim();
+    if value.eq_ignore_ascii_case(name.trim()) {
+        return true;
+    }
+    value.contains(' ') && value.chars().all(|c| c.is_alphabetic() || c == ' ' || c == '-' || c == '\'' || c == '.')
+}
+
+pub fn normalize_twitter_handle(value: &str) -> Option<String> {
+    let mut handle = value.trim();
+    for prefix in &["https://", "http://", "www.", "mobile.", "twitter.com/", "@"] {
+        if handle.get(..prefix.len()).is_some_and(|p| p.eq_ignore_ascii_case(prefix)) {
+            handle = &handle[prefix.len()..];
+        }
+    }
+    let handle = handle.split(['?', '/']).next().unwrap_or("");
+
+    if handle.is_empty() || handle.len() > 15 || !handle.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return None;
+    }
+
+    Some(handle.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::parse::{parse_addresses_xml, parse_date};
+    use crate::test_support::{commons, document};
+
+    #[test]
+    fn house_round_trips_through_strings() {
+        for name in &["Commons", "Lords", "Unknown"] {
+            let house: House = name.parse().unwrap();
+            assert_eq!(house.to_string(), *name);
+        }
+        assert!(matches!("Commons".parse(), Ok(House::Commons)));
+        assert!(matches!("Lords".parse(), Ok(House::Lords)));
+        assert!(matches!("commons".parse(), Ok(House::Unknown)));
+    }
+
+    fn spell(start: &str, end: Option<&str>) -> Party {
+        let mut party = Party::new();
+        party.name = "Labour".to_string();
+        party.start_date = parse_date(start).unwrap();
+        party.end_date = end.map(|end| parse_date(end).unwrap());
+        party
+    }
+
+    #[test]
+    fn active_since_checks_spell_end_dates() {
+        let mut members = Members::new();
+        for (id, spells) in [
+            (1, vec![spell("1987-06-11", None)]),
+            (2, vec![spell("2019-12-12", None)]),
+            (3, vec![spell("2001-06-07", Some("2010-05-06"))]),
+            (4, vec![spell("2010-05-06", Some("2017-06-08")), spell("2005-05-05", Some("2010-05-06"))]),
+            (5, vec![]),
+        ] {
+            let mut member = Member::new();
+            member.id = id;
+            member.parties = spells;
+            members.members.push(member);
+        }
+
+        assert_eq!(members.retain_active_since(parse_date("2015-05-07").unwrap()), 2);
+        let ids: Vec<u32> = members.members.iter().map(|m| m.id).collect();
+        assert_eq!(ids, [1, 2, 4]);
+    }
+
+    #[test]
+    fn coverage_counts_missing_and_invalid_handles() {
+        let mut members = Members::new();
+        for (id, twitter) in [(1, Some("https://twitter.com/HackneyAbbott")), (2, None), (3, Some("Ynys Môn MP")),
+                              (4, Some("@IanLaveryMP"))] {
+            let mut member = Member::new();
+            member.id = id;
+            member.twitter = twitter.map(str::to_string);
+            members.members.push(member);
+        }
+
+        assert_eq!(members.members[0].twitter_handle().as_deref(), Some("HackneyAbbott"));
+        let missing: Vec<u32> = members.without_twitter().iter().map(|m| m.id).collect();
+        assert_eq!(missing, [2, 3]);
+        assert_eq!(members.twitter_coverage(), 50.0);
+    }
+
+    #[test]
+    fn parties_are_counted_largest_first() {
+        let data = document(&[
+            (172, "<Party Id=\"15\">Labour</Party>"),
+            (4212, "<Party Id=\"4\">Conservative</Party>"),
+            (1524, "<Party Id=\"47\">Speaker</Party>"),
+            (4005, "<Party Id=\"15\">Labour</Party>"),
+            (4520, "<Party Id=\"4\">Conservative</Party><Parties>\
+                <Party><Name>Independent</Name><StartDate>2019-09-03T00:00:00</StartDate></Party>\
+                <Party><Name>Conservative</Name><StartDate>2015-05-07T00:00:00</StartDate><EndDate>2019-09-03T00:00:00</EndDate></Party>\
+                </Parties>"),
+            (4064, "<Party Id=\"22\">Plaid Cymru</Party>"),
+            (4138, "<Party Id=\"15\">Labour</Party>"),
+        ]);
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+
+        let counts = members.party_counts();
+        let counts: Vec<(&str, usize)> = counts.iter().map(|c| (c.party.as_str(), c.members)).collect();
+        assert_eq!(counts, [("Labour", 3), ("Conservative", 1), ("Independent", 1), ("Plaid Cymru", 1), ("Speaker", 1)]);
+    }
+
+    #[test]
+    fn find_ranks_closer_matches_first() {
+        let members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+        let find = |query: &str| members.find(query).iter().map(|m| m.id).collect::<Vec<_>>();
+
+        assert_eq!(find("Anna Soubry"), [4212]);
+        assert_eq!(find("broxtowe"), [4212]);
+        assert_eq!(find("  IAN lavery "), [4005]);
+        assert_eq!(find("an"), [4212, 4005, 172]);
+        assert_eq!(find("buck"), [1524]);
+        assert_eq!(find("roberts dwyfor"), [4064]);
+        assert!(find("Westminster").is_empty());
+        assert!(find("   ").is_empty());
+    }
+
+    #[test]
+    fn members_are_filtered_by_id() {
+        let filtered = |include: &[u32], exclude: &[u32]| {
+            let mut members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+            let removed = members.retain_ids(&include.iter().copied().collect(), &exclude.iter().copied().collect());
+            (removed, members.members.iter().map(|m| m.id).collect::<Vec<_>>())
+        };
+
+        assert_eq!(filtered(&[4212, 172, 9999], &[]), (5, vec![172, 4212]));
+        assert_eq!(filtered(&[], &[1524, 4005]), (2, vec![172, 4212, 4064, 4520, 1437]));
+        assert_eq!(filtered(&[172, 4212, 1524], &[4212]), (5, vec![172, 1524]));
+        assert_eq!(filtered(&[], &[]), (0, vec![172, 4212, 1524, 4005, 4064, 4520, 1437]));
+    }
+
+    #[test]
+    fn fill_rates_cover_each_field() {
+        let members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+        let rates = members.fill_rates();
+
+        assert_eq!(rates.keys().map(String::as_str).collect::<Vec<_>>(),
+                   ["constituency", "constituency_id", "email", "facebook", "full_title", "known_as", "list_as", "name",
+                    "parties", "party", "twitter"]);
+        assert_eq!(rates["name"], 100.0);
+        assert_eq!(rates["email"], 100.0);
+        assert_eq!(rates["twitter"], 100.0 * 5.0 / 7.0);
+        assert_eq!(rates["known_as"], 100.0 / 7.0);
+        assert_eq!(rates["constituency_id"], 100.0 / 7.0);
+        assert_eq!(rates["facebook"], 0.0);
+        assert!(Members::new().fill_rates().is_empty());
+
+        let mut previous = rates.clone();
+        previous.insert("facebook".to_string(), 50.0);
+        let drops = crate::validate::fill_rate_drops(&previous, &rates, 10.0);
+        let drops: Vec<String> = drops.iter().map(ToString::to_string).collect();
+        assert_eq!(drops, ["facebook dropped from 50.0% to 0.0%"]);
+    }
+
+    #[test]
+    fn flattened_members_replace_the_party_spells() {
+        let members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+        let soubry = &members.members[1];
+        assert_eq!(soubry.parties.len(), 3);
+
+        let nested = serde_json::to_value(soubry).unwrap();
+        let flattened = soubry.flattened();
+        assert_eq!(nested["parties"].as_array().unwrap().len(), 3);
+        assert!(flattened.get("parties").is_none());
+        assert_eq!(flattened["current_party"], "The Independent Group for Change");
+        assert_eq!(flattened["party_history"], "The Independent Group for Change (2019-04-29 to present); \
+                                                The Independent Group (2019-02-20 to 2019-04-29); \
+                                                Conservative (2010-05-06 to 2019-02-20)");
+
+        let mut rest = nested.as_object().unwrap().clone();
+        rest.remove("parties");
+        let mut flat_rest = flattened.as_object().unwrap().clone();
+        flat_rest.remove("current_party");
+        flat_rest.remove("party_history");
+        assert_eq!(flat_rest, rest);
+    }
+
+    #[test]
+    fn members_are_filtered_by_status() {
+        let mut members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+
+        assert_eq!(members.retain_status("Suspended"), 6);
+        assert_eq!(members.members.iter().map(|m| m.id).collect::<Vec<_>>(), [4005]);
+    }
+
+    #[test]
+    fn names_in_the_twitter_field_are_flagged() {
+        let members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+
+        let names = members.twitter_names();
+        assert_eq!(names.iter().map(|t| (t.id, t.value.as_str())).collect::<Vec<_>>(), [(1437, "Sylvia Hermon")]);
+        let hermon = members.members.iter().find(|m| m.id == 1437).unwrap();
+        assert_eq!(hermon.twitter_handle(), None);
+        assert!(members.without_twitter().iter().any(|m| m.id == 1437));
+    }
+
+    #[test]
+    fn members_can_be_iterated_and_looked_up_by_id() {
+        let mut members = parse_addresses_xml(&document(&[(172, ""), (4212, ""), (1524, "")]), &Config::default()).unwrap();
+
+        assert_eq!(members.len(), 3);
+        assert!(!members.is_empty());
+        assert!(Members::new().is_empty());
+        assert_eq!(members.iter().map(|m| m.id).collect::<Vec<_>>(), [172, 4212, 1524]);
+        assert_eq!((&members).into_iter().filter(|m| m.id > 1000).count(), 2);
+
+        assert_eq!(members.by_id(4212).map(|m| m.name.as_str()), Some("Member 4212"));
+        assert!(members.by_id(4005).is_none());
+
+        // Changes made straight to the Vec are picked up on the next lookup.
+        members.members.push(Member { id: 4005, name: "Ian Lavery".to_string(), ..Member::new() });
+        assert_eq!(members.by_id(4005).map(|m| m.name.as_str()), Some("Ian Lavery"));
+        members.members.swap(0, 1);
+        assert_eq!(members.by_id(172).map(|m| m.id), Some(172));
+        assert_eq!(members.by_id(4212).map(|m| m.id), Some(4212));
+        members.retain_ids(&HashSet::new(), &[4212].iter().copied().collect());
+        assert!(members.by_id(4212).is_none());
+        assert_eq!(members.by_id(4005).map(|m| m.id), Some(4005));
+
+        assert_eq!(members.into_iter().map(|m| m.id).collect::<Vec<_>>(), [172, 1524, 4005]);
+    }
+
+    #[test]
+    fn the_latest_open_party_spell_is_current() {
+        let data = document(&[(4212, "<Party Id=\"4\">Conservative</Party><Parties>\
+            <Party><Name>Change UK</Name><StartDate>2019-02-18T00:00:00</StartDate><EndDate/></Party>\
+            <Party><Name>Conservative</Name><StartDate>2005-05-05T00:00:00</StartDate><EndDate/></Party>\
+            <Party><Name>Independent</Name><StartDate>2019-06-04T00:00:00</StartDate><EndDate/></Party>\
+            </Parties>")]);
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+        let member = &members.members[0];
+
+        assert_eq!(member.open_parties().len(), 3);
+        assert_eq!(member.current_party(), "Independent");
+    }
+
+    #[test]
+    fn members_are_filtered_by_house() {
+        let data = document(&[(172, "<House>Commons</House>"), (3898, "<House>Lords</House>"), (4212, "<House>Commons</House>"),
+                              (1, "")]);
+        let mut members = parse_addresses_xml(&data, &Config::default()).unwrap();
+        assert!(members.by_id(3898).is_some());
+
+        assert_eq!(members.retain_house(House::Commons), 2);
+        assert_eq!(members.iter().map(|m| m.id).collect::<Vec<_>>(), [172, 4212]);
+        assert!(members.by_id(3898).is_none());
+        assert_eq!(members.retain_house(House::Lords), 2);
+        assert!(members.is_empty());
+    }
+
+    #[test]
+    fn members_are_sorted_by_each_key() {
+        let sorted = |order: &str| {
+            let mut members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+            members.sort(order.parse().unwrap());
+            assert_eq!(members.by_id(4212).map(|m| m.id), Some(4212));
+            members.members.iter().map(|m| m.id).collect::<Vec<_>>()
+        };
+
+        assert_eq!(sorted("by-id"), [172, 1437, 1524, 4005, 4064, 4212, 4520]);
+        assert_eq!(sorted("by-name"), [172, 1524, 1437, 4005, 4064, 4212, 4520]);
+        assert_eq!(sorted("by-party"), [1437, 172, 4005, 4520, 4064, 1524, 4212]);
+        assert!("by-age".parse::<SortOrder>().is_err());
+        assert_eq!(SortOrder::default(), SortOrder::ById);
+    }
+
+    #[test]
+    fn tenure_covers_current_and_former_members() {
+        let date = |d| parse_date(d).unwrap();
+        let now = date("2020-05-07");
+
+        let mut current = Member::new();
+        current.house = House::Commons;
+        current.seats = vec![
+            SeatSpell { name: "Hammersmith".to_string(), start_date: date("2010-05-06"), end_date: Some(date("2015-03-30")), ..SeatSpell::default() },
+            SeatSpell { name: "Hammersmith".to_string(), start_date: date("2015-05-07"), end_date: None, ..SeatSpell::default() },
+        ];
+        assert_eq!(current.tenure_in_house_at(House::Commons, now).num_days(), 1789 + 1827);
+        assert_eq!(current.tenure_in_house_at(House::Lords, now).num_days(), 0);
+        assert_eq!(current.total_tenure_at(now).num_days(), 3616);
+        assert!(current.active_since(now));
+
+        let mut former = Member::new();
+        former.house = House::Lords;
+        former.house_start_date = Some(date("1997-05-01"));
+        former.house_end_date = Some(date("2019-01-01"));
+        former.parties = vec![spell("1997-05-01", None)];
+        assert_eq!(former.tenure_in_house_at(House::Lords, now).num_days(), 7915);
+        assert_eq!(former.tenure_in_house_at(House::Commons, now).num_days(), 0);
+        assert_eq!(former.total_tenure_at(now).num_days(), 7915);
+
+        // Leaving the house ends the membership even with a party spell left open.
+        assert!(former.active_since(date("2018-06-01")));
+        assert!(!former.active_since(date("2019-06-01")));
+    }
+
+    #[test]
+    fn members_without_an_oath_date_are_filtered_as_unsworn() {
+        let mut members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+        let abbott = members.by_id(172).unwrap();
+        assert_eq!(abbott.oath_date, Some(parse_date("2017-06-13").unwrap()));
+        // Soubry's OathDate is xsi:nil, not missing.
+        assert_eq!(members.by_id(4212).unwrap().oath_date, None);
+        assert!(members.skipped.is_empty());
+
+        assert_eq!(members.retain_sworn(), 6);
+        assert_eq!(members.iter().map(|m| m.id).collect::<Vec<_>>(), [172]);
+    }
+
+    #[test]
+    fn the_primary_of_overlapping_constituencies_is_chosen_deterministically() {
+        let members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+        let thomas = members.by_id(4520).unwrap();
+        // MemberFrom wins over the newer seat.
+        assert_eq!(thomas.constituency, "Harrow West");
+        assert_eq!(thomas.current_constituencies(), ["Harrow West", "Harrow North"]);
+        assert_eq!(thomas.constituency_id, Some(3480));
+
+        let seat = |name: &str, id: u32| format!("<Constituency Id=\"{}\"><Name>{}</Name><StartDate>2019-12-12T00:00:00</StartDate></Constituency>", id, name);
+        let primaries: Vec<(String, Option<u32>)> = [
+            format!("<Constituencies>{}{}</Constituencies>", seat("Harrow West", 3480), seat("Harrow North", 3501)),
+            format!("<Constituencies>{}{}</Constituencies>", seat("Harrow North", 3501), seat("Harrow West", 3480)),
+        ].iter().map(|body| {
+            let members = parse_addresses_xml(&document(&[(1, body)]), &Config::default()).unwrap();
+            let member = &members.members[0];
+            assert_eq!(member.current_constituencies(), ["Harrow North", "Harrow West"]);
+            (member.constituency.clone(), member.constituency_id)
+        }).collect();
+        assert_eq!(primaries, [("Harrow North".to_string(), Some(3501)), ("Harrow North".to_string(), Some(3501))]);
+    }
+
+    #[test]
+    fn former_names_are_kept_and_searchable() {
+        let members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+
+        let former: Vec<(u32, &[String])> = members.iter().filter(|m| !m.former_names.is_empty())
+            .map(|m| (m.id, m.former_names.as_slice())).collect();
+        assert_eq!(former, [(1437, &["Sylvia Paisley".to_string()][..])]);
+        // The current preferred name has no end date, so it isn't a former one.
+        assert_eq!(members.find("paisley").iter().map(|m| m.id).collect::<Vec<_>>(), [1437]);
+    }
+
+    #[test]
+    fn default_fingerprint_ignores_only_modified() {
+        let fields = default_fingerprint_fields();
+        let member = Member::new();
+
+        let mut touched = member.clone();
+        touched.modified = Some(Utc::now());
+        assert_eq!(fingerprint(&member, &fields), fingerprint(&touched, &fields));
+        let all: Vec<String> = FINGERPRINT_FIELDS.iter().map(|(name, _)| name.to_string()).collect();
+        assert_ne!(fingerprint(&member, &all), fingerprint(&touched, &all));
+
+        let mut changed = member.clone();
+        changed.facebook = Some("https://www.facebook.com/example".to_string());
+        assert_ne!(fingerprint(&member, &fields), fingerprint(&changed, &fields));
+
+        // The hash follows the table order, not the order fields are listed in.
+        let reversed: Vec<String> = fields.iter().rev().cloned().collect();
+        assert_eq!(fingerprint(&changed, &fields), fingerprint(&changed, &reversed));
+    }
+
+    #[test]
+    fn party_spells_closed_before_the_cutoff_are_trimmed() {
+        let data = document(&[(4212, "<Parties>\
+            <Party><Name>Conservative</Name><StartDate>2005-05-05T00:00:00</StartDate><EndDate>2019-02-20T00:00:00</EndDate></Party>\
+            <Party><Name>The Independent Group</Name><StartDate>2019-02-20T00:00:00</StartDate><EndDate>2019-06-01T00:00:00</EndDate></Party>\
+            <Party><Name>Change UK</Name><StartDate>2019-06-01T00:00:00</StartDate><EndDate/></Party>\
+            </Parties>"), (1437, "<Party>Independent</Party>")]);
+        let mut members = parse_addresses_xml(&data, &Config::default()).unwrap();
+
+        assert_eq!(members.trim_party_history(parse_date("2019-03-01").unwrap()), 1);
+        let soubry = members.by_id(4212).unwrap();
+        assert_eq!(soubry.parties.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(), ["Change UK", "The Independent Group"]);
+        assert_eq!(soubry.current_party(), "Change UK");
+        assert_eq!(members.by_id(1437).unwrap().current_party(), "Independent");
+
+        assert_eq!(members.trim_party_history(parse_date("2030-01-01").unwrap()), 1);
+        assert_eq!(members.by_id(4212).unwrap().parties.len(), 1);
+    }
+
+    #[test]
+    fn members_sharing_a_twitter_handle_are_reported() {
+        let twitter = |address: &str| format!("<Addresses><Address><Type>Twitter</Type><Address1>{}</Address1></Address></Addresses>", address);
+        let data = document(&[
+            (172, &twitter("@HackneyAbbott")),
+            (4212, &twitter("https://twitter.com/HACKNEYABBOTT")),
+            (1524, &twitter("@SpeakerBercow")),
+            (4005, ""),
+        ]);
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+
+        let shared = members.shared_twitter_handles();
+        assert_eq!(shared.len(), 1);
+        assert_eq!(shared[0].handle.to_lowercase(), "hackneyabbott");
+        assert_eq!(shared[0].members.iter().map(|t| t.id).collect::<Vec<_>>(), [172, 4212]);
+
+        let members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+        assert!(members.shared_twitter_handles().is_empty());
+    }
+
+    #[test]
+    fn the_current_party_is_derived_as_of_a_date() {
+        let mut member = Member::new();
+        member.party = "Independent".to_string();
+        member.parties = vec![
+            Party { name: "Conservative".to_string(), ..spell("2005-05-05", Some("2019-02-20")) },
+            Party { name: "Change UK".to_string(), ..spell("2019-02-20", Some("2019-06-04")) },
+            Party { name: "Liberal Democrat".to_string(), ..spell("2019-06-04", None) },
+        ];
+
+        let as_of = |date| parse_date(date).unwrap();
+        assert_eq!(member.current_party_at(as_of("2010-01-01")), "Conservative");
+        assert_eq!(member.current_party_at(as_of("2019-03-01")), "Change UK");
+        assert_eq!(member.current_party_since_at(as_of("2019-03-01")), Some(as_of("2019-02-20")));
+        assert_eq!(member.current_party(), "Liberal Democrat");
+        assert_eq!(member.current_party_since(), Some(as_of("2019-06-04")));
+        assert_eq!(member.current_party_at(as_of("2000-01-01")), "Independent");
+        assert_eq!(member.current_party_since_at(as_of("2000-01-01")), None);
+    }
+
+    #[test]
+    fn capping_party_history_keeps_the_newest_spells() {
+        let data = document(&[(4212, "<Parties>\
+            <Party><Name>Conservative</Name><StartDate>2010-05-06T00:00:00</StartDate><EndDate>2019-02-20T00:00:00</EndDate></Party>\
+            <Party><Name>Change UK</Name><StartDate>2019-02-20T00:00:00</StartDate><EndDate>2019-06-04T00:00:00</EndDate></Party>\
+            <Party><Name>The Independent Group for Change</Name><StartDate>2019-06-04T00:00:00</StartDate><EndDate/></Party>\
+            </Parties>"), (172, "")]);
+        let mut members = parse_addresses_xml(&data, &Config::default()).unwrap();
+
+        assert_eq!(members.cap_party_history(2), [(4212, 3)]);
+        let kept: Vec<&str> = members.by_id(4212).unwrap().parties.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(kept, ["The Independent Group for Change", "Change UK"]);
+        assert!(members.cap_party_history(2).is_empty());
+    }
+}
@@ -0,0 +1,1840 @@
+use crate::config::Config;
+use crate::members::{GovernmentPost, House, Interest, Member, Members, Party, SeatSpell, SkippedMember};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+use unicode_normalization::UnicodeNormalization;
+use xml::attribute::OwnedAttribute;
+use xml::escape::{escape_str_attribute, escape_str_pcdata};
+use xml::name::OwnedName;
+use xml::reader::{EventReader, XmlEvent};
+
+const MIN_PARALLEL_INPUT: usize = 1024 * 1024;
+
+const KNOWN_IGNORED_ELEMENTS: &[&str] = &[
+    "LayingMinisterName", "DateOfBirth", "DateOfDeath", "Gender",
+    "IsPhysical", "Note", "Address2", "Address3", "Address4", "Address5",
+    "Postcode", "Phone", "Fax", "OtherAddress",
+    "SubType", "Notes",
+    "EndReason", "Election", "SwearInType",
+    "Amended", "Deleted", "IsCorrection",
+    "HansardName", "EndNote", "IsJoint", "IsUnpaid", "Email",
+];
+
+#[derive(Debug, Error)]
+pub enum ParseError {
+    #[error("malformed XML at {path}")]
+    Xml {
+        path: String,
+        #[source]
+        source: xml::reader::Error,
+    },
+    #[error("malformed JSON")]
+    Json(#[from] serde_json::Error),
+    #[error("unable to detect input format")]
+    UnknownFormat,
+    #[error("feed has no Members element")]
+    MissingMembers,
+    #[error("member has no Member_Id")]
+    MissingMemberId,
+    #[error("invalid Member_Id {value:?}")]
+    InvalidMemberId {
+        value: String,
+        #[source]
+        source: std::num::ParseIntError,
+    },
+    #[error("invalid {field} {value:?} for member {member_id}")]
+    InvalidDate {
+        member_id: u32,
+        field: &'static str,
+        value: String,
+        #[source]
+        source: chrono::ParseError,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputFormat {
+    Xml,
+    Json,
+}
+
+impl FromStr for InputFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "xml" => Ok(InputFormat::Xml),
+            "json" => Ok(InputFormat::Json),
+            _ => Err(())
+        }
+    }
+}
+
+impl InputFormat {
+    pub fn detect(data: &str) -> Option<Self> {
+        match strip_bom(data).chars().next() {
+            Some('<') => Some(InputFormat::Xml),
+            Some('{') | Some('[') => Some(InputFormat::Json),
+            _ => None
+        }
+    }
+}
+
+pub fn parse_addresses(data: &str, format: Option<InputFormat>, config: &Config) -> Result<Members, ParseError> {
+    match format.or_else(|| InputFormat::detect(data)) {
+        Some(InputFormat::Xml) => parse_addresses_xml(data, config),
+        Some(InputFormat::Json) => parse_addresses_json(data, config),
+        None => Err(ParseError::UnknownFormat)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ParseWarning {
+    SkippedMember {
+        id: Option<u32>,
+        reason: String,
+    },
+    UnknownElement(String),
+    EmptySocialAddress {
+        member_id: u32,
+        kind: String,
+    },
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseWarning::SkippedMember { id: Some(id), reason } => write!(f, "skipped member {}: {}", id, reason),
+            ParseWarning::SkippedMember { id: None, reason } => write!(f, "skipped member without id: {}", reason),
+            ParseWarning::UnknownElement(name) => write!(f, "unrecognised element {}", name),
+            ParseWarning::EmptySocialAddress { member_id, kind } => write!(f, "empty {} address for member {}", kind, member_id),
+        }
+    }
+}
+
+pub fn normalize_party_name(name: &str, aliases: &HashMap<String, String>) -> String {
+    match aliases.get(name) {
+        Some(alias) => alias.clone(),
+        None => name.to_string()
+    }
+}
+
+const LOWERCASE_WORDS: &[&str] = &["and", "of", "the", "upon", "on", "under", "in", "with", "by", "le", "an"];
+
+fn title_case(name: &str) -> String {
+    let mut out = String::new();
+    for (i, word) in name.split(' ').enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        for (j, part) in word.split('-').enumerate() {
+            if j > 0 {
+                out.push('-');
+            }
+            if (i > 0 || j > 0) && LOWERCASE_WORDS.contains(&part) {
+                out.push_str(part);
+                continue;
+            }
+            let mut chars = part.chars();
+            if let Some(first) = chars.next() {
+                out.extend(first.to_uppercase());
+                out.push_str(chars.as_str());
+            }
+        }
+    }
+    out
+}
+
+pub fn normalize_constituency(name: &str, aliases: &HashMap<String, String>) -> String {
+    let name: String = name.nfc().map(|c| match c {
+        '\u{2018}' | '\u{2019}' => '\'',
+        '\u{2010}'..='\u{2015}' => '-',
+        c => c
+    }).collect();
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ").replace(" -", "-").replace("- ", "-");
+
+    let key = name.to_lowercase();
+    if let Some(alias) = aliases.iter().find(|(k, _)| k.nfc().collect::<String>().to_lowercase() == key).map(|(_, v)| v) {
+        return alias.clone();
+    }
+
+    if name.chars().any(char::is_lowercase) && name.chars().any(char::is_uppercase) {
+        return name;
+    }
+    title_case(&name.to_lowercase())
+}
+
+pub fn normalize_status(name: &str) -> Option<String> {
+    match name.trim().to_lowercase().as_str() {
+        "" => None,
+        "current member" => Some("sitting".to_string()),
+        status => Some(status.to_string())
+    }
+}
+
+fn merge_spells<T: Clone>(spells: &[T], same: impl Fn(&T, &T) -> bool, join: impl Fn(T, T) -> T) -> Vec<T> {
+    let mut spells = spells.to_vec();
+    if spells.len() < 2 {
+        return spells;
+    }
+
+    let mut out: Vec<T> = vec![];
+    loop {
+        let a = spells.pop();
+        let b = spells.pop();
+        match (a, b) {
+            (Some(a), Some(b)) => {
+                if same(&a, &b) {
+                    spells.push(join(a, b));
+                } else {
+                    out.push(a);
+                    spells.push(b);
+                }
+            }
+            (Some(a), None) => {
+                out.push(a);
+                return out;
+            }
+            (None, None) => return out,
+            (None, Some(_)) => unreachable!()
+        }
+    }
+}
+
+/// Orders party spells by start date. Spells starting on the same day are
+/// ordered by end date with an open spell last, then by name, so the
+/// history never depends on the order of the source document.
+pub fn compare_parties(a: &Party, b: &Party) -> Ordering {
+    a.start_date.cmp(&b.start_date)
+        .then_with(|| a.end_date.is_none().cmp(&b.end_date.is_none()))
+        .then_with(|| a.end_date.cmp(&b.end_date))
+        .then_with(|| a.name.cmp(&b.name))
+}
+
+pub fn merge_parties(parties: &[Party]) -> Vec<Party> {
+    merge_spells(parties, |a, b| a.name == b.name, |a, b| Party {
+        name: a.name,
+        original_name: a.original_name,
+        start_date: b.start_date,
+        end_date: a.end_date,
+    })
+}
+
+/// Finds the most recent move from a party to one of the independent names,
+/// returning the start of the independent spell. Expects the merged,
+/// newest-first history.
+pub fn whip_removal(parties: &[Party], independent: &[String]) -> Option<DateTime<Utc>> {
+    let is_independent = |p: &Party| independent.iter().any(|i| i.eq_ignore_ascii_case(&p.name));
+    parties.windows(2)
+        .find(|w| is_independent(&w[0]) && !is_independent(&w[1]))
+        .map(|w| w[0].start_date)
+}
+
+pub fn merge_seats(seats: &[SeatSpell]) -> Vec<SeatSpell> {
+    merge_spells(seats, |a, b| a.name == b.name, |a, b| SeatSpell {
+        id: a.id.or(b.id),
+        name: a.name,
+        start_date: b.start_date,
+        end_date: a.end_date,
+    })
+}
+
+pub const DEFAULT_DATE_FORMATS: &[&str] = &["rfc3339", "%Y-%m-%d", "%Y-%m-%dT%H:%M:%S%.f"];
+
+fn parse_date_format(value: &str, format: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    if format == "rfc3339" {
+        return DateTime::parse_from_rfc3339(value).map(|d| d.with_timezone(&Utc));
+    }
+    DateTime::parse_from_str(value, format).map(|d| d.with_timezone(&Utc))
+        .or_else(|_| NaiveDateTime::parse_from_str(value, format).map(|d| Utc.from_utc_datetime(&d)))
+        .or_else(|_| NaiveDate::parse_from_str(value, format)
+            .map(|d| Utc.from_utc_datetime(&d.and_hms_opt(0, 0, 0).unwrap())))
+}
+
+pub fn parse_date_with<S: AsRef<str>>(value: &str, formats: &[S]) -> Result<DateTime<Utc>, chrono::ParseError> {
+    let value = value.trim();
+    let mut error = None;
+    for format in formats {
+        match parse_date_format(value, format.as_ref()) {
+            Ok(d) => {
+                crate::debug!("parsed date {:?} with format {:?}", value, format.as_ref());
+                return Ok(d);
+            }
+            Err(e) => error = Some(e)
+        }
+    }
+    match error {
+        Some(e) => Err(e),
+        None => parse_date_format(value, "rfc3339")
+    }
+}
+
+pub fn parse_date(value: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    parse_date_with(value, DEFAULT_DATE_FORMATS)
+}
+
+fn breadcrumb(path: &[String]) -> String {
+    match path {
+        [] => "document root".to_string(),
+        path => path.join(">")
+    }
+}
+
+fn canonical_name(mut name: OwnedName, aliases: &HashMap<String, String>) -> OwnedName {
+    if let Some(canonical) = aliases.get(&name.local_name) {
+        name.local_name = canonical.clone();
+    }
+    name
+}
+
+fn is_nil(attributes: &[OwnedAttribute]) -> bool {
+    attributes.iter().any(|a| a.name.local_name == "nil" && a.value == "true")
+}
+
+fn parse_optional_date(field: &'static str, member_id: u32, nil: bool, value: &str, formats: &[String])
+                       -> Result<Option<DateTime<Utc>>, ParseError> {
+    if nil || value.trim().is_empty() {
+        return Ok(None);
+    }
+    parse_date_with(value, formats).map(Some).map_err(|e| ParseError::InvalidDate {
+        member_id,
+        field,
+        value: value.to_string(),
+        source: e,
+    })
+}
+
+fn select_address(slot: &mut Option<String>, value: &str, preferred: bool, kind: &'static str,
+                  preferred_kinds: &mut HashSet<&'static str>) {
+    let value = value.trim();
+    if value.is_empty() || preferred_kinds.contains(kind) {
+        return;
+    }
+
+    if slot.is_none() || preferred {
+        *slot = Some(value.to_string());
+        if preferred {
+            preferred_kinds.insert(kind);
+        }
+    }
+}
+
+pub fn is_official_note(note: &str) -> bool {
+    note.split(|c: char| !c.is_alphanumeric())
+        .any(|w| w.eq_ignore_ascii_case("official") || w.eq_ignore_ascii_case("parliamentary"))
+}
+
+fn in_range(config: &Config, id: u32) -> bool {
+    config.member_ids.is_none_or(|range| range.contains(id))
+}
+
+pub fn strip_bom(data: &str) -> &str {
+    data.trim_start_matches('\u{feff}').trim_start()
+}
+
+pub fn parse_addresses_xml(data: &str, config: &Config) -> Result<Members, ParseError> {
+    parse_addresses_xml_with_warnings(data, config).map(|(members, _)| members)
+}
+
+pub fn parse_addresses_xml_with_warnings(data: &str, config: &Config) -> Result<(Members, Vec<ParseWarning>), ParseError> {
+    let parser = EventReader::from_str(strip_bom(data));
+
+    #[derive(Debug, PartialEq)]
+    enum Element {
+        None,
+        Members,
+        Member,
+        Addresses,
+        Address,
+        AddrType,
+        AddrIsPreferred,
+        AddrEmail,
+        AddrNote,
+        AddressLine1,
+        Parties,
+        Party,
+        PartyPartyName,
+        PartyStartDate,
+        PartyEndDate,
+        Constituencies,
+        Seat,
+        SeatName,
+        SeatStartDate,
+        SeatEndDate,
+        Interests,
+        InterestCategory,
+        Interest,
+        InterestText,
+        InterestCreated,
+        GovernmentPosts,
+        GovernmentPost,
+        PreferredNames,
+        PreferredName,
+        PreferredForename,
+        PreferredSurname,
+        PostName,
+        PostStartDate,
+        PostEndDate,
+        Name,
+        FullTitle,
+        ListAs,
+        KnownAs,
+        PartyName,
+        House,
+        Constituency,
+        CurrentStatus,
+        StatusName,
+        LastModified,
+        HouseStartDate,
+        HouseEndDate,
+        OathDate,
+        Other,
+    }
+
+    #[derive(Debug)]
+    struct Address {
+        addr_type: String,
+        is_preferred: bool,
+        address: String,
+        email: String,
+        note: String,
+    }
+
+    struct PreferredName {
+        forename: String,
+        surname: String,
+        ended: bool,
+    }
+
+    impl Address {
+        fn new() -> Self {
+            Self {
+                addr_type: "".to_string(),
+                is_preferred: false,
+                address: "".to_string(),
+                email: "".to_string(),
+                note: "".to_string(),
+            }
+        }
+    }
+
+    let mut members: Option<Members> = None;
+    let mut member: Option<Member> = None;
+    let mut address: Option<Address> = None;
+    let mut party: Option<Party> = None;
+    let mut seat: Option<SeatSpell> = None;
+    let mut interest_category = String::new();
+    let mut interest: Option<Interest> = None;
+    let mut post: Option<GovernmentPost> = None;
+    let mut preferred_name: Option<PreferredName> = None;
+    let mut preferred_addresses: HashSet<&'static str> = HashSet::new();
+
+    let mut current_element = Element::None;
+    let mut previous_elements: Vec<Element> = vec![];
+    let mut unknown_elements = BTreeSet::new();
+    let mut empty_social_addresses = 0;
+    let mut member_error: Option<(Option<u32>, ParseError, String)> = None;
+    // Set once a member's id falls outside `config.member_ids`; the rest of it is skipped unparsed.
+    let mut out_of_range = false;
+    let mut path: Vec<String> = vec![];
+    let mut date_nil = false;
+    let mut skipped = vec![];
+    let mut warnings = vec![];
+
+    for e in parser {
+        match e {
+            Ok(XmlEvent::StartElement { name, attributes, .. }) => {
+                let name = canonical_name(name, &config.element_aliases);
+                path.push(name.local_name.clone());
+                if member_error.is_some() || out_of_range {
+                    previous_elements.push(current_element);
+                    current_element = Element::Other;
+                    continue;
+                }
+                if current_element == Element::None {
+                    if name.local_name == "Members" {
+                        let mut m = Members::new();
+                        for a in &attributes {
+                            if a.name.local_name == "MoreResults" {
+                                m.more_results = a.value.eq_ignore_ascii_case("true");
+                            }
+                        }
+                        members = Some(m);
+                        previous_elements.push(current_element);
+                        current_element = Element::Members;
+                        continue;
+                    }
+                } else if current_element == Element::Members {
+                    if name.local_name == "Member" {
+                        if let Some(members) = &mut members {
+                            members.seen += 1;
+                        }
+                        let mut id = None;
+                        let mut m = Member::new();
+                        for a in attributes {
+                            if a.name.local_name == "Member_Id" {
+                                id = Some(match a.value.parse::<u32>() {
+                                    Ok(id) => Ok(id),
+                                    Err(e) => Err(ParseError::InvalidMemberId { value: a.value, source: e })
+                                })
+                            } else if a.name.local_name.ends_with("_Id") && !a.value.trim().is_empty() {
+                                let kind = a.name.local_name.trim_end_matches("_Id").to_lowercase();
+                                m.alt_ids.insert(kind, a.value.trim().to_string());
+                            }
+                        }
+
+                        match id {
+                            Some(Ok(id)) => {
+                                m.id = id;
+                                out_of_range = !in_range(config, id);
+                            }
+                            Some(Err(e)) => member_error = Some((None, e, breadcrumb(&path))),
+                            None => member_error = Some((None, ParseError::MissingMemberId, breadcrumb(&path)))
+                        }
+                        member = Some(m);
+                        preferred_addresses.clear();
+                        previous_elements.push(current_element);
+                        current_element = Element::Member;
+                        continue;
+                    }
+                } else if current_element == Element::Member {
+                    if name.local_name == "Addresses" {
+                        previous_elements.push(current_element);
+                        current_element = Element::Addresses;
+                        continue;
+                    } else if name.local_name == "Parties" {
+                        previous_elements.push(current_element);
+                        current_element = Element::Parties;
+                        continue;
+                    } else if name.local_name == "Constituencies" {
+                        previous_elements.push(current_element);
+                        current_element = Element::Constituencies;
+                        continue;
+                    } else if name.local_name == "Interests" {
+                        previous_elements.push(current_element);
+                        current_element = Element::Interests;
+                        continue;
+                    } else if name.local_name == "GovernmentPosts" || name.local_name == "OppositionPosts" {
+                        previous_elements.push(current_element);
+                        current_element = Element::GovernmentPosts;
+                        continue;
+                    } else if name.local_name == "PreferredNames" {
+                        previous_elements.push(current_element);
+                        current_element = Element::PreferredNames;
+                        continue;
+                    } else if name.local_name == "DisplayAs" {
+                        previous_elements.push(current_element);
+                        current_element = Element::Name;
+                        continue;
+                    } else if name.local_name == "FullTitle" {
+                        previous_elements.push(current_element);
+                        current_element = Element::FullTitle;
+                        continue;
+                    } else if name.local_name == "ListAs" {
+                        previous_elements.push(current_element);
+                        current_element = Element::ListAs;
+                        continue;
+                    } else if name.local_name == "KnownAs" {
+                        previous_elements.push(current_element);
+                        current_element = Element::KnownAs;
+                        continue;
+                    } else if name.local_name == "Party" {
+                        previous_elements.push(current_element);
+                        current_element = Element::PartyName;
+                        continue;
+                    } else if name.local_name == "House" {
+                        previous_elements.push(current_element);
+                        current_element = Element::House;
+                        continue;
+                    } else if name.local_name == "LastModified" {
+                        date_nil = is_nil(&attributes);
+                        previous_elements.push(current_element);
+                        current_element = Element::LastModified;
+                        continue;
+                    } else if name.local_name == "HouseStartDate" {
+                        date_nil = is_nil(&attributes);
+                        previous_elements.push(current_element);
+                        current_element = Element::HouseStartDate;
+                        continue;
+                    } else if name.local_name == "HouseEndDate" {
+                        date_nil = is_nil(&attributes);
+                        previous_elements.push(current_element);
+                        current_element = Element::HouseEndDate;
+                        continue;
+                    } else if name.local_name == "OathDate" {
+                        date_nil = is_nil(&attributes);
+                        previous_elements.push(current_element);
+                        current_element = Element::OathDate;
+                        continue;
+                    } else if name.local_name == "CurrentStatus" {
+                        previous_elements.push(current_element);
+                        current_element = Element::CurrentStatus;
+                        continue;
+                    } else if name.local_name == "MemberFrom" {
+                        if let Some(member) = &mut member {
+                            for a in &attributes {
+                                if a.name.local_name == "Id" {
+                                    member.constituency_id = a.value.parse().ok();
+                                }
+                            }
+                        }
+                        previous_elements.push(current_element);
+                        current_element = Element::Constituency;
+                        continue;
+                    }
+                } else if current_element == Element::CurrentStatus {
+                    previous_elements.push(current_element);
+                    current_element = if name.local_name == "Name" { Element::StatusName } else { Element::Other };
+                    continue;
+                } else if current_element == Element::Addresses {
+                    if name.local_name == "Address" {
+                        address = Some(Address::new());
+                        previous_elements.push(current_element);
+                        current_element = Element::Address;
+                        continue;
+                    }
+                } else if current_element == Element::Address {
+                    if name.local_name == "Type" {
+                        previous_elements.push(current_element);
+                        current_element = Element::AddrType;
+                        continue;
+                    } else if name.local_name == "IsPreferred" {
+                        previous_elements.push(current_element);
+                        current_element = Element::AddrIsPreferred;
+                        continue;
+                    } else if name.local_name == "Email" {
+                        previous_elements.push(current_element);
+                        current_element = Element::AddrEmail;
+                        continue;
+                    } else if name.local_name == "Note" {
+                        previous_elements.push(current_element);
+                        current_element = Element::AddrNote;
+                        continue;
+                    } else if name.local_name == "Address1" {
+                        previous_elements.push(current_element);
+                        current_element = Element::AddressLine1;
+                        continue;
+                    }
+                } else if current_element == Element::Constituencies {
+                    if name.local_name == "Constituency" {
+                        let mut s = SeatSpell::new();
+                        for a in &attributes {
+                            if a.name.local_name == "Id" {
+                                s.id = a.value.parse().ok();
+                            }
+                        }
+                        seat = Some(s);
+                        previous_elements.push(current_element);
+                        current_element = Element::Seat;
+                        continue;
+                    }
+                } else if current_element == Element::Seat {
+                    if name.local_name == "Name" {
+                        previous_elements.push(current_element);
+                        current_element = Element::SeatName;
+                        continue;
+                    } else if name.local_name == "StartDate" {
+                        date_nil = false;
+                        previous_elements.push(current_element);
+                        current_element = Element::SeatStartDate;
+                        continue;
+                    } else if name.local_name == "EndDate" {
+                        date_nil = is_nil(&attributes);
+                        previous_elements.push(current_element);
+                        current_element = Element::SeatEndDate;
+                        continue;
+                    }
+                } else if current_element == Element::Interests {
+                    if name.local_name == "Category" {
+                        interest_category.clear();
+                        for a in &attributes {
+                            if a.name.local_name == "Name" {
+                                interest_category = a.value.clone();
+                            }
+                        }
+                        previous_elements.push(current_element);
+                        current_element = Element::InterestCategory;
+                        continue;
+                    }
+                } else if current_element == Element::InterestCategory {
+                    if name.local_name == "Interest" {
+                        let mut i = Interest::new();
+                        i.category = interest_category.clone();
+                        interest = Some(i);
+                        previous_elements.push(current_element);
+                        current_element = Element::Interest;
+                        continue;
+                    }
+                } else if current_element == Element::Interest {
+                    if name.local_name == "RegisteredInterest" {
+                        previous_elements.push(current_element);
+                        current_element = Element::InterestText;
+                        continue;
+                    } else if name.local_name == "Created" {
+                        date_nil = is_nil(&attributes);
+                        previous_elements.push(current_element);
+                        current_element = Element::InterestCreated;
+                        continue;
+                    }
+                } else if current_element == Element::GovernmentPosts {
+                    if name.local_name == "GovernmentPost" || name.local_name == "OppositionPost" {
+                        post = Some(GovernmentPost { opposition: name.local_name == "OppositionPost", ..GovernmentPost::new() });
+                        previous_elements.push(current_element);
+                        current_element = Element::GovernmentPost;
+                        continue;
+                    }
+                } else if current_element == Element::PreferredNames {
+                    if name.local_name == "PreferredName" {
+                        preferred_name = Some(PreferredName { forename: String::new(), surname: String::new(), ended: false });
+                        previous_elements.push(current_element);
+                        current_element = Element::PreferredName;
+                        continue;
+                    }
+                } else if current_element == Element::PreferredName {
+                    previous_elements.push(current_element);
+                    current_element = match name.local_name.as_str() {
+                        "Forename" => Element::PreferredForename,
+                        "Surname" => Element::PreferredSurname,
+                        "EndDate" => {
+                            if let Some(preferred_name) = &mut preferred_name {
+                                preferred_name.ended = !is_nil(&attributes);
+                            }
+                            Element::Other
+                        }
+                        _ => Element::Other
+                    };
+                    continue;
+                } else if current_element == Element::GovernmentPost {
+                    if name.local_name == "Name" {
+                        previous_elements.push(current_element);
+                        current_element = Element::PostName;
+                        continue;
+                    } else if name.local_name == "StartDate" {
+                        date_nil = false;
+                        previous_elements.push(current_element);
+                        current_element = Element::PostStartDate;
+                        continue;
+                    } else if name.local_name == "EndDate" {
+                        date_nil = is_nil(&attributes);
+                        previous_elements.push(current_element);
+                        current_element = Element::PostEndDate;
+                        continue;
+                    }
+                } else if current_element == Element::Parties {
+                    if name.local_name == "Party" {
+                        party = Some(Party::new());
+                        previous_elements.push(current_element);
+                        current_element = Element::Party;
+                        continue;
+                    }
+                } else if current_element == Element::Party {
+                    if name.local_name == "Name" {
+                        previous_elements.push(current_element);
+                        current_element = Element::PartyPartyName;
+                        continue;
+                    } else if name.local_name == "StartDate" {
+                        date_nil = false;
+                        previous_elements.push(current_element);
+                        current_element = Element::PartyStartDate;
+                        continue;
+                    } else if name.local_name == "EndDate" {
+                        date_nil = is_nil(&attributes);
+                        previous_elements.push(current_element);
+                        current_element = Element::PartyEndDate;
+                        continue;
+                    }
+                }
+                if current_element != Element::Other && !KNOWN_IGNORED_ELEMENTS.contains(&name.local_name.as_str())
+                    && unknown_elements.insert(name.local_name.clone()) {
+                    warnings.push(ParseWarning::UnknownElement(name.local_name));
+                }
+                previous_elements.push(current_element);
+                current_element = Element::Other;
+            }
+            Ok(XmlEvent::EndElement { name }) => {
+                let name = canonical_name(name, &config.element_aliases);
+                if member_error.is_some() || out_of_range {
+                    if name.local_name == "Member" && current_element == Element::Member {
+                        out_of_range = false;
+                        if let Some((id, e, at)) = member_error.take() {
+                            let reason = format!("{} at {}", e, at);
+                            warnings.push(ParseWarning::SkippedMember { id, reason: reason.clone() });
+                            skipped.push(SkippedMember { id, reason });
+                        }
+                        member = None;
+                    }
+                } else if name.local_name == "Member" {
+                    match &mut members {
+                        Some(members) => members.members.push(member.clone().unwrap().clone()),
+                        None => unreachable!()
+                    }
+                } else if name.local_name == "Address" {
+                    match &mut member {
+                        None => unreachable!(),
+                        Some(member) => {
+                            match &address {
+                                Some(address) => {
+                                    let preferred = address.is_preferred;
+                                    if (address.addr_type == "Twitter" || address.addr_type == "Facebook") && address.address.trim().is_empty() {
+                                        empty_social_addresses += 1;
+                                        warnings.push(ParseWarning::EmptySocialAddress { member_id: member.id, kind: address.addr_type.clone() });
+                                    }
+                                    match address.addr_type.as_str() {
+                                        "Twitter" => select_address(&mut member.twitter, &address.address, preferred, "Twitter", &mut preferred_addresses),
+                                        "Facebook" => select_address(&mut member.facebook, &address.address, preferred, "Facebook", &mut preferred_addresses),
+                                        _ => {}
+                                    }
+                                    if address.addr_type == "Twitter" && is_official_note(&address.note) {
+                                        select_address(&mut member.twitter_official, &address.address, preferred, "TwitterOfficial", &mut preferred_addresses);
+                                    }
+                                    if address.addr_type == "Constituency" {
+                                        select_address(&mut member.constituency_email, &address.email, preferred, "ConstituencyEmail", &mut preferred_addresses);
+                                    } else {
+                                        select_address(&mut member.parliamentary_email, &address.email, preferred, "ParliamentaryEmail", &mut preferred_addresses);
+                                    }
+                                }
+                                None => unreachable!()
+                            }
+                        }
+                    }
+                } else if name.local_name == "Party" && current_element == Element::Party {
+                    match &mut member {
+                        None => unreachable!(),
+                        Some(member) => {
+                            match &party {
+                                Some(party) => {
+                                    let mut party = party.clone();
+                                    party.original_name = party.name.clone();
+                                    party.name = normalize_party_name(&party.name, &config.party_aliases);
+                                    member.parties.push(party)
+                                }
+                                None => unreachable!()
+                            }
+                        }
+                    }
+                } else if name.local_name == "Interest" && current_element == Element::Interest {
+                    match &mut member {
+                        None => unreachable!(),
+                        Some(member) => {
+                            match &interest {
+                                Some(interest) => member.interests.push(interest.clone()),
+                                None => unreachable!()
+                            }
+                        }
+                    }
+                } else if name.local_name == "PreferredName" && current_element == Element::PreferredName {
+                    match (&mut member, preferred_name.take()) {
+                        (Some(member), Some(p)) => {
+                            let former = format!("{} {}", p.forename.trim(), p.surname.trim()).trim().to_string();
+                            if p.ended && !former.is_empty() && !member.former_names.contains(&former) {
+                                member.former_names.push(former);
+                            }
+                        }
+                        _ => unreachable!()
+                    }
+                } else if (name.local_name == "GovernmentPost" || name.local_name == "OppositionPost") && current_element == Element::GovernmentPost {
+                    match &mut member {
+                        None => unreachable!(),
+                        Some(member) => {
+                            match &post {
+                                Some(post) => member.government_posts.push(post.clone()),
+                                None => unreachable!()
+                            }
+                        }
+                    }
+                } else if name.local_name == "Constituency" && current_element == Element::Seat {
+                    match &mut member {
+                        None => unreachable!(),
+                        Some(member) => {
+                            match &seat {
+                                Some(seat) => member.seats.push(seat.clone()),
+                                None => unreachable!()
+                            }
+                        }
+                    }
+                } else if name.local_name == "Constituencies" && current_element == Element::Constituencies {
+                    match &mut member {
+                        None => unreachable!(),
+                        Some(member) => {
+                            member.seats.sort_by_key(|s| s.start_date);
+                            member.seats = merge_seats(&member.seats);
+                            let primary = member.current_seats().first().map(|s| (s.name.clone(), s.id));
+                            if let Some((name, id)) = primary {
+                                if member.constituency.is_empty() {
+                                    member.normalized_constituency = normalize_constituency(&name, &config.constituency_aliases);
+                                    member.constituency = name;
+                                }
+                                if member.constituency_id.is_none() {
+                                    member.constituency_id = id;
+                                }
+                            }
+                        }
+                    }
+                } else if name.local_name == "Parties" && current_element == Element::Parties {
+                    match &mut member {
+                        None => unreachable!(),
+                        Some(member) => {
+                            member.parties.sort_by(compare_parties);
+                            member.parties = merge_parties(&member.parties);
+                            member.whip_removed = whip_removal(&member.parties, &config.independent_parties);
+                        }
+                    }
+                }
+                current_element = match previous_elements.pop() {
+                    Some(e) => e,
+                    None => Element::None
+                };
+                path.pop();
+            }
+            Ok(XmlEvent::Characters(data)) => {
+                if member_error.is_some() || out_of_range {
+                    continue;
+                }
+                let member_id = member.as_ref().map_or(0, |m| m.id);
+                match current_element {
+                    Element::None | Element::Other | Element::Members | Element::Member | Element::CurrentStatus |
+                    Element::Addresses | Element::Address |
+                    Element::Parties | Element::Party |
+                    Element::Constituencies | Element::Seat |
+                    Element::Interests | Element::InterestCategory | Element::Interest |
+                    Element::GovernmentPosts | Element::GovernmentPost |
+                    Element::PreferredNames | Element::PreferredName => {}
+                    Element::Name => {
+                        match &mut member {
+                            None => unreachable!(),
+                            Some(member) => member.name = data.clone()
+                        }
+                    }
+                    Element::FullTitle => {
+                        match &mut member {
+                            None => unreachable!(),
+                            Some(member) => member.full_title = Some(data.clone())
+                        }
+                    }
+                    Element::ListAs => {
+                        match &mut member {
+                            None => unreachable!(),
+                            Some(member) => member.list_as = Some(data.clone())
+                        }
+                    }
+                    Element::KnownAs => {
+                        match &mut member {
+                            None => unreachable!(),
+                            Some(member) => member.known_as = Some(data.clone())
+                        }
+                    }
+                    Element::PartyName => {
+                        match &mut member {
+                            None => unreachable!(),
+                            Some(member) => member.party = data.clone()
+                        }
+                    }
+                    Element::Constituency => {
+                        match &mut member {
+                            None => unreachable!(),
+                            Some(member) => {
+                                member.normalized_constituency = normalize_constituency(&data, &config.constituency_aliases);
+                                member.constituency = data.clone();
+                            }
+                        }
+                    }
+                    Element::House => {
+                        match &mut member {
+                            None => unreachable!(),
+                            Some(member) => member.house = data.parse().unwrap_or(House::Unknown)
+                        }
+                    }
+                    Element::StatusName => {
+                        match &mut member {
+                            None => unreachable!(),
+                            Some(member) => member.status = normalize_status(&data)
+                        }
+                    }
+                    Element::LastModified => {
+                        match &mut member {
+                            None => unreachable!(),
+                            Some(member) => match parse_optional_date("LastModified", member_id, date_nil, &data, &config.date_formats) {
+                                Ok(d) => member.modified = d,
+                                Err(e) => {
+                                    member_error = Some((Some(member_id), e, breadcrumb(&path)));
+                                    continue;
+                                }
+                            }
+                        }
+                    }
+                    Element::HouseStartDate => {
+                        match &mut member {
+                            None => unreachable!(),
+                            Some(member) => match parse_optional_date("HouseStartDate", member_id, date_nil, &data, &config.date_formats) {
+                                Ok(d) => member.house_start_date = d,
+                                Err(e) => {
+                                    member_error = Some((Some(member_id), e, breadcrumb(&path)));
+                                    continue;
+                                }
+                            }
+                        }
+                    }
+                    Element::HouseEndDate => {
+                        match &mut member {
+                            None => unreachable!(),
+                            Some(member) => match parse_optional_date("HouseEndDate", member_id, date_nil, &data, &config.date_formats) {
+                                Ok(d) => member.house_end_date = d,
+                                Err(e) => {
+                                    member_error = Some((Some(member_id), e, breadcrumb(&path)));
+                                    continue;
+                                }
+                            }
+                        }
+                    }
+                    Element::OathDate => {
+                        match &mut member {
+                            None => unreachable!(),
+                            Some(member) => match parse_optional_date("OathDate", member_id, date_nil, &data, &config.date_formats) {
+                                Ok(d) => member.oath_date = d,
+                                Err(e) => {
+                                    member_error = Some((Some(member_id), e, breadcrumb(&path)));
+                                    continue;
+                                }
+                            }
+                        }
+                    }
+                    Element::AddrType => {
+                        match &mut address {
+                            None => unreachable!(),
+                            Some(address) => address.addr_type = data.clone()
+                        }
+                    }
+                    Element::AddrIsPreferred => {
+                        match &mut address {
+                            None => unreachable!(),
+                            Some(address) => address.is_preferred = data.eq_ignore_ascii_case("true")
+                        }
+                    }
+                    Element::AddrEmail => {
+                        match &mut address {
+                            None => unreachable!(),
+                            Some(address) => address.email = data.clone()
+                        }
+                    }
+                    Element::AddrNote => {
+                        match &mut address {
+                            None => unreachable!(),
+                            Some(address) => address.note = data.clone()
+                        }
+                    }
+                    Element::AddressLine1 => {
+                        match &mut address {
+                            None => unreachable!(),
+                            Some(address) => address.address = data.clone()
+                        }
+                    }
+                    Element::PartyPartyName => {
+                        match &mut party {
+                            None => unreachable!(),
+                            Some(party) => party.name = data.clone()
+                        }
+                    }
+                    Element::PartyStartDate => {
+                        match &mut party {
+                            None => unreachable!(),
+                            Some(party) => match parse_optional_date("party StartDate", member_id, date_nil, &data, &config.date_formats) {
+                                Ok(d) => if let Some(d) = d {
+                                    party.start_date = d;
+                                },
+                                Err(e) => {
+                                    member_error = Some((Some(member_id), e, breadcrumb(&path)));
+                                    continue;
+                                }
+                            }
+                        }
+                    }
+                    Element::PartyEndDate => {
+                        match &mut party {
+                            None => unreachable!(),
+                            Some(party) => match parse_optional_date("party EndDate", member_id, date_nil, &data, &config.date_formats) {
+                                Ok(d) => party.end_date = d,
+                                Err(e) => {
+                                    member_error = Some((Some(member_id), e, breadcrumb(&path)));
+                                    continue;
+                                }
+                            }
+                        }
+                    }
+                    Element::InterestText => {
+                        match &mut interest {
+                            None => unreachable!(),
+                            Some(interest) => interest.description = data.trim().to_string()
+                        }
+                    }
+                    Element::InterestCreated => {
+                        match &mut interest {
+                            None => unreachable!(),
+                            Some(interest) => match parse_optional_date("interest Created", member_id, date_nil, &data, &config.date_formats) {
+                                Ok(d) => interest.created = d,
+                                Err(e) => {
+                                    member_error = Some((Some(member_id), e, breadcrumb(&path)));
+                                    continue;
+                                }
+                            }
+                        }
+                    }
+                    Element::PreferredForename => {
+                        match &mut preferred_name {
+                            None => unreachable!(),
+                            Some(p) => p.forename = data.clone()
+                        }
+                    }
+                    Element::PreferredSurname => {
+                        match &mut preferred_name {
+                            None => unreachable!(),
+                            Some(p) => p.surname = data.clone()
+                        }
+                    }
+                    Element::PostName => {
+                        match &mut post {
+                            None => unreachable!(),
+                            Some(post) => post.name = data.clone()
+                        }
+                    }
+                    Element::PostStartDate => {
+                        match &mut post {
+                            None => unreachable!(),
+                            Some(post) => match parse_optional_date("government post StartDate", member_id, date_nil, &data, &config.date_formats) {
+                                Ok(d) => if let Some(d) = d {
+                                    post.start_date = d;
+                                },
+                                Err(e) => {
+                                    member_error = Some((Some(member_id), e, breadcrumb(&path)));
+                                    continue;
+                                }
+                            }
+                        }
+                    }
+                    Element::PostEndDate => {
+                        match &mut post {
+                            None => unreachable!(),
+                            Some(post) => match parse_optional_date("government post EndDate", member_id, date_nil, &data, &config.date_formats) {
+                                Ok(d) => post.end_date = d,
+                                Err(e) => {
+                                    member_error = Some((Some(member_id), e, breadcrumb(&path)));
+                                    continue;
+                                }
+                            }
+                        }
+                    }
+                    Element::SeatName => {
+                        match &mut seat {
+                            None => unreachable!(),
+                            Some(seat) => seat.name = data.clone()
+                        }
+                    }
+                    Element::SeatStartDate => {
+                        match &mut seat {
+                            None => unreachable!(),
+                            Some(seat) => match parse_optional_date("constituency StartDate", member_id, date_nil, &data, &config.date_formats) {
+                                Ok(d) => if let Some(d) = d {
+                                    seat.start_date = d;
+                                },
+                                Err(e) => {
+                                    member_error = Some((Some(member_id), e, breadcrumb(&path)));
+                                    continue;
+                                }
+                            }
+                        }
+                    }
+                    Element::SeatEndDate => {
+                        match &mut seat {
+                            None => unreachable!(),
+                            Some(seat) => match parse_optional_date("constituency EndDate", member_id, date_nil, &data, &config.date_formats) {
+                                Ok(d) => seat.end_date = d,
+                                Err(e) => {
+                                    member_error = Some((Some(member_id), e, breadcrumb(&path)));
+                                    continue;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            Err(e) => return Err(ParseError::Xml { path: breadcrumb(&path), source: e }),
+            _ => {}
+        }
+    }
+
+    let mut members = members.ok_or(ParseError::MissingMembers)?;
+    members.unknown_elements = unknown_elements;
+    members.empty_social_addresses = empty_social_addresses;
+    members.skipped = skipped;
+
+    Ok((members, warnings))
+}
+
+fn json_to_xml(name: &str, value: &serde_json::Value, out: &mut String) {
+    use serde_json::Value;
+
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                json_to_xml(name, item, out);
+            }
+        }
+        Value::Object(fields) => {
+            out.push('<');
+            out.push_str(name);
+            for (k, v) in fields {
+                if let (Some(attr), Some(v)) = (k.strip_prefix('@'), v.as_str()) {
+                    if !attr.contains(':') {
+                        out.push_str(&format!(" {}=\"{}\"", attr, escape_str_attribute(v)));
+                    }
+                }
+            }
+            out.push('>');
+            for (k, v) in fields {
+                if k == "#text" {
+                    if let Some(text) = v.as_str() {
+                        out.push_str(&escape_str_pcdata(text));
+                    }
+                } else if !k.starts_with('@') {
+                    json_to_xml(k, v, out);
+                }
+            }
+            out.push_str(&format!("</{}>", name));
+        }
+        Value::Null => out.push_str(&format!("<{}/>", name)),
+        Value::String(text) => out.push_str(&format!("<{0}>{1}</{0}>", name, escape_str_pcdata(text))),
+        other => out.push_str(&format!("<{0}>{1}</{0}>", name, other)),
+    }
+}
+
+pub fn parse_addresses_json(data: &str, config: &Config) -> Result<Members, ParseError> {
+    let value: serde_json::Value = serde_json::from_str(strip_bom(data))?;
+
+    let mut xml = String::new();
+    match value.as_object() {
+        Some(fields) => {
+            for (k, v) in fields {
+                json_to_xml(k, v, &mut xml);
+            }
+        }
+        None => return Err(ParseError::MissingMembers)
+    }
+
+    parse_addresses_xml(&xml, config)
+}
+
+fn member_starts(data: &str) -> Vec<usize> {
+    let bytes = data.as_bytes();
+    data.match_indices("<Member").map(|(i, _)| i)
+        .filter(|i| matches!(bytes.get(i + "<Member".len()), Some(b' ') | Some(b'>') | Some(b'/') | Some(b'\t') | Some(b'\r') | Some(b'\n')))
+        .collect()
+}
+
+fn split_members(data: &str, chunks: usize) -> Option<Vec<String>> {
+    let open = data.find("<Members")?;
+    let open_end = open + data[open..].find('>')? + 1;
+    let close = data.rfind("</Members>")?;
+    let header = &data[open..open_end];
+
+    let starts = member_starts(&data[open_end..close]).into_iter().map(|i| i + open_end).collect::<Vec<_>>();
+    if starts.len() < chunks {
+        return None;
+    }
+
+    let per_chunk = starts.len().div_ceil(chunks);
+    let mut out = vec![];
+    for (n, chunk) in starts.chunks(per_chunk).enumerate() {
+        let end = starts.get((n + 1) * per_chunk).copied().unwrap_or(close);
+        out.push(format!("{}{}</Members>", header, &data[chunk[0]..end]));
+    }
+    Some(out)
+}
+
+pub fn parse_addresses_parallel(data: &str, config: &Config, threads: usize) -> Result<(Members, Vec<ParseWarning>), ParseError> {
+    parse_in_chunks(data, config, threads, MIN_PARALLEL_INPUT)
+}
+
+fn parse_in_chunks(data: &str, config: &Config, threads: usize, min_input: usize) -> Result<(Members, Vec<ParseWarning>), ParseError> {
+    if threads <= 1 || data.len() < min_input {
+        return parse_addresses_xml_with_warnings(data, config);
+    }
+
+    let chunks = match split_members(data, threads) {
+        Some(c) => c,
+        None => return parse_addresses_xml_with_warnings(data, config)
+    };
+    let pool = match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+        Ok(p) => p,
+        Err(_) => return parse_addresses_xml_with_warnings(data, config)
+    };
+
+    let results: Vec<Result<(Members, Vec<ParseWarning>), ParseError>> = pool.install(|| {
+        chunks.into_par_iter().map(|c| parse_addresses_xml_with_warnings(&c, config)).collect()
+    });
+
+    // Every chunk reports the unknown elements it saw, so the same warning can come back more than once.
+    let mut members = Members::new();
+    let mut warnings = vec![];
+    let mut seen = HashSet::new();
+    for result in results {
+        let (m, w) = result?;
+        members.extend(m);
+        for warning in w {
+            if seen.insert(warning.clone()) {
+                warnings.push(warning);
+            }
+        }
+    }
+
+    Ok((members, warnings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::members::IdRange;
+    use crate::test_support::{commons, document};
+
+    fn party(name: &str, start: &str, end: Option<&str>) -> Party {
+        Party {
+            name: name.to_string(),
+            original_name: name.to_string(),
+            start_date: parse_date(start).unwrap(),
+            end_date: end.map(|end| parse_date(end).unwrap()),
+        }
+    }
+
+    fn history(mut parties: Vec<Party>) -> Vec<Party> {
+        parties.sort_by(compare_parties);
+        merge_parties(&parties)
+    }
+    use chrono::Datelike;
+
+    #[test]
+    fn unexpected_elements_are_reported() {
+        let data = document(&[(1, "<Gender>F</Gender><Pronouns><Subject>she</Subject></Pronouns>"),
+                              (2, "<Pronouns><Subject>he</Subject></Pronouns>")]);
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+
+        assert_eq!(members.members.len(), 2);
+        let unknown: Vec<&str> = members.unknown_elements.iter().map(String::as_str).collect();
+        assert_eq!(unknown, ["Pronouns"]);
+    }
+
+    #[test]
+    fn peers_keep_their_full_title_and_list_name() {
+        let data = document(&[(3898, "<ListAs>Stansgate, V.</ListAs>\
+            <FullTitle>The Rt Hon. the Viscount Stansgate DL, Baron of Stansgate in the County of Essex</FullTitle>")]);
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+
+        let member = &members.members[0];
+        assert_eq!(member.name, "Member 3898");
+        assert_eq!(member.full_title.as_deref(), Some("The Rt Hon. the Viscount Stansgate DL, Baron of Stansgate in the County of Essex"));
+        assert_eq!(member.list_as.as_deref(), Some("Stansgate, V."));
+        assert!(members.unknown_elements.is_empty());
+    }
+
+    #[test]
+    fn co_op_spells_merge_into_labour() {
+        let data = document(&[(1, "<Parties>\
+            <Party><Name>Labour</Name><StartDate>2010-05-06T00:00:00</StartDate><EndDate>2015-05-07T00:00:00</EndDate></Party>\
+            <Party><Name>Labour (Co-op)</Name><StartDate>2015-05-07T00:00:00</StartDate><EndDate xsi:nil=\"true\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\"/></Party>\
+            </Parties>")]);
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+
+        let spells: Vec<(&str, &str)> = members.members[0].parties.iter()
+            .map(|p| (p.name.as_str(), p.original_name.as_str()))
+            .collect();
+        assert_eq!(spells, [("Labour", "Labour (Co-op)")]);
+    }
+
+    #[test]
+    fn unaliased_party_names_are_kept() {
+        let config = Config::default();
+        assert_eq!(normalize_party_name("Labour/Co-operative", &config.party_aliases), "Labour");
+        assert_eq!(normalize_party_name("Conservative", &config.party_aliases), "Conservative");
+    }
+
+    #[test]
+    fn limit_keeps_the_first_members() {
+        let data = document(&[(172, ""), (4212, ""), (1524, "")]);
+        let mut members = parse_addresses_xml(&data, &Config::default()).unwrap();
+
+        assert!(!members.truncate(3));
+        assert!(members.truncate(2));
+        let ids: Vec<u32> = members.members.iter().map(|m| m.id).collect();
+        assert_eq!(ids, [172, 4212]);
+    }
+
+    #[test]
+    fn preferred_email_wins_over_the_first() {
+        let data = document(&[(172, "<Addresses>\
+            <Address><Type>Parliamentary</Type><IsPreferred>False</IsPreferred><Email>diane.abbott.office@parliament.uk</Email></Address>\
+            <Address><Type>Constituency</Type><IsPreferred>True</IsPreferred><Email>diane.abbott.mp@parliament.uk</Email></Address>\
+            <Address><Type>Website</Type><IsPreferred>False</IsPreferred><Email>other@example.com</Email></Address>\
+            </Addresses>"), (4212, "<Addresses>\
+            <Address><Type>Parliamentary</Type><IsPreferred>False</IsPreferred><Email> </Email></Address>\
+            <Address><Type>Constituency</Type><IsPreferred>False</IsPreferred><Email>first@parliament.uk</Email></Address>\
+            <Address><Type>Website</Type><IsPreferred>False</IsPreferred><Email>second@parliament.uk</Email></Address>\
+            </Addresses>")]);
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+
+        assert_eq!(members.members[0].constituency_email.as_deref(), Some("diane.abbott.mp@parliament.uk"));
+        assert_eq!(members.members[0].parliamentary_email.as_deref(), Some("diane.abbott.office@parliament.uk"));
+        assert_eq!(members.members[1].constituency_email.as_deref(), Some("first@parliament.uk"));
+        assert_eq!(members.members[1].parliamentary_email.as_deref(), Some("second@parliament.uk"));
+    }
+
+    #[test]
+    fn constituency_and_parliamentary_emails_are_split() {
+        let members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+
+        let abbott = &members.members[0];
+        assert_eq!(abbott.id, 172);
+        assert_eq!(abbott.parliamentary_email.as_deref(), Some("abbott.mp@parliament.uk"));
+        assert_eq!(abbott.constituency_email.as_deref(), Some("diane.abbott.office@parliament.uk"));
+        assert_eq!(abbott.email(), Some("abbott.mp@parliament.uk"));
+
+        let soubry = &members.members[1];
+        assert_eq!(soubry.constituency_email, None);
+        assert_eq!(soubry.email(), Some("soubry.mp@parliament.uk"));
+    }
+
+    #[test]
+    fn empty_social_addresses_are_none() {
+        let data = document(&[(172, "<Addresses>\
+            <Address><Type>Twitter</Type><Address1>  </Address1></Address>\
+            <Address><Type>Facebook</Type><Address1>https://www.facebook.com/DianeAbbott</Address1></Address>\
+            </Addresses>")]);
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+
+        assert_eq!(members.members[0].twitter, None);
+        assert_eq!(members.members[0].facebook.as_deref(), Some("https://www.facebook.com/DianeAbbott"));
+        assert_eq!(members.empty_social_addresses, 1);
+    }
+
+    #[test]
+    fn constituency_id_is_captured() {
+        let data = document(&[(172, "<MemberFrom Id=\"146727\">Hackney North and Stoke Newington</MemberFrom>"),
+                              (3898, "<MemberFrom>Life peer</MemberFrom>")]);
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+
+        assert_eq!(members.members[0].constituency, "Hackney North and Stoke Newington");
+        assert_eq!(members.members[0].constituency_id, Some(146727));
+        assert_eq!(members.members[1].constituency_id, None);
+    }
+
+    #[test]
+    fn seat_history_is_merged_newest_first() {
+        let data = document(&[(4005, "<Constituencies>\
+            <Constituency Id=\"146890\"><Name>Morley and Outwood</Name><StartDate>2010-05-06T00:00:00</StartDate><EndDate>2015-05-07T00:00:00</EndDate></Constituency>\
+            <Constituency Id=\"146629\"><Name>Normanton, Pontefract and Castleford</Name><StartDate>1997-05-01T00:00:00</StartDate><EndDate>2010-05-06T00:00:00</EndDate></Constituency>\
+            <Constituency Id=\"146890\"><Name>Morley and Outwood</Name><StartDate>2015-05-07T00:00:00</StartDate></Constituency>\
+            </Constituencies>")]);
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+
+        let seats: Vec<(Option<u32>, &str, i32, Option<i32>)> = members.members[0].seats.iter()
+            .map(|s| (s.id, s.name.as_str(), s.start_date.year(), s.end_date.map(|d| d.year())))
+            .collect();
+        assert_eq!(seats, [
+            (Some(146890), "Morley and Outwood", 2010, None),
+            (Some(146629), "Normanton, Pontefract and Castleford", 1997, Some(2010)),
+        ]);
+    }
+
+    #[test]
+    fn corrupt_members_are_skipped() {
+        let data = document(&[
+            (172, "<Parties><Party><Name>Labour</Name><StartDate>1987-06-11T00:00:00</StartDate></Party></Parties>"),
+            (4212, "<Parties><Party><Name>Labour</Name><StartDate>last Tuesday</StartDate></Party></Parties>"),
+            (1524, ""),
+        ]).replace("<Member Member_Id=\"1524\">", "<Member Member_Id=\"x1524\">");
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+
+        let ids: Vec<u32> = members.members.iter().map(|m| m.id).collect();
+        assert_eq!(ids, [172]);
+        let skipped: Vec<(Option<u32>, &str)> = members.skipped.iter().map(|s| (s.id, s.reason.as_str())).collect();
+        assert_eq!(skipped, [
+            (Some(4212), "invalid party StartDate \"last Tuesday\" for member 4212 at Members>Member>Parties>Party>StartDate"),
+            (None, "invalid Member_Id \"x1524\" at Members>Member"),
+        ]);
+    }
+
+    #[test]
+    fn more_results_flag_is_read() {
+        let data = document(&[(172, "")]);
+        assert!(!parse_addresses_xml(&data, &Config::default()).unwrap().more_results);
+
+        let data = data.replace("<Members>", "<Members MoreResults=\"True\">");
+        assert!(parse_addresses_xml(&data, &Config::default()).unwrap().more_results);
+    }
+
+    #[test]
+    fn input_format_is_detected() {
+        let xml = document(&[(172, ""), (4212, "")]);
+        let json = r#"
+            {"Members": {"Member": [
+                {"@Member_Id": "172", "DisplayAs": "Member 172"},
+                {"@Member_Id": "4212", "DisplayAs": "Member 4212"}
+            ]}}"#;
+        assert_eq!(InputFormat::detect(&xml), Some(InputFormat::Xml));
+        assert_eq!(InputFormat::detect(json), Some(InputFormat::Json));
+
+        for data in &[xml.as_str(), json] {
+            let members = parse_addresses(data, None, &Config::default()).unwrap();
+            let ids: Vec<u32> = members.members.iter().map(|m| m.id).collect();
+            assert_eq!(ids, [172, 4212]);
+        }
+
+        assert!(parse_addresses(json, Some(InputFormat::Xml), &Config::default()).is_err());
+        assert!(matches!(parse_addresses("Members: none", None, &Config::default()), Err(ParseError::UnknownFormat)));
+    }
+
+    #[test]
+    fn registered_interests_are_parsed() {
+        let members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+
+        let soubry = &members.members[1];
+        assert_eq!(soubry.id, 4212);
+        let interests: Vec<(&str, &str, Option<i32>)> = soubry.interests.iter()
+            .map(|i| (i.category.as_str(), i.description.as_str(), i.created.map(|d| d.year())))
+            .collect();
+        assert_eq!(interests, [
+            ("Employment and earnings", "Payments from Guardian News and Media, Kings Place, 90 York Way, London N1 9GU, for articles. Hours: 3 hrs. (Registered 12 March 2018)", Some(2018)),
+            ("Miscellaneous", "Non-practising barrister.", Some(2015)),
+        ]);
+        assert!(members.members[0].interests.is_empty());
+        assert!(members.unknown_elements.is_empty());
+    }
+
+    #[test]
+    fn warnings_are_returned_for_known_issues() {
+        let data = document(&[(172, "<Pronouns/><Addresses><Address><Type>Twitter</Type><Address1> </Address1></Address></Addresses>"),
+                              (4212, "<Pronouns/>"),
+                              (1524, "<Parties><Party><Name>Conservative</Name><StartDate>soon</StartDate></Party></Parties>")]);
+        let (members, warnings) = parse_addresses_xml_with_warnings(&data, &Config::default()).unwrap();
+
+        assert_eq!(members.members.len(), 2);
+        assert_eq!(warnings, [
+            ParseWarning::UnknownElement("Pronouns".to_string()),
+            ParseWarning::EmptySocialAddress { member_id: 172, kind: "Twitter".to_string() },
+            ParseWarning::SkippedMember { id: Some(1524), reason: "invalid party StartDate \"soon\" for member 1524 at Members>Member>Parties>Party>StartDate".to_string() },
+        ]);
+        assert_eq!(warnings[1].to_string(), "empty Twitter address for member 172");
+    }
+
+    #[test]
+    fn parallel_and_serial_parses_agree() {
+        // Every chunk sees the unknown element, but it should still only be warned about once.
+        let data = commons().replace("<DisplayAs>", "<Pronouns/><DisplayAs>");
+        let (serial, serial_warnings) = parse_addresses_xml_with_warnings(&data, &Config::default()).unwrap();
+        assert_eq!(serial_warnings, [ParseWarning::UnknownElement("Pronouns".to_string())]);
+
+        for threads in 2..=4 {
+            assert!(split_members(&data, threads).is_some());
+            let (parallel, parallel_warnings) = parse_in_chunks(&data, &Config::default(), threads, 0).unwrap();
+
+            let json = |members: &Members| serde_json::to_value(&members.members).unwrap();
+            assert_eq!(json(&parallel), json(&serial));
+            assert_eq!(parallel.unknown_elements, serial.unknown_elements);
+            assert_eq!(parallel_warnings, serial_warnings);
+        }
+    }
+
+    #[test]
+    fn government_posts_give_current_roles() {
+        let members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+
+        let soubry = &members.members[1];
+        let posts: Vec<(&str, i32, Option<i32>)> = soubry.government_posts.iter()
+            .map(|p| (p.name.as_str(), p.start_date.year(), p.end_date.map(|d| d.year())))
+            .collect();
+        assert_eq!(posts, [
+            ("Minister of State (Ministry of Defence) (Defence Personnel, Welfare and Veterans)", 2014, Some(2015)),
+            ("Minister of State (Department for Business, Innovation and Skills) (Small Business, Industry and Enterprise)", 2015, Some(2016)),
+        ]);
+        assert!(!soubry.is_minister());
+        assert!(members.unknown_elements.is_empty());
+
+        let data = document(&[(4520, "<GovernmentPosts><GovernmentPost Id=\"1234\">\
+            <Name>Parliamentary Under-Secretary of State (Department for International Trade)</Name>\
+            <StartDate>2019-07-26T00:00:00</StartDate><EndDate xsi:nil=\"true\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\"/>\
+            </GovernmentPost></GovernmentPosts>")]);
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+        assert!(members.members[0].is_minister());
+        assert_eq!(members.members[0].current_roles(), ["Parliamentary Under-Secretary of State (Department for International Trade)"]);
+    }
+
+    #[test]
+    fn optional_dates_may_be_nil_present_or_malformed() {
+        let formats = Config::default().date_formats;
+        assert_eq!(parse_optional_date("party EndDate", 172, true, "", &formats).unwrap(), None);
+        assert_eq!(parse_optional_date("party EndDate", 172, true, "unknown", &formats).unwrap(), None);
+        assert_eq!(parse_optional_date("party EndDate", 172, false, "  ", &formats).unwrap(), None);
+        assert_eq!(parse_optional_date("party EndDate", 172, false, "2017-06-08T00:00:00", &formats).unwrap(),
+                   Some(parse_date("2017-06-08").unwrap()));
+        match parse_optional_date("party EndDate", 172, false, "sometime in June", &formats) {
+            Err(ParseError::InvalidDate { member_id: 172, field: "party EndDate", value, .. }) =>
+                assert_eq!(value, "sometime in June"),
+            other => panic!("expected an invalid date, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn malformed_dates_skip_the_member_and_nil_dates_are_absent() {
+        let data = document(&[
+            (1, "<Parties><Party><Name>Labour</Name><StartDate>2010-05-06T00:00:00</StartDate><EndDate xsi:nil=\"true\"/></Party></Parties>"),
+            (2, "<Parties><Party><Name>Labour</Name><StartDate>2010-05-06T00:00:00</StartDate><EndDate>never</EndDate></Party></Parties>"),
+            (3, "<LastModified>yesterday</LastModified>"),
+            (4, "<LastModified xsi:nil=\"true\"/>"),
+        ]).replace("<Members>", "<Members xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">");
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+
+        let ids: Vec<u32> = members.members.iter().map(|m| m.id).collect();
+        assert_eq!(ids, [1, 4]);
+        assert_eq!(members.members[0].parties[0].end_date, None);
+        assert_eq!(members.members[1].modified, None);
+        let skipped: Vec<Option<u32>> = members.skipped.iter().map(|s| s.id).collect();
+        assert_eq!(skipped, [Some(2), Some(3)]);
+    }
+
+    #[test]
+    fn alternate_member_ids_are_captured() {
+        let members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+
+        let abbott = &members.members[0];
+        assert_eq!(abbott.alt_ids.get("dods").map(String::as_str), Some("25790"));
+        assert_eq!(abbott.alt_ids.get("pims").map(String::as_str), Some("3707"));
+        assert!(!abbott.alt_ids.contains_key("member"));
+
+        let soubry = &members.members[1];
+        assert_eq!(soubry.alt_ids.len(), 2);
+        assert!(!soubry.alt_ids.contains_key("clerks"));
+    }
+
+    #[test]
+    fn constituency_names_are_normalized() {
+        let aliases = Config::default().constituency_aliases;
+
+        assert_eq!(normalize_constituency("Ynys Môn", &aliases), "Ynys Môn");
+        assert_eq!(normalize_constituency("Ynys Mo\u{302}n", &aliases), "Ynys Môn");
+        assert_eq!(normalize_constituency("Ynys Mon", &aliases), "Ynys Môn");
+        assert_eq!(normalize_constituency("YNYS MÔN", &aliases), "Ynys Môn");
+
+        assert_eq!(normalize_constituency("Na h-Eileanan an Iar", &aliases), "Na h-Eileanan an Iar");
+        assert_eq!(normalize_constituency("Na h-Eileanan An Iar", &aliases), "Na h-Eileanan an Iar");
+        assert_eq!(normalize_constituency("STRATFORD-ON-AVON", &aliases), "Stratford-on-Avon");
+        assert_eq!(normalize_constituency("Weston \u{2013} super - Mare", &aliases), "Weston-super-Mare");
+        assert_eq!(normalize_constituency("  Hackney North   and Stoke Newington ", &aliases), "Hackney North and Stoke Newington");
+
+        let mut aliases = HashMap::new();
+        aliases.insert("Hackney N".to_string(), "Hackney North and Stoke Newington".to_string());
+        assert_eq!(normalize_constituency("hackney n", &aliases), "Hackney North and Stoke Newington");
+    }
+
+    #[test]
+    fn members_without_party_history_keep_their_party() {
+        let members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+
+        let hermon = members.members.iter().find(|m| m.id == 1437).unwrap();
+        assert!(hermon.parties.is_empty());
+        assert_eq!(hermon.party, "Independent");
+        assert_eq!(hermon.constituency, "North Down");
+        assert!(merge_parties(&[]).is_empty());
+    }
+
+    #[test]
+    fn each_default_date_format_is_tried() {
+        let expected = Utc.from_utc_datetime(&NaiveDate::from_ymd_opt(2017, 6, 8).unwrap().and_hms_opt(0, 0, 0).unwrap());
+        assert_eq!(parse_date("2017-06-08T00:00:00Z"), Ok(expected));
+        assert_eq!(parse_date("2017-06-08T01:00:00+01:00"), Ok(expected));
+        assert_eq!(parse_date(" 2017-06-08 "), Ok(expected));
+        assert_eq!(parse_date("2017-06-08T00:00:00"), Ok(expected));
+        assert_eq!(parse_date("2017-06-08T00:00:00.000"), Ok(expected));
+        assert!(parse_date("08/06/2017").is_err());
+    }
+
+    #[test]
+    fn custom_date_formats_can_be_added() {
+        let june_8 = Utc.from_utc_datetime(&NaiveDate::from_ymd_opt(2017, 6, 8).unwrap().and_hms_opt(0, 0, 0).unwrap());
+        let mut config = Config::default();
+        config.date_formats.push("%d/%m/%Y".to_string());
+
+        assert_eq!(parse_date_with("08/06/2017", &config.date_formats), Ok(june_8));
+        assert_eq!(parse_date_with("2017-06-08", &config.date_formats), parse_date("2017-06-08"));
+        assert!(parse_date_with("08/06/2017", &["%Y-%m-%d"]).is_err());
+        assert!(parse_date_with("2017-06-08T00:00:00Z", &[] as &[&str]).is_ok());
+
+        let data = document(&[(1, "<Parties><Party><Name>Labour</Name><StartDate>08/06/2017</StartDate></Party></Parties>")]);
+        let members = parse_addresses_xml(&data, &config).unwrap();
+        assert_eq!(members.members[0].parties[0].start_date, june_8);
+        assert!(parse_addresses_xml(&data, &Config::default()).unwrap().members.is_empty());
+    }
+
+    #[test]
+    fn seat_status_is_captured() {
+        let members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+        let status = |id| members.members.iter().find(|m| m.id == id).unwrap().status.as_deref();
+
+        assert_eq!(status(4005), Some("suspended"));
+        assert_eq!(status(172), Some("sitting"));
+        assert_eq!(status(1437), Some("sitting"));
+        assert_eq!(normalize_status(" Current Member "), Some("sitting".to_string()));
+        assert_eq!(normalize_status(""), None);
+        assert!(!members.unknown_elements.contains("CurrentStatus"));
+    }
+
+    #[test]
+    fn whip_removal_finds_the_move_to_independent() {
+        let independent = ["Independent".to_string()];
+        let removed = history(vec![party("Labour", "2010-05-06", Some("2020-10-29")),
+                                   party("Independent", "2020-10-29", None)]);
+        assert_eq!(whip_removal(&removed, &independent), Some(parse_date("2020-10-29").unwrap()));
+
+        let restored = history(vec![party("Labour", "2010-05-06", Some("2020-10-29")),
+                                    party("Independent", "2020-10-29", Some("2020-11-17")),
+                                    party("Labour", "2020-11-17", None)]);
+        assert_eq!(whip_removal(&restored, &independent), Some(parse_date("2020-10-29").unwrap()));
+
+        let loyal = history(vec![party("Labour", "2010-05-06", None)]);
+        assert_eq!(whip_removal(&loyal, &independent), None);
+        let always_independent = history(vec![party("Independent", "2010-05-06", None)]);
+        assert_eq!(whip_removal(&always_independent, &independent), None);
+    }
+
+    #[test]
+    fn official_twitter_accounts_are_told_apart() {
+        let members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+        let thomas = members.members.iter().find(|m| m.id == 4520).unwrap();
+
+        assert_eq!(thomas.twitter_handle().as_deref(), Some("GarethThomasHW"));
+        assert_eq!(thomas.twitter_official_handle().as_deref(), Some("GarethThomasMP"));
+        let official: Vec<u32> = members.members.iter().filter(|m| m.twitter_official.is_some()).map(|m| m.id).collect();
+        assert_eq!(official, [4520]);
+
+        assert!(is_official_note("Official parliamentary account"));
+        assert!(is_official_note("(official)"));
+        assert!(!is_official_note("Personal account"));
+        assert!(!is_official_note("Unofficial"));
+    }
+
+    #[test]
+    fn known_as_names_are_preferred_for_display() {
+        let members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+        let abbott = members.members.iter().find(|m| m.id == 172).unwrap();
+        let soubry = members.members.iter().find(|m| m.id == 4212).unwrap();
+
+        assert_eq!(abbott.name, "Ms Diane Abbott");
+        assert_eq!(abbott.known_as.as_deref(), Some("Diane Abbott"));
+        assert_eq!(abbott.preferred_name(), "Diane Abbott");
+        assert_eq!(soubry.known_as, None);
+        assert_eq!(soubry.preferred_name(), "Anna Soubry");
+    }
+
+    #[test]
+    fn same_day_spells_are_ordered_deterministically() {
+        let spell = |name: &str, end: &str| format!(
+            "<Party><Name>{}</Name><StartDate>2019-02-18T00:00:00</StartDate>{}</Party>", name, end);
+        let closed = spell("Change UK", "<EndDate>2019-06-04T00:00:00</EndDate>");
+        let open = spell("The Independent Group", "");
+        let labour = spell("Labour", "<EndDate>2019-06-04T00:00:00</EndDate>");
+
+        let names = |spells: &[&String]| {
+            let data = document(&[(4212, &format!("<Parties>{}</Parties>", spells.iter().map(|s| s.as_str()).collect::<String>()))]);
+            let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+            let member = &members.members[0];
+            let names: Vec<String> = member.parties.iter().map(|p| p.name.clone()).collect();
+            (names, member.same_day_switches().len())
+        };
+
+        // Histories are stored newest first, so the open spell leads.
+        let expected = (vec!["The Independent Group".to_string(), "Labour".to_string(), "Change UK".to_string()], 3);
+        assert_eq!(names(&[&open, &labour, &closed]), expected);
+        assert_eq!(names(&[&closed, &open, &labour]), expected);
+        assert_eq!(names(&[&labour, &closed, &open]), expected);
+    }
+
+    #[test]
+    fn house_dates_are_parsed() {
+        let data = document(&[
+            (1, "<HouseStartDate>1987-06-11T00:00:00</HouseStartDate><HouseEndDate xsi:nil=\"true\"/>"),
+            (2, "<HouseStartDate>sometime in 1987</HouseStartDate>"),
+            (3, "<HouseStartDate>1997-05-01T00:00:00</HouseStartDate><HouseEndDate>2019-01-01T00:00:00</HouseEndDate>"),
+        ]).replace("<Members>", "<Members xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">");
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+
+        let dates: Vec<_> = members.members.iter()
+            .map(|m| (m.id, m.house_start_date, m.house_end_date))
+            .collect();
+        assert_eq!(dates, [
+            (1, Some(parse_date("1987-06-11").unwrap()), None),
+            (3, Some(parse_date("1997-05-01").unwrap()), Some(parse_date("2019-01-01").unwrap())),
+        ]);
+        assert_eq!(members.skipped.len(), 1);
+        assert!(members.skipped[0].reason.contains("HouseStartDate"));
+    }
+
+    #[test]
+    fn malformed_xml_reports_where_it_broke() {
+        let data = document(&[(172, "<Parties><Party><Name>Labour</Name><StartDate>1987-06-11</Party></Parties>")]);
+
+        match parse_addresses_xml(&data, &Config::default()) {
+            Err(ParseError::Xml { path, .. }) => assert_eq!(path, "Members>Member>Parties>Party>StartDate"),
+            other => panic!("expected malformed XML, got {:?}", other.map(|m| m.members.len())),
+        }
+        let e = parse_addresses_xml("<Members", &Config::default()).err().unwrap();
+        assert_eq!(e.to_string(), "malformed XML at document root");
+    }
+
+    #[test]
+    fn invalid_oath_date_skips_the_member() {
+        let data = document(&[(1, "<OathDate>2017-06-13T00:00:00</OathDate>"), (2, "<OathDate>13/06/2017</OathDate>"),
+                              (3, "<OathDate xsi:nil=\"true\"/>")])
+            .replace("<Members>", "<Members xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">");
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+
+        let sworn: Vec<(u32, bool)> = members.members.iter().map(|m| (m.id, m.oath_date.is_some())).collect();
+        assert_eq!(sworn, [(1, true), (3, false)]);
+        assert_eq!(members.skipped.len(), 1);
+        assert!(members.skipped[0].reason.contains("OathDate"));
+    }
+
+    #[test]
+    fn aliased_elements_fill_the_canonical_field() {
+        let data = document(&[(172, "<Addresses><Address><Type>Twitter</Type><Handle>@HackneyAbbott</Handle></Address></Addresses>")]);
+
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+        assert_eq!(members.members[0].twitter_handle(), None);
+
+        let mut config = Config::default();
+        config.element_aliases.insert("Handle".to_string(), "Address1".to_string());
+        let members = parse_addresses_xml(&data, &config).unwrap();
+        assert_eq!(members.members[0].twitter_handle().as_deref(), Some("HackneyAbbott"));
+    }
+
+    #[test]
+    fn only_members_in_the_id_range_are_kept() {
+        let mut body: Vec<(u32, String)> = (1..=5000).map(|id| (id, String::new())).collect();
+        body[1049].1 = "<Parties><Party><Name>Labour</Name><StartDate>soon</StartDate></Party></Parties>".to_string();
+        body[3999].1 = "<Parties><Party><Name>Labour</Name><StartDate>soon</StartDate></Party></Parties>".to_string();
+        let body: Vec<(u32, &str)> = body.iter().map(|(id, b)| (*id, b.as_str())).collect();
+        let config = Config { member_ids: Some("1000-1099".parse().unwrap()), ..Config::default() };
+
+        let members = parse_addresses_xml(&document(&body), &config).unwrap();
+        assert_eq!(members.members.len(), 99);
+        assert!(members.iter().all(|m| (1000..=1099).contains(&m.id)));
+        assert_eq!(members.skipped.iter().map(|s| s.id).collect::<Vec<_>>(), [Some(1050)]);
+        assert_eq!(members.seen, 5000);
+
+        assert_eq!("-10".parse(), Ok(IdRange { from: 0, to: 10 }));
+        assert_eq!("4000-".parse(), Ok(IdRange { from: 4000, to: u32::MAX }));
+        assert_eq!("172".parse(), Ok(IdRange { from: 172, to: 172 }));
+        assert!("10-1".parse::<IdRange>().is_err());
+    }
+}
@@ -0,0 +1,201 @@
+use crate::commit::FailedMember;
+use crate::json::JsonStyle;
+use crate::members::{House, Members, SharedTwitterHandle, SkippedMember, TwitterName};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub started_at: DateTime<Utc>,
+    pub elapsed_ms: u64,
+    pub sources: Vec<SourceReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub sources: usize,
+    pub members: usize,
+    pub skipped: usize,
+    pub unknown_elements: usize,
+    pub empty_social_addresses: usize,
+    pub twitter_names: usize,
+    pub shared_twitter_handles: usize,
+    pub warnings: usize,
+    pub unchanged: usize,
+    pub failed_commits: usize,
+    pub twitter_coverage: f64,
+    pub elapsed_ms: u64,
+    pub parse_ms: u64,
+    pub output_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SourceReport {
+    pub house: String,
+    pub source: String,
+    pub members: usize,
+    pub skipped: Vec<SkippedMember>,
+    pub unknown_elements: Vec<String>,
+    pub empty_social_addresses: usize,
+    pub twitter_coverage: f64,
+    pub twitter_names: Vec<TwitterName>,
+    pub shared_twitter_handles: Vec<SharedTwitterHandle>,
+    pub fill_rates: BTreeMap<String, f64>,
+    pub parse_ms: u64,
+    pub output_ms: u64,
+    pub unchanged: usize,
+    pub failed_commits: Vec<FailedMember>,
+}
+
+impl Report {
+    pub fn new(started_at: DateTime<Utc>) -> Self {
+        Self {
+            started_at,
+            elapsed_ms: 0,
+            sources: vec![],
+        }
+    }
+
+    pub fn write(&self, path: &Path, style: JsonStyle) -> io::Result<()> {
+        let file = File::create(path)?;
+        style.to_writer(file, self)?;
+        Ok(())
+    }
+
+    pub fn summary(&self) -> Summary {
+        let members: usize = self.sources.iter().map(|s| s.members).sum();
+        let with_twitter: f64 = self.sources.iter().map(|s| s.twitter_coverage * s.members as f64).sum();
+        Summary {
+            sources: self.sources.len(),
+            members,
+            skipped: self.sources.iter().map(|s| s.skipped.len()).sum(),
+            unknown_elements: self.sources.iter().map(|s| s.unknown_elements.len()).sum(),
+            empty_social_addresses: self.sources.iter().map(|s| s.empty_social_addresses).sum(),
+            twitter_names: self.sources.iter().map(|s| s.twitter_names.len()).sum(),
+            shared_twitter_handles: self.sources.iter().map(|s| s.shared_twitter_handles.len()).sum(),
+            warnings: self.sources.iter().map(|s| s.warnings()).sum(),
+            unchanged: self.sources.iter().map(|s| s.unchanged).sum(),
+            failed_commits: self.sources.iter().map(|s| s.failed_commits.len()).sum(),
+            twitter_coverage: if members == 0 { 0.0 } else { with_twitter / members as f64 },
+            elapsed_ms: self.elapsed_ms,
+            parse_ms: self.sources.iter().map(|s| s.parse_ms).sum(),
+            output_ms: self.sources.iter().map(|s| s.output_ms).sum(),
+        }
+    }
+}
+
+impl SourceReport {
+    pub fn new(house: House, source: &str, members: &Members, parse_time: Duration) -> Self {
+        Self {
+            house: house.to_string(),
+            source: source.to_string(),
+            members: members.members.len(),
+            skipped: members.skipped.clone(),
+            unknown_elements: members.unknown_elements.iter().cloned().collect(),
+            empty_social_addresses: members.empty_social_addresses,
+            twitter_coverage: members.twitter_coverage(),
+            twitter_names: members.twitter_names(),
+            shared_twitter_handles: members.shared_twitter_handles(),
+            fill_rates: members.fill_rates(),
+            parse_ms: parse_time.as_millis() as u64,
+            output_ms: 0,
+            unchanged: 0,
+            failed_commits: vec![],
+        }
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.skipped.len() + self.unknown_elements.len() + self.empty_social_addresses + self.twitter_names.len()
+            + self.shared_twitter_handles.len() + self.failed_commits.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::parse::parse_addresses_xml;
+    use crate::test_support::{commons, document, TempDir};
+    use serde_json::{json, Value};
+
+    #[test]
+    fn report_describes_each_source() {
+        let data = document(&[(172, "<Addresses><Address><Type>Twitter</Type><Address1>@HackneyAbbott</Address1></Address></Addresses>"),
+                              (4212, "<Pronouns/><Addresses><Address><Type>Twitter</Type><Address1></Address1></Address></Addresses>"),
+                              (1524, "<Parties><Party><Name>Conservative</Name><StartDate>soon</StartDate></Party></Parties>")]);
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+
+        let mut report = Report::new(Utc::now());
+        report.sources.push(SourceReport::new(House::Commons, "fixture.xml", &members, Duration::from_millis(12)));
+
+        let dir = TempDir::new("report");
+        let path = dir.path().join("report.json");
+        report.write(&path, JsonStyle::Compact).unwrap();
+        let written: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+
+        let mut keys: Vec<&str> = written.as_object().unwrap().keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        assert_eq!(keys, ["elapsed_ms", "sources", "started_at"]);
+        assert_eq!(written["sources"], json!([{
+            "house": "Commons",
+            "source": "fixture.xml",
+            "members": 2,
+            "skipped": [{"id": 1524, "reason": "invalid party StartDate \"soon\" for member 1524 at Members>Member>Parties>Party>StartDate"}],
+            "unknown_elements": ["Pronouns"],
+            "empty_social_addresses": 1,
+            "twitter_coverage": 50.0,
+            "twitter_names": [],
+            "shared_twitter_handles": [],
+            "fill_rates": {
+                "constituency": 0.0, "constituency_id": 0.0, "email": 0.0, "facebook": 0.0, "full_title": 0.0,
+                "known_as": 0.0, "list_as": 0.0, "name": 100.0, "parties": 0.0, "party": 0.0, "twitter": 50.0,
+            },
+            "parse_ms": 12,
+            "output_ms": 0,
+            "unchanged": 0,
+            "failed_commits": [],
+        }]));
+    }
+
+    #[test]
+    fn summary_totals_every_source() {
+        let data = document(&[(172, "<Addresses><Address><Type>Twitter</Type><Address1>@HackneyAbbott</Address1></Address></Addresses>"),
+                              (4212, "<Pronouns/><Addresses><Address><Type>Twitter</Type><Address1></Address1></Address></Addresses>"),
+                              (1524, "<Parties><Party><Name>Conservative</Name><StartDate>soon</StartDate></Party></Parties>")]);
+        let mut report = Report::new(Utc::now());
+        report.elapsed_ms = 250;
+        report.sources.push(SourceReport::new(House::Commons, "commons.xml", &parse_addresses_xml(commons(), &Config::default()).unwrap(),
+                                              Duration::from_millis(30)));
+        let mut source = SourceReport::new(House::Commons, "fixture.xml", &parse_addresses_xml(&data, &Config::default()).unwrap(),
+                                           Duration::from_millis(12));
+        source.output_ms = 40;
+        source.unchanged = 1;
+        report.sources.push(source);
+
+        let summary = serde_json::to_value(report.summary()).unwrap();
+        let coverage = summary["twitter_coverage"].as_f64().unwrap();
+        assert!((coverage - 600.0 / 9.0).abs() < 1e-9, "{}", coverage);
+        assert_eq!(summary, json!({
+            "sources": 2,
+            "members": 9,
+            "skipped": 1,
+            "unknown_elements": 1,
+            "empty_social_addresses": 1,
+            "twitter_names": 1,
+            "shared_twitter_handles": 0,
+            "warnings": 4,
+            "unchanged": 1,
+            "failed_commits": 0,
+            "twitter_coverage": coverage,
+            "elapsed_ms": 250,
+            "parse_ms": 42,
+            "output_ms": 40,
+        }));
+        assert_eq!(Report::new(Utc::now()).summary().twitter_coverage, 0.0);
+    }
+}
@@ -0,0 +1,478 @@
+use crate::checkpoint::Checkpoint;
+use crate::commit::{self, CommitError, CommitOptions, ConflictStrategy, Provenance};
+use crate::config::Config;
+use crate::csv;
+use crate::graphml;
+use crate::json::JsonStyle;
+use crate::loader::{LoadError, Output};
+use crate::markdown;
+use crate::members::{self, House, Member, Members};
+use crate::report::SourceReport;
+use crate::spill::{self, Spill};
+use crate::sqlite;
+use crate::verify;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub struct Batch<'a> {
+    pub house: House,
+    pub members: &'a Members,
+    pub provenance: &'a Provenance,
+    pub config: &'a Config,
+    pub json_style: JsonStyle,
+    pub flatten_parties: bool,
+    pub redact_contact: bool,
+}
+
+pub trait Sink {
+    fn name(&self) -> String;
+    fn write(&self, batch: &Batch, report: &mut SourceReport) -> Result<(), LoadError>;
+}
+
+pub struct DgraphSink {
+    pub addr: String,
+    pub options: CommitOptions,
+    pub spill_dir: Option<PathBuf>,
+    pub verify_writes: bool,
+}
+
+impl DgraphSink {
+    fn spill(&self, batch: &Batch, source: CommitError) -> LoadError {
+        let house = batch.house;
+        let dir = match &self.spill_dir {
+            Some(dir) => dir,
+            None => return LoadError::Commit { house, source }
+        };
+
+        let spill = Spill {
+            house,
+            source: batch.provenance.source_url.clone(),
+            fetched_at: batch.provenance.fetched_at,
+            members: batch.members.members.clone(),
+        };
+        match spill::write_spill(dir, &spill, batch.json_style) {
+            Ok(path) => {
+                log!("Spilled {} members to {}", spill.members.len(), path.display());
+                LoadError::Spilled { house, path, source }
+            }
+            Err(e) => {
+                log!("Unable to spill members to {}: {}", dir.display(), e);
+                LoadError::Commit { house, source }
+            }
+        }
+    }
+}
+
+impl Sink for DgraphSink {
+    fn name(&self) -> String {
+        format!("dgraph at {}", self.addr)
+    }
+
+    fn write(&self, batch: &Batch, report: &mut SourceReport) -> Result<(), LoadError> {
+        let house = batch.house;
+
+        log!("Connecting to dgraph...");
+        let dgraph = dgraph::make_dgraph!(dgraph::new_dgraph_client(&self.addr));
+
+        log!("Commiting data...");
+        let checkpoint = Checkpoint::for_run(&house.to_string(), batch.members.members.iter().map(|m| m.id));
+        let summary = commit::commit_member_data(&dgraph, batch.members, batch.provenance, &checkpoint, &self.options)
+            .map_err(|e| self.spill(batch, e))?;
+        if self.options.only_changed {
+            log!("Committed {} members, skipped {} unchanged", summary.committed, summary.unchanged);
+        }
+        if self.options.conflict == ConflictStrategy::KeepExisting {
+            log!("Kept {} members already stored in dgraph", summary.kept);
+        }
+        log!("Committed at {:.1} members/s over {:.1}s with {} retries across {} concurrent transactions", summary.rate(),
+                 summary.elapsed.as_secs_f64(), summary.retries, self.options.concurrency);
+        report.unchanged = summary.unchanged;
+        if !summary.failed.is_empty() {
+            log!("Failed to commit {} members:", summary.failed.len());
+            for failed in &summary.failed {
+                log!("  {}: {}", failed.member_id, failed.reason);
+            }
+        }
+        report.failed_commits = summary.failed;
+
+        if self.verify_writes {
+            log!("Verifying committed members...");
+            let stored = commit::stored_members(&dgraph).map_err(|e| LoadError::Commit { house, source: e })?;
+            let discrepancies = verify::check_writes(batch.members, &stored);
+            for d in &discrepancies {
+                log!("  {}", d);
+            }
+            if !discrepancies.is_empty() {
+                return Err(LoadError::WriteVerification { house, discrepancies });
+            }
+            log!("Read back all {} committed members", batch.members.members.len());
+        }
+
+        Ok(())
+    }
+}
+
+pub struct SqliteSink {
+    pub path: PathBuf,
+}
+
+impl Sink for SqliteSink {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn write(&self, batch: &Batch, _report: &mut SourceReport) -> Result<(), LoadError> {
+        log!("Writing data to {}...", self.path.display());
+        sqlite::write_members(&self.path, batch.members)
+            .map_err(|e| LoadError::Sqlite { path: self.path.clone(), source: e })
+    }
+}
+
+pub struct GraphmlSink {
+    pub path: PathBuf,
+}
+
+impl Sink for GraphmlSink {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn write(&self, batch: &Batch, _report: &mut SourceReport) -> Result<(), LoadError> {
+        log!("Writing graph to {}...", self.path.display());
+        graphml::write_graphml(&self.path, batch.members, batch.config)
+            .map_err(|e| LoadError::Graphml { path: self.path.clone(), source: e })
+    }
+}
+
+pub struct JsonSink {
+    pub path: PathBuf,
+}
+
+impl Sink for JsonSink {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn write(&self, batch: &Batch, _report: &mut SourceReport) -> Result<(), LoadError> {
+        log!("Writing data to {}...", self.path.display());
+        let file = File::create(&self.path).map_err(|e| LoadError::Json { path: self.path.clone(), source: e })?;
+        let written = if batch.flatten_parties || batch.redact_contact {
+            let values: Vec<_> = batch.members.members.iter().map(|m| {
+                let mut value = if batch.flatten_parties { m.flattened() } else { serde_json::to_value(m).expect("Invalid json") };
+                if batch.redact_contact {
+                    members::redact_contact(&mut value);
+                }
+                value
+            }).collect();
+            batch.json_style.to_writer(file, &values)
+        } else {
+            batch.json_style.to_writer(file, &batch.members.members)
+        };
+        written.map_err(|e| LoadError::Json { path: self.path.clone(), source: e.into() })
+    }
+}
+
+pub struct CsvSink {
+    pub path: PathBuf,
+}
+
+impl Sink for CsvSink {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn write(&self, batch: &Batch, _report: &mut SourceReport) -> Result<(), LoadError> {
+        log!("Writing data to {}...", self.path.display());
+        csv::write_csv(&self.path, batch.members, batch.flatten_parties, batch.redact_contact)
+            .map_err(|e| LoadError::Csv { path: self.path.clone(), source: e })
+    }
+}
+
+pub struct MarkdownSink {
+    pub path: PathBuf,
+}
+
+impl Sink for MarkdownSink {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn write(&self, batch: &Batch, _report: &mut SourceReport) -> Result<(), LoadError> {
+        log!("Writing Markdown table to {}...", self.path.display());
+        markdown::write_markdown(&self.path, batch.members, batch.redact_contact)
+            .map_err(|e| LoadError::Markdown { path: self.path.clone(), source: e })
+    }
+}
+
+pub struct LiveJsonSink {
+    pub path: PathBuf,
+}
+
+impl Sink for LiveJsonSink {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn write(&self, batch: &Batch, _report: &mut SourceReport) -> Result<(), LoadError> {
+        log!("Writing dgraph live loader data to {}...", self.path.display());
+        let file = File::create(&self.path).map_err(|e| LoadError::Json { path: self.path.clone(), source: e })?;
+        commit::write_live_json(BufWriter::new(file), batch.members, batch.provenance, &batch.config.fingerprint_fields, batch.json_style)
+            .map_err(|e| LoadError::Json { path: self.path.clone(), source: e.into() })
+    }
+}
+
+pub fn file_sink(kind: Output, path: &Path) -> Option<Box<dyn Sink>> {
+    let path = path.to_path_buf();
+    Some(match kind {
+        Output::Dgraph => return None,
+        Output::Sqlite => Box::new(SqliteSink { path }),
+        Output::Graphml => Box::new(GraphmlSink { path }),
+        Output::Json => Box::new(JsonSink { path }),
+        Output::Csv => Box::new(CsvSink { path }),
+        Output::Markdown => Box::new(MarkdownSink { path }),
+        Output::LiveJson => Box::new(LiveJsonSink { path }),
+    })
+}
+
+fn party_file_stem(party: &str) -> String {
+    let mut stem = String::new();
+    for c in party.chars().flat_map(char::to_lowercase) {
+        if c.is_ascii_alphanumeric() {
+            stem.push(c);
+        } else if !stem.is_empty() && !stem.ends_with('-') {
+            stem.push('-');
+        }
+    }
+    let stem = stem.trim_end_matches('-');
+    if stem.is_empty() { "no-party".to_string() } else { stem.to_string() }
+}
+
+pub struct PartySplitSink {
+    pub dir: PathBuf,
+    pub kind: Output,
+}
+
+impl Sink for PartySplitSink {
+    fn name(&self) -> String {
+        format!("{} per party in {}", self.kind.extension(), self.dir.display())
+    }
+
+    fn write(&self, batch: &Batch, report: &mut SourceReport) -> Result<(), LoadError> {
+        fs::create_dir_all(&self.dir).map_err(|e| LoadError::Split { dir: self.dir.clone(), source: e })?;
+
+        for (party, members) in batch.members.by_party() {
+            let path = self.dir.join(format!("{}.{}", party_file_stem(&party), self.kind.extension()));
+            let sink = match file_sink(self.kind, &path) {
+                Some(sink) => sink,
+                None => return Ok(())
+            };
+            sink.write(&Batch { members: &members, ..*batch }, report)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct InMemorySink {
+    members: Arc<Mutex<HashMap<u32, Member>>>,
+}
+
+impl InMemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, id: u32) -> Option<Member> {
+        self.members.lock().unwrap().get(&id).cloned()
+    }
+
+    pub fn members(&self) -> Members {
+        let mut members = Members::new();
+        members.members = self.members.lock().unwrap().values().cloned().collect();
+        members.members.sort_by_key(|m| m.id);
+        members
+    }
+
+    pub fn find(&self, query: &str) -> Vec<Member> {
+        self.members().find(query).into_iter().cloned().collect()
+    }
+}
+
+impl Sink for InMemorySink {
+    fn name(&self) -> String {
+        "in-memory store".to_string()
+    }
+
+    fn write(&self, batch: &Batch, _report: &mut SourceReport) -> Result<(), LoadError> {
+        let mut members = self.members.lock().unwrap();
+        for member in &batch.members.members {
+            members.insert(member.id, member.clone());
+        }
+        log!("Stored {} members in memory ({} total)", batch.members.members.len(), members.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::{Input, Loader};
+    use crate::parse::parse_addresses_xml;
+    use crate::test_support::{commons, document, MockResponse, MockServer, TempDir};
+    use chrono::Utc;
+    use std::time::Duration;
+
+    #[test]
+    fn loaded_members_can_be_queried_in_memory() {
+        let server = MockServer::new(vec![MockResponse::ok(commons())]);
+        let store = InMemorySink::new();
+
+        Loader::new()
+            .api_base(&format!("{}/Members/Query/", server.url()))
+            .request_delay(Duration::from_millis(0))
+            .min_members(Some(0))
+            .sink(Box::new(store.clone()))
+            .build()
+            .unwrap()
+            .run()
+            .unwrap();
+
+        assert_eq!(store.len(), 7);
+        assert_eq!(store.get(4064).map(|m| m.name), Some("Liz Saville Roberts".to_string()));
+        assert!(store.get(1).is_none());
+        let found: Vec<u32> = store.find("abbott").iter().map(|m| m.id).collect();
+        assert_eq!(found, [172]);
+        let ids: Vec<u32> = store.members().members.iter().map(|m| m.id).collect();
+        assert_eq!(ids, [172, 1437, 1524, 4005, 4064, 4212, 4520]);
+    }
+
+    #[test]
+    fn failed_dgraph_commits_are_spilled_and_can_be_replayed() {
+        let dir = TempDir::new("spill");
+        let members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+        let provenance = Provenance { fetched_at: Utc::now(), source_url: "fixtures/commons.xml".to_string() };
+        let config = Config::default();
+        let batch = Batch {
+            house: House::Commons,
+            members: &members,
+            provenance: &provenance,
+            config: &config,
+            json_style: JsonStyle::Compact,
+            flatten_parties: false,
+            redact_contact: false,
+        };
+        let refused = || CommitError::Commit(failure::err_msg("connection refused").compat());
+
+        let sink = DgraphSink { addr: "localhost:9080".to_string(), options: CommitOptions::default(), spill_dir: None,
+                                verify_writes: false };
+        assert!(matches!(sink.spill(&batch, refused()), LoadError::Commit { house: House::Commons, .. }));
+
+        let sink = DgraphSink { spill_dir: Some(dir.path().join("spill")), ..sink };
+        let path = match sink.spill(&batch, refused()) {
+            LoadError::Spilled { house: House::Commons, path, .. } => path,
+            e => panic!("expected the members to be spilled, got {}", e)
+        };
+        assert!(path.starts_with(dir.path().join("spill")));
+
+        let store = InMemorySink::new();
+        Loader::new()
+            .input(Input::Spill(path))
+            .min_members(Some(0))
+            .sink(Box::new(store.clone()))
+            .build()
+            .unwrap()
+            .run()
+            .unwrap();
+        assert_eq!(store.len(), members.members.len());
+        assert_eq!(store.get(4212).map(|m| m.name), Some("Anna Soubry".to_string()));
+    }
+
+    #[test]
+    fn contact_details_are_redacted_from_exports() {
+        let dir = TempDir::new("redact");
+        let members = parse_addresses_xml(commons(), &Config::default()).unwrap();
+        let provenance = Provenance { fetched_at: Utc::now(), source_url: "fixtures/commons.xml".to_string() };
+        let config = Config::default();
+        let json = JsonSink { path: dir.path().join("members.json") };
+        let csv = CsvSink { path: dir.path().join("members.csv") };
+
+        for &redact_contact in &[false, true] {
+            let batch = Batch {
+                house: House::Commons,
+                members: &members,
+                provenance: &provenance,
+                config: &config,
+                json_style: JsonStyle::Compact,
+                flatten_parties: false,
+                redact_contact,
+            };
+            let mut report = SourceReport::new(House::Commons, "fixtures/commons.xml", &members, Duration::from_millis(0));
+            json.write(&batch, &mut report).unwrap();
+            csv.write(&batch, &mut report).unwrap();
+
+            let written: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&json.path).unwrap()).unwrap();
+            let abbott = &written[0];
+            assert_eq!(abbott["name"], "Ms Diane Abbott");
+            assert_eq!(abbott["twitter"], members.members[0].twitter.as_deref().unwrap());
+            assert_eq!(abbott["party"], "Labour");
+            assert_eq!(abbott.get("parliamentary_email").is_some(), !redact_contact);
+            assert_eq!(abbott.get("constituency_email").is_some(), !redact_contact);
+
+            let rows = std::fs::read_to_string(&csv.path).unwrap();
+            let header: Vec<&str> = rows.lines().next().unwrap().split(',').collect();
+            assert!(header.contains(&"twitter"));
+            assert_eq!(header.contains(&"email"), !redact_contact);
+            assert_eq!(rows.contains("@parliament.uk"), !redact_contact);
+        }
+    }
+
+    #[test]
+    fn members_are_split_into_one_file_per_party() {
+        let dir = TempDir::new("split");
+        let data = document(&[
+            (172, "<Party Id=\"15\">Labour</Party>"),
+            (4212, "<Party Id=\"15\">Labour (Co-op)</Party>"),
+            (4064, "<Party Id=\"15\">Labour</Party>"),
+        ]);
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+        let provenance = Provenance { fetched_at: Utc::now(), source_url: "members.xml".to_string() };
+        let config = Config::default();
+        let batch = Batch {
+            house: House::Commons,
+            members: &members,
+            provenance: &provenance,
+            config: &config,
+            json_style: JsonStyle::Compact,
+            flatten_parties: false,
+            redact_contact: false,
+        };
+        let mut report = SourceReport::new(House::Commons, "members.xml", &members, Duration::from_millis(0));
+        let split = dir.path().join("parties");
+        PartySplitSink { dir: split.clone(), kind: Output::Json }.write(&batch, &mut report).unwrap();
+
+        let mut files: Vec<String> = std::fs::read_dir(&split).unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["labour-co-op.json", "labour.json"]);
+
+        let ids = |file: &str| {
+            let written: Vec<serde_json::Value> = serde_json::from_str(&std::fs::read_to_string(split.join(file)).unwrap()).unwrap();
+            written.iter().map(|m| m["id"].as_u64().unwrap()).collect::<Vec<_>>()
+        };
+        assert_eq!(ids("labour.json"), [172, 4064]);
+        assert_eq!(ids("labour-co-op.json"), [4212]);
+    }
+}
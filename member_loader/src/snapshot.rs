@@ -0,0 +1,61 @@
+use crate::members::Members;
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const DEFAULT_SNAPSHOT_FILE: &str = "members.snapshot.json";
+
+// Fields that change between fetches without the membership itself changing
+const VOLATILE_FIELDS: &[&str] = &["modified"];
+
+pub fn canonical_json(members: &Members) -> String {
+    let mut sorted = members.members.iter().collect::<Vec<_>>();
+    sorted.sort_by_key(|m| m.id);
+
+    let values = sorted.into_iter().map(|m| {
+        let mut value = serde_json::to_value(m).expect("Invalid json");
+        if let Some(object) = value.as_object_mut() {
+            for field in VOLATILE_FIELDS {
+                object.remove(*field);
+            }
+        }
+        value
+    }).collect::<Vec<Value>>();
+
+    let mut out = serde_json::to_string_pretty(&values).expect("Invalid json");
+    out.push('\n');
+    out
+}
+
+pub fn write_snapshot(path: &Path, members: &Members) -> io::Result<()> {
+    fs::write(path, canonical_json(members))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::parse::parse_addresses_xml;
+    use crate::test_support::{commons, TempDir};
+
+    #[test]
+    fn identical_data_gives_byte_identical_snapshots() {
+        let dir = TempDir::new("snapshot");
+        let (first, second) = (dir.path().join("first.json"), dir.path().join("second.json"));
+
+        write_snapshot(&first, &parse_addresses_xml(commons(), &Config::default()).unwrap()).unwrap();
+        let mut again = parse_addresses_xml(commons(), &Config::default()).unwrap();
+        again.members.reverse();
+        for member in &mut again.members {
+            member.modified = None;
+        }
+        write_snapshot(&second, &again).unwrap();
+
+        let first = fs::read(&first).unwrap();
+        assert_eq!(first, fs::read(&second).unwrap());
+        let ids: Vec<u64> = serde_json::from_slice::<Vec<Value>>(&first).unwrap().iter()
+            .map(|m| m["id"].as_u64().unwrap()).collect();
+        assert_eq!(ids, [172, 1437, 1524, 4005, 4064, 4212, 4520]);
+    }
+}
@@ -0,0 +1,42 @@
+use crate::json::JsonStyle;
+use crate::members::{House, Member};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Spill {
+    pub house: House,
+    pub source: String,
+    pub fetched_at: DateTime<Utc>,
+    pub members: Vec<Member>,
+}
+
+pub fn write_spill(dir: &Path, spill: &Spill, style: JsonStyle) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+
+    let stem = format!("{}-{}", spill.house, Utc::now().format("%Y%m%dT%H%M%SZ"));
+    let mut n = 0;
+    loop {
+        let path = match n {
+            0 => dir.join(format!("{}.json", stem)),
+            n => dir.join(format!("{}-{}.json", stem, n)),
+        };
+
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(f) => {
+                style.to_writer(f, spill)?;
+                return Ok(path);
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(e)
+        }
+    }
+}
+
+pub fn read_spill(path: &Path) -> io::Result<Spill> {
+    let data = fs::read_to_string(path)?;
+    serde_json::from_str(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
@@ -0,0 +1,107 @@
+use crate::members::Members;
+use rusqlite::{params, Connection};
+use std::path::Path;
+
+const SCHEMA: &str = "
+PRAGMA foreign_keys = ON;
+
+CREATE TABLE IF NOT EXISTS members (
+    member_id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    full_title TEXT,
+    list_as TEXT,
+    party TEXT NOT NULL,
+    house TEXT NOT NULL,
+    constituency TEXT NOT NULL,
+    normalized_constituency TEXT NOT NULL,
+    constituency_id INTEGER,
+    status TEXT,
+    whip_removed TEXT,
+    twitter TEXT,
+    facebook TEXT,
+    email TEXT,
+    constituency_email TEXT,
+    parliamentary_email TEXT
+);
+
+CREATE TABLE IF NOT EXISTS parties (
+    party_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS memberships (
+    member_id INTEGER NOT NULL REFERENCES members(member_id) ON DELETE CASCADE,
+    party_id INTEGER NOT NULL REFERENCES parties(party_id),
+    original_name TEXT NOT NULL,
+    start_date TEXT NOT NULL,
+    end_date TEXT,
+    PRIMARY KEY (member_id, start_date)
+);
+";
+
+pub fn write_members(path: &Path, members: &Members) -> rusqlite::Result<()> {
+    let mut conn = Connection::open(path)?;
+    conn.execute_batch(SCHEMA)?;
+
+    let txn = conn.transaction()?;
+    for member in &members.members {
+        txn.execute(
+            "INSERT OR REPLACE INTO members (member_id, name, full_title, list_as, party, house, constituency, normalized_constituency, constituency_id, status, whip_removed, twitter, facebook, email, constituency_email, parliamentary_email)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+            params![member.id, member.name, member.full_title, member.list_as, member.party,
+                    member.house.to_string(), member.constituency, member.normalized_constituency, member.constituency_id, member.status, member.whip_removed.map(|d| d.to_rfc3339()), member.twitter, member.facebook,
+                    member.email(), member.constituency_email, member.parliamentary_email],
+        )?;
+
+        txn.execute("DELETE FROM memberships WHERE member_id = ?1", params![member.id])?;
+        for party in &member.parties {
+            txn.execute("INSERT OR IGNORE INTO parties (name) VALUES (?1)", params![party.name])?;
+            let party_id: i64 = txn.query_row("SELECT party_id FROM parties WHERE name = ?1", params![party.name], |row| row.get(0))?;
+
+            txn.execute(
+                "INSERT OR REPLACE INTO memberships (member_id, party_id, original_name, start_date, end_date)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![member.id, party_id, party.original_name, party.start_date.to_rfc3339(),
+                        party.end_date.map(|d| d.to_rfc3339())],
+            )?;
+        }
+    }
+    txn.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::parse::parse_addresses_xml;
+    use crate::test_support::{document, TempDir};
+
+    #[test]
+    fn members_round_trip_through_sqlite() {
+        let data = document(&[(172, "<Party>Labour</Party><MemberFrom>Hackney North and Stoke Newington</MemberFrom>\
+            <Parties><Party><Name>Labour</Name><StartDate>1987-06-11T00:00:00</StartDate><EndDate>2017-03-01T00:00:00</EndDate></Party>\
+            <Party><Name>Labour (Co-op)</Name><StartDate>2017-03-01T00:00:00</StartDate></Party></Parties>"),
+            (4212, "<Party>Labour</Party><Parties><Party><Name>Labour</Name><StartDate>2015-05-07T00:00:00</StartDate></Party></Parties>")]);
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+
+        let dir = TempDir::new("sqlite");
+        let path = dir.path().join("members.db");
+        write_members(&path, &members).unwrap();
+        write_members(&path, &members).unwrap();
+
+        let conn = Connection::open(&path).unwrap();
+        let (name, constituency): (String, String) = conn.query_row(
+            "SELECT name, constituency FROM members WHERE member_id = 172", params![], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+        assert_eq!((name.as_str(), constituency.as_str()), ("Member 172", "Hackney North and Stoke Newington"));
+
+        let counts: (i64, i64, i64) = conn.query_row(
+            "SELECT (SELECT COUNT(*) FROM members), (SELECT COUNT(*) FROM parties), (SELECT COUNT(*) FROM memberships)",
+            params![], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?))).unwrap();
+        assert_eq!(counts, (2, 1, 2));
+
+        let original: String = conn.query_row(
+            "SELECT m.original_name FROM memberships m JOIN parties p ON p.party_id = m.party_id WHERE m.member_id = 172 AND p.name = 'Labour'",
+            params![], |row| row.get(0)).unwrap();
+        assert_eq!(original, "Labour (Co-op)");
+    }
+}
@@ -0,0 +1,134 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// A scratch directory under the system temp dir, removed again on drop.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "member_loader-{}-{}-{}", name, std::process::id(), NEXT_DIR.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&path).expect("failed to create temp dir");
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Wraps `(Member_Id, inner XML)` pairs in a minimal members feed.
+pub fn document(members: &[(u32, &str)]) -> String {
+    let members: String = members.iter()
+        .map(|(id, body)| format!("<Member Member_Id=\"{}\"><DisplayAs>Member {}</DisplayAs>{}</Member>", id, id, body))
+        .collect();
+    format!("<?xml version=\"1.0\" encoding=\"utf-8\"?><Members>{}</Members>", members)
+}
+
+/// The checked-in Commons feed under `fixtures/`.
+pub fn commons() -> &'static str {
+    include_str!("../fixtures/commons.xml")
+}
+
+/// A canned HTTP response served by `MockServer`.
+pub struct MockResponse {
+    status: u16,
+    body: Vec<u8>,
+    content_length: bool,
+}
+
+impl MockResponse {
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self { status: 200, body: body.into(), content_length: true }
+    }
+
+    /// Leaves out the Content-Length header, so the body runs until the connection closes.
+    pub fn without_content_length(mut self) -> Self {
+        self.content_length = false;
+        self
+    }
+}
+
+/// The lower-cased name and value of each header sent with a request.
+pub type RequestHeaders = Vec<(String, String)>;
+
+/// Serves one canned response per connection on a local port, recording the requested paths
+/// and headers.
+pub struct MockServer {
+    url: String,
+    paths: Arc<Mutex<Vec<String>>>,
+    headers: Arc<Mutex<Vec<RequestHeaders>>>,
+}
+
+impl MockServer {
+    pub fn new(responses: Vec<MockResponse>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind mock server");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let paths = Arc::new(Mutex::new(vec![]));
+        let headers = Arc::new(Mutex::new(vec![]));
+
+        let recorded = paths.clone();
+        let recorded_headers = headers.clone();
+        thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = match listener.accept() {
+                    Ok(s) => s,
+                    Err(_) => return
+                };
+
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut line = String::new();
+                let mut request_headers = vec![];
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    if let Some((name, value)) = line.trim_end().split_once(':') {
+                        request_headers.push((name.to_ascii_lowercase(), value.trim().to_string()));
+                    }
+                    line.clear();
+                }
+                recorded.lock().unwrap().push(request_line.split(' ').nth(1).unwrap_or("").to_string());
+                recorded_headers.lock().unwrap().push(request_headers);
+
+                let length = if response.content_length {
+                    format!("Content-Length: {}\r\n", response.body.len())
+                } else {
+                    String::new()
+                };
+                let head = format!("HTTP/1.1 {} Mock\r\n{}Connection: close\r\n\r\n", response.status, length);
+                let _ = stream.write_all(head.as_bytes()).and_then(|_| stream.write_all(&response.body));
+            }
+        });
+
+        Self { url, paths, headers }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn paths(&self) -> Vec<String> {
+        self.paths.lock().unwrap().clone()
+    }
+
+    /// The headers of each request, with lowercased names.
+    pub fn headers(&self) -> Vec<RequestHeaders> {
+        self.headers.lock().unwrap().clone()
+    }
+}
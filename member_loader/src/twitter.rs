@@ -0,0 +1,153 @@
+use crate::api::{ApiClient, ApiError};
+use crate::members::Members;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub const TWITTER_API_BASE: &str = "https://api.twitter.com/2/users/by";
+
+const LOOKUP_BATCH_SIZE: usize = 100;
+
+#[derive(Debug, Error)]
+pub enum TwitterError {
+    #[error(transparent)]
+    Api(#[from] ApiError),
+    #[error("invalid response from {url}")]
+    Response {
+        url: String,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum HandleStatus {
+    Ok,
+    Renamed {
+        username: String,
+    },
+    Missing,
+    Suspended,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HandleCheck {
+    pub member_id: u32,
+    pub name: String,
+    pub handle: String,
+    #[serde(flatten)]
+    pub status: HandleStatus,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct LookupResponse {
+    #[serde(default)]
+    pub data: Vec<LookupUser>,
+    #[serde(default)]
+    pub errors: Vec<LookupError>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LookupUser {
+    pub username: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LookupError {
+    #[serde(default)]
+    pub value: Option<String>,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub detail: String,
+}
+
+pub fn classify(handle: &str, response: &LookupResponse) -> HandleStatus {
+    if let Some(user) = response.data.iter().find(|u| u.username.eq_ignore_ascii_case(handle)) {
+        return if user.username == handle {
+            HandleStatus::Ok
+        } else {
+            HandleStatus::Renamed { username: user.username.clone() }
+        };
+    }
+
+    let error = response.errors.iter().find(|e| e.value.as_deref().is_some_and(|v| v.eq_ignore_ascii_case(handle)));
+    match error {
+        Some(e) if e.title.contains("Forbidden") || e.detail.to_lowercase().contains("suspended") => HandleStatus::Suspended,
+        _ => HandleStatus::Missing
+    }
+}
+
+pub fn get_lookup_url(base: &str, handles: &[String]) -> String {
+    format!("{}?usernames={}", base, handles.join(","))
+}
+
+pub fn check_handles(client: &ApiClient, base: &str, members: &Members) -> Result<Vec<HandleCheck>, TwitterError> {
+    let handles = members.members.iter()
+        .filter_map(|m| m.twitter_handle().map(|h| (m, h)))
+        .collect::<Vec<_>>();
+
+    let mut checks = vec![];
+    for batch in handles.chunks(LOOKUP_BATCH_SIZE) {
+        let url = get_lookup_url(base, &batch.iter().map(|(_, h)| h.clone()).collect::<Vec<_>>());
+        log!("Looking up {} Twitter handles...", batch.len());
+        let body = client.get_json(&url)?;
+        let response: LookupResponse = serde_json::from_str(&body)
+            .map_err(|e| TwitterError::Response { url: url.clone(), source: e })?;
+
+        for (member, handle) in batch {
+            checks.push(HandleCheck {
+                member_id: member.id,
+                name: member.name.clone(),
+                handle: handle.clone(),
+                status: classify(handle, &response),
+            });
+        }
+    }
+
+    Ok(checks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::TlsOptions;
+    use crate::config::Config;
+    use crate::parse::parse_addresses_xml;
+    use crate::test_support::{document, MockResponse, MockServer};
+    use std::time::Duration;
+
+    #[test]
+    fn handles_are_classified_from_lookup_responses() {
+        let twitter = |handle: &str| format!(
+            "<Addresses><Address><Type>Twitter</Type><Address1>https://twitter.com/{}</Address1></Address></Addresses>", handle);
+        let data = document(&[
+            (172, &twitter("HackneyAbbott")),
+            (4212, &twitter("Anna_Soubry")),
+            (1524, &twitter("SpeakerBercow")),
+            (4520, &twitter("GarethThomasMP")),
+            (4005, ""),
+        ]);
+        let members = parse_addresses_xml(&data, &Config::default()).unwrap();
+        let server = MockServer::new(vec![MockResponse::ok(r#"{
+            "data": [{"username": "HackneyAbbott"}, {"username": "anna_soubry"}],
+            "errors": [
+                {"value": "speakerbercow", "title": "Not Found Error", "detail": "Could not find user with usernames: [speakerbercow]."},
+                {"value": "GarethThomasMP", "title": "Forbidden", "detail": "User has been suspended: [GarethThomasMP]."}
+            ]
+        }"#)]);
+        let client = ApiClient::new(Duration::from_millis(0), TlsOptions::default(), &[]).unwrap();
+
+        let base = format!("{}/2/users/by", server.url());
+        let checks = check_handles(&client, &base, &members).unwrap();
+        let statuses: Vec<(u32, HandleStatus)> = checks.into_iter().map(|c| (c.member_id, c.status)).collect();
+
+        assert_eq!(server.paths(), ["/2/users/by?usernames=HackneyAbbott,Anna_Soubry,SpeakerBercow,GarethThomasMP"]);
+        assert_eq!(statuses, [
+            (172, HandleStatus::Ok),
+            (4212, HandleStatus::Renamed { username: "anna_soubry".to_string() }),
+            (1524, HandleStatus::Missing),
+            (4520, HandleStatus::Suspended),
+        ]);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::members::{House, Members};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FillRateDrop {
+    pub field: String,
+    pub previous: f64,
+    pub current: f64,
+}
+
+impl fmt::Display for FillRateDrop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} dropped from {:.1}% to {:.1}%", self.field, self.previous, self.current)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ValidationError {
+    #[error("feed contains unrecognised elements: {}", .0.join(", "))]
+    UnknownElements(Vec<String>),
+    #[error("Twitter coverage of {coverage:.1}% is below the required {required:.1}%")]
+    TwitterCoverage {
+        coverage: f64,
+        required: f64,
+    },
+    #[error("field coverage dropped sharply: {}", join_drops(.0))]
+    FillRateDrops(Vec<FillRateDrop>),
+    #[error("only {count} members were parsed, fewer than the {required} required to commit")]
+    TooFewMembers {
+        count: usize,
+        required: usize,
+    },
+}
+
+fn join_drops(drops: &[FillRateDrop]) -> String {
+    drops.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+}
+
+pub fn default_min_members(house: House) -> usize {
+    match house {
+        House::Commons => 500,
+        _ => 0
+    }
+}
+
+pub fn check_schema(members: &Members) -> Result<(), ValidationError> {
+    if members.unknown_elements.is_empty() {
+        return Ok(());
+    }
+
+    Err(ValidationError::UnknownElements(members.unknown_elements.iter().cloned().collect()))
+}
+
+pub fn check_twitter_coverage(members: &Members, required: f64) -> Result<(), ValidationError> {
+    let coverage = members.twitter_coverage();
+    if coverage < required {
+        return Err(ValidationError::TwitterCoverage { coverage, required });
+    }
+
+    Ok(())
+}
+
+pub fn check_member_count(members: &Members, required: usize) -> Result<(), ValidationError> {
+    let count = members.members.len();
+    if count < required {
+        return Err(ValidationError::TooFewMembers { count, required });
+    }
+
+    Ok(())
+}
+
+pub fn fill_rate_drops(previous: &BTreeMap<String, f64>, current: &BTreeMap<String, f64>, max_drop: f64) -> Vec<FillRateDrop> {
+    let mut drops = vec![];
+    for (field, &previous) in previous {
+        let current = current.get(field).copied().unwrap_or(0.0);
+        if previous - current > max_drop {
+            drops.push(FillRateDrop { field: field.clone(), previous, current });
+        }
+    }
+    drops
+}
@@ -0,0 +1,535 @@
+use crate::members::{self, Member, Members};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StoredMember {
+    pub member_id: u32,
+    pub name: String,
+    pub twitter: Option<String>,
+    #[serde(default)]
+    pub fingerprint: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Discrepancy {
+    Missing {
+        member_id: u32,
+        name: String,
+    },
+    Unexpected {
+        member_id: u32,
+        name: String,
+    },
+    NameChanged {
+        member_id: u32,
+        stored: String,
+        fetched: String,
+    },
+    TwitterChanged {
+        member_id: u32,
+        stored: Option<String>,
+        fetched: Option<String>,
+    },
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Discrepancy::Missing { member_id, name } => write!(f, "{} ({}) is not stored", name, member_id),
+            Discrepancy::Unexpected { member_id, name } => write!(f, "{} ({}) is stored but no longer in the feed", name, member_id),
+            Discrepancy::NameChanged { member_id, stored, fetched } => write!(f, "{} ({}) is stored as {}", fetched, member_id, stored),
+            Discrepancy::TwitterChanged { member_id, stored, fetched } => write!(f, "Twitter handle of {} changed from {:?} to {:?}", member_id, stored, fetched),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Verification {
+    pub checked: usize,
+    pub stored: usize,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+pub fn compare(fetched: &Members, stored: &[StoredMember]) -> Verification {
+    let mut stored_by_id: HashMap<u32, &StoredMember> = stored.iter().map(|m| (m.member_id, m)).collect();
+
+    let mut verification = Verification {
+        checked: fetched.members.len(),
+        stored: stored.len(),
+        discrepancies: vec![],
+    };
+
+    for member in &fetched.members {
+        let s = match stored_by_id.remove(&member.id) {
+            Some(s) => s,
+            None => {
+                verification.discrepancies.push(Discrepancy::Missing { member_id: member.id, name: member.name.clone() });
+                continue;
+            }
+        };
+
+        if s.name != member.name {
+            verification.discrepancies.push(Discrepancy::NameChanged {
+                member_id: member.id,
+                stored: s.name.clone(),
+                fetched: member.name.clone(),
+            });
+        }
+
+        let handle = member.twitter_handle();
+        if s.twitter != handle {
+            verification.discrepancies.push(Discrepancy::TwitterChanged {
+                member_id: member.id,
+                stored: s.twitter.clone(),
+                fetched: handle,
+            });
+        }
+    }
+
+    let mut unexpected = stored_by_id.into_values().collect::<Vec<_>>();
+    unexpected.sort_by_key(|s| s.member_id);
+    for s in unexpected {
+        verification.discrepancies.push(Discrepancy::Unexpected { member_id: s.member_id, name: s.name.clone() });
+    }
+
+    verification
+}
+
+pub fn check_writes(committed: &Members, stored: &[StoredMember]) -> Vec<Discrepancy> {
+    compare(committed, stored).discrepancies.into_iter()
+        .filter(|d| matches!(d, Discrepancy::Missing { .. } | Discrepancy::NameChanged { .. }))
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PlannedChange {
+    Add {
+        member_id: u32,
+        name: String,
+    },
+    Update {
+        member_id: u32,
+        name: String,
+        fields: Vec<&'static str>,
+    },
+    Stale {
+        member_id: u32,
+        name: String,
+    },
+}
+
+impl fmt::Display for PlannedChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlannedChange::Add { member_id, name } => write!(f, "+ {} ({})", name, member_id),
+            PlannedChange::Update { member_id, name, fields } if fields.is_empty() => write!(f, "~ {} ({})", name, member_id),
+            PlannedChange::Update { member_id, name, fields } => write!(f, "~ {} ({}): {}", name, member_id, fields.join(", ")),
+            PlannedChange::Stale { member_id, name } => write!(f, "- {} ({}) is no longer in the feed and will be left in place", name, member_id),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Plan {
+    pub fetched: usize,
+    pub stored: usize,
+    pub unchanged: usize,
+    pub changes: Vec<PlannedChange>,
+}
+
+impl Plan {
+    pub fn pending(&self) -> usize {
+        self.changes.iter().filter(|c| !matches!(c, PlannedChange::Stale { .. })).count()
+    }
+}
+
+pub fn plan(fetched: &Members, stored: &[StoredMember], fingerprint_fields: &[String]) -> Plan {
+    let mut stored_by_id: HashMap<u32, &StoredMember> = stored.iter().map(|m| (m.member_id, m)).collect();
+
+    let mut plan = Plan {
+        fetched: fetched.members.len(),
+        stored: stored.len(),
+        unchanged: 0,
+        changes: vec![],
+    };
+
+    for member in &fetched.members {
+        let s = match stored_by_id.remove(&member.id) {
+            Some(s) => s,
+            None => {
+                plan.changes.push(PlannedChange::Add { member_id: member.id, name: member.name.clone() });
+                continue;
+            }
+        };
+
+        let fingerprint = format!("{:016x}", members::fingerprint(member, fingerprint_fields));
+        if s.fingerprint.as_ref() == Some(&fingerprint) {
+            plan.unchanged += 1;
+            continue;
+        }
+
+        let mut fields = vec![];
+        if s.name != member.name {
+            fields.push("name");
+        }
+        if s.twitter != member.twitter_handle() {
+            fields.push("twitter");
+        }
+        plan.changes.push(PlannedChange::Update { member_id: member.id, name: member.name.clone(), fields });
+    }
+
+    let mut stale = stored_by_id.into_values().collect::<Vec<_>>();
+    stale.sort_by_key(|s| s.member_id);
+    for s in stale {
+        plan.changes.push(PlannedChange::Stale { member_id: s.member_id, name: s.name.clone() });
+    }
+
+    plan
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LegacyNode {
+    pub uid: String,
+    pub member_id: Option<u32>,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Relink {
+    Link {
+        uid: String,
+        member_id: u32,
+    },
+    Unresolved {
+        uid: String,
+        name: Option<String>,
+        reason: &'static str,
+    },
+}
+
+impl fmt::Display for Relink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Relink::Link { uid, member_id } => write!(f, "{} -> {}", uid, members::xid_for_id(*member_id)),
+            Relink::Unresolved { uid, name: Some(name), reason } => write!(f, "{} ({}): {}", uid, name, reason),
+            Relink::Unresolved { uid, name: None, reason } => write!(f, "{}: {}", uid, reason),
+        }
+    }
+}
+
+// Nodes without a member_id are matched on their name against the fetched members, and only when exactly one matches
+pub fn plan_relink(fetched: &Members, linked: &HashSet<u32>, legacy: &[LegacyNode]) -> Vec<Relink> {
+    let mut claimed = linked.clone();
+    let mut relinks = vec![];
+    for node in legacy {
+        let member_id = match (node.member_id, &node.name) {
+            (Some(id), _) => Ok(id),
+            (None, Some(name)) => {
+                let matches = fetched.members.iter()
+                    .filter(|m| m.name == *name || m.preferred_name() == name)
+                    .map(|m| m.id).collect::<Vec<_>>();
+                match matches[..] {
+                    [id] => Ok(id),
+                    [] => Err("no fetched member has this name"),
+                    _ => Err("several fetched members have this name"),
+                }
+            }
+            (None, None) => Err("node has neither a member_id nor a name"),
+        };
+
+        relinks.push(match member_id {
+            Ok(id) if !claimed.insert(id) => Relink::Unresolved {
+                uid: node.uid.clone(),
+                name: node.name.clone(),
+                reason: "another node is already linked to this member",
+            },
+            Ok(id) => Relink::Link { uid: node.uid.clone(), member_id: id },
+            Err(reason) => Relink::Unresolved { uid: node.uid.clone(), name: node.name.clone(), reason },
+        });
+    }
+    relinks
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FormatDiscrepancy {
+    OnlyInXml {
+        member_id: u32,
+        name: String,
+    },
+    OnlyInJson {
+        member_id: u32,
+        name: String,
+    },
+    NameDiffers {
+        member_id: u32,
+        xml: String,
+        json: String,
+    },
+    PartyDiffers {
+        member_id: u32,
+        xml: String,
+        json: String,
+    },
+    TwitterDiffers {
+        member_id: u32,
+        xml: Option<String>,
+        json: Option<String>,
+    },
+}
+
+impl fmt::Display for FormatDiscrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatDiscrepancy::OnlyInXml { member_id, name } => write!(f, "{} ({}) is only in the XML feed", name, member_id),
+            FormatDiscrepancy::OnlyInJson { member_id, name } => write!(f, "{} ({}) is only in the JSON feed", name, member_id),
+            FormatDiscrepancy::NameDiffers { member_id, xml, json } => write!(f, "Name of {} is {:?} in XML but {:?} in JSON", member_id, xml, json),
+            FormatDiscrepancy::PartyDiffers { member_id, xml, json } => write!(f, "Party of {} is {:?} in XML but {:?} in JSON", member_id, xml, json),
+            FormatDiscrepancy::TwitterDiffers { member_id, xml, json } => write!(f, "Twitter handle of {} is {:?} in XML but {:?} in JSON", member_id, xml, json),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CrossValidation {
+    pub xml_members: usize,
+    pub json_members: usize,
+    pub discrepancies: Vec<FormatDiscrepancy>,
+}
+
+pub fn cross_validate(xml: &Members, json: &Members) -> CrossValidation {
+    let mut json_by_id: HashMap<u32, &Member> = json.members.iter().map(|m| (m.id, m)).collect();
+
+    let mut validation = CrossValidation {
+        xml_members: xml.members.len(),
+        json_members: json.members.len(),
+        discrepancies: vec![],
+    };
+
+    for x in &xml.members {
+        let j = match json_by_id.remove(&x.id) {
+            Some(j) => j,
+            None => {
+                validation.discrepancies.push(FormatDiscrepancy::OnlyInXml { member_id: x.id, name: x.name.clone() });
+                continue;
+            }
+        };
+
+        if x.name != j.name {
+            validation.discrepancies.push(FormatDiscrepancy::NameDiffers {
+                member_id: x.id,
+                xml: x.name.clone(),
+                json: j.name.clone(),
+            });
+        }
+        if x.current_party() != j.current_party() {
+            validation.discrepancies.push(FormatDiscrepancy::PartyDiffers {
+                member_id: x.id,
+                xml: x.current_party().to_string(),
+                json: j.current_party().to_string(),
+            });
+        }
+        if x.twitter_handle() != j.twitter_handle() {
+            validation.discrepancies.push(FormatDiscrepancy::TwitterDiffers {
+                member_id: x.id,
+                xml: x.twitter_handle(),
+                json: j.twitter_handle(),
+            });
+        }
+    }
+
+    let mut only_json = json_by_id.into_values().collect::<Vec<_>>();
+    only_json.sort_by_key(|m| m.id);
+    for j in only_json {
+        validation.discrepancies.push(FormatDiscrepancy::OnlyInJson { member_id: j.id, name: j.name.clone() });
+    }
+
+    validation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::parse::{parse_addresses_json, parse_addresses_xml};
+    use crate::test_support::{commons, document};
+
+    #[test]
+    fn fixture_is_compared_with_the_store() {
+        let twitter = |handle: &str| format!("<Addresses><Address><Type>Twitter</Type><Address1>{}</Address1></Address></Addresses>", handle);
+        let data = document(&[(172, &twitter("@HackneyAbbott")), (4212, &twitter("IanLaveryMP")), (1524, "")]);
+        let fetched = parse_addresses_xml(&data, &Config::default()).unwrap();
+
+        let stored = |member_id: u32, name: &str, twitter: Option<&str>| StoredMember {
+            member_id,
+            name: name.to_string(),
+            twitter: twitter.map(str::to_string),
+            fingerprint: None,
+        };
+        let store = vec![
+            stored(172, "Member 172", Some("HackneyAbbott")),
+            stored(4212, "Ian Lavery", Some("IanLaveryMP")),
+            stored(4005, "Member 4005", None),
+            stored(1, "Member 1", Some("old")),
+        ];
+
+        let verification = compare(&fetched, &store);
+        assert_eq!((verification.checked, verification.stored), (3, 4));
+        let discrepancies: Vec<String> = verification.discrepancies.iter().map(ToString::to_string).collect();
+        assert_eq!(discrepancies, [
+            "Member 4212 (4212) is stored as Ian Lavery",
+            "Member 1524 (1524) is not stored",
+            "Member 1 (1) is stored but no longer in the feed",
+            "Member 4005 (4005) is stored but no longer in the feed",
+        ]);
+    }
+
+    #[test]
+    fn xml_and_json_feeds_are_cross_validated() {
+        let labour = "<Parties><Party><Name>Labour</Name><StartDate>2010-05-06T00:00:00</StartDate></Party></Parties>";
+        let xml = document(&[
+            (172, &format!("{}<Addresses><Address><Type>Twitter</Type><Address1>@HackneyAbbott</Address1></Address></Addresses>", labour)),
+            (4005, labour),
+            (1524, ""),
+        ]);
+        let xml = parse_addresses_xml(&xml, &Config::default()).unwrap();
+
+        let member = |id: u32, name: &str, party: &str, twitter: Option<&str>| {
+            let mut member = serde_json::json!({
+                "@Member_Id": id.to_string(),
+                "DisplayAs": name,
+                "Parties": {"Party": {"Name": party, "StartDate": "2010-05-06T00:00:00"}},
+            });
+            if let Some(twitter) = twitter {
+                member["Addresses"] = serde_json::json!({"Address": {"Type": "Twitter", "Address1": twitter}});
+            }
+            member
+        };
+        let json = |members: Vec<serde_json::Value>| {
+            parse_addresses_json(&serde_json::json!({"Members": {"Member": members}}).to_string(), &Config::default()).unwrap()
+        };
+
+        let matching = json(vec![
+            member(172, "Member 172", "Labour", Some("https://twitter.com/HackneyAbbott")),
+            member(4005, "Member 4005", "Labour", None),
+            serde_json::json!({"@Member_Id": "1524", "DisplayAs": "Member 1524"}),
+        ]);
+        let validation = cross_validate(&xml, &matching);
+        assert_eq!((validation.xml_members, validation.json_members), (3, 3));
+        assert!(validation.discrepancies.is_empty());
+
+        let mismatching = json(vec![
+            member(172, "Ms Diane Abbott", "Labour", Some("DianeAbbott")),
+            member(4005, "Member 4005", "Independent", None),
+            member(4520, "Member 4520", "Labour", None),
+        ]);
+        let discrepancies: Vec<String> = cross_validate(&xml, &mismatching).discrepancies.iter().map(ToString::to_string).collect();
+        assert_eq!(discrepancies, [
+            "Name of 172 is \"Member 172\" in XML but \"Ms Diane Abbott\" in JSON",
+            "Twitter handle of 172 is Some(\"HackneyAbbott\") in XML but Some(\"DianeAbbott\") in JSON",
+            "Party of 4005 is \"Labour\" in XML but \"Independent\" in JSON",
+            "Member 1524 (1524) is only in the XML feed",
+            "Member 4520 (4520) is only in the JSON feed",
+        ]);
+    }
+
+    #[test]
+    fn committed_members_are_read_back() {
+        let twitter = "<Addresses><Address><Type>Twitter</Type><Address1>@HackneyAbbott</Address1></Address></Addresses>";
+        let committed = parse_addresses_xml(&document(&[(172, twitter), (4212, ""), (1524, "")]), &Config::default()).unwrap();
+        let stored = |member_id: u32, name: &str| StoredMember { member_id, name: name.to_string(), twitter: None, fingerprint: None };
+
+        let all_landed = vec![stored(172, "Member 172"), stored(4212, "Member 4212"), stored(1524, "Member 1524"), stored(1, "Member 1")];
+        assert!(check_writes(&committed, &all_landed).is_empty());
+
+        let some_lost = vec![stored(172, "Member 172"), stored(4212, "Anna Soubry")];
+        let discrepancies: Vec<String> = check_writes(&committed, &some_lost).iter().map(ToString::to_string).collect();
+        assert_eq!(discrepancies, [
+            "Member 4212 (4212) is stored as Anna Soubry",
+            "Member 1524 (1524) is not stored",
+        ]);
+    }
+
+    #[test]
+    fn fixture_is_planned_against_an_in_memory_store() {
+        let fetched = parse_addresses_xml(commons(), &Config::default()).unwrap();
+        let stored_as = |member: &Member| StoredMember {
+            member_id: member.id,
+            name: member.name.clone(),
+            twitter: member.twitter_handle(),
+            fingerprint: Some(format!("{:016x}", members::fingerprint(member, &members::default_fingerprint_fields()))),
+        };
+
+        let mut store: Vec<StoredMember> = fetched.members.iter().skip(1).map(stored_as).collect();
+        store[0].name = "Anna Soubry MP".to_string();
+        store[0].fingerprint = Some("0".to_string());
+        store[1].twitter = Some("old_handle".to_string());
+        store[1].fingerprint = Some("0".to_string());
+        store[2].fingerprint = None;
+        store.push(StoredMember { member_id: 1, name: "Gone".to_string(), twitter: None, fingerprint: None });
+
+        let plan = plan(&fetched, &store, &members::default_fingerprint_fields());
+        assert_eq!((plan.fetched, plan.stored, plan.unchanged), (7, 7, 3));
+        let changes: Vec<String> = plan.changes.iter().map(ToString::to_string).collect();
+        assert_eq!(changes, [
+            "+ Ms Diane Abbott (172)",
+            "~ Anna Soubry (4212): name",
+            "~ Mr John Bercow (1524): twitter",
+            "~ Ian Lavery (4005)",
+            "- Gone (1) is no longer in the feed and will be left in place",
+        ]);
+        assert_eq!(plan.pending(), 4);
+
+        let json = serde_json::to_value(&plan.changes[2]).unwrap();
+        assert_eq!(json, serde_json::json!({"kind": "update", "member_id": 1524, "name": "Mr John Bercow", "fields": ["twitter"]}));
+
+        let unchanged: Vec<StoredMember> = fetched.members.iter().map(stored_as).collect();
+        assert_eq!(super::plan(&fetched, &unchanged, &members::default_fingerprint_fields()).pending(), 0);
+    }
+
+    #[test]
+    fn legacy_nodes_are_relinked_by_id_or_unique_name() {
+        let mut fetched = Members::new();
+        for (id, name) in &[(172, "Ms Diane Abbott"), (4212, "Anna Soubry"), (1524, "Mr John Bercow"), (4005, "John Smith"), (4006, "John Smith")] {
+            fetched.members.push(Member { id: *id, name: name.to_string(), ..Member::new() });
+        }
+        fetched.members[1].known_as = Some("Anna Soubry MP".to_string());
+        let node = |uid: &str, member_id: Option<u32>, name: Option<&str>| LegacyNode {
+            uid: uid.to_string(),
+            member_id,
+            name: name.map(str::to_string),
+        };
+        let legacy = [
+            node("0x1", Some(172), None),
+            node("0x2", None, Some("Anna Soubry MP")),
+            node("0x3", None, Some("Nobody In Particular")),
+            node("0x4", Some(172), Some("Ms Diane Abbott")),
+            node("0x5", Some(1524), Some("Mr John Bercow")),
+            node("0x6", None, Some("John Smith")),
+            node("0x7", None, None),
+        ];
+        let linked: HashSet<u32> = [1524].iter().copied().collect();
+
+        let relinks = plan_relink(&fetched, &linked, &legacy);
+        let outcome: Vec<(&str, Result<u32, &str>)> = relinks.iter().map(|r| match r {
+            Relink::Link { uid, member_id } => (uid.as_str(), Ok(*member_id)),
+            Relink::Unresolved { uid, reason, .. } => (uid.as_str(), Err(*reason)),
+        }).collect();
+        assert_eq!(outcome, [
+            ("0x1", Ok(172)),
+            ("0x2", Ok(4212)),
+            ("0x3", Err("no fetched member has this name")),
+            ("0x4", Err("another node is already linked to this member")),
+            ("0x5", Err("another node is already linked to this member")),
+            ("0x6", Err("several fetched members have this name")),
+            ("0x7", Err("node has neither a member_id nor a name")),
+        ]);
+        assert_eq!(relinks[0].to_string(), "0x1 -> uk-parliament/member/172");
+        assert_eq!(relinks[2].to_string(), "0x3 (Nobody In Particular): no fetched member has this name");
+    }
+}
@@ -0,0 +1,80 @@
+use crate::members::{House, Members};
+use chrono::{DateTime, Utc};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub struct Watermark {
+    path: PathBuf,
+}
+
+impl Watermark {
+    pub fn for_house(house: &House) -> Self {
+        Self::in_dir(Path::new("."), house)
+    }
+
+    pub fn in_dir(dir: &Path, house: &House) -> Self {
+        Self {
+            path: dir.join(format!(".member_loader-{}.watermark", house)),
+        }
+    }
+
+    pub fn load(&self) -> Option<DateTime<Utc>> {
+        match fs::read_to_string(&self.path) {
+            Ok(s) => s.trim().parse().ok(),
+            Err(_) => None
+        }
+    }
+
+    pub fn save(&self, watermark: DateTime<Utc>) -> io::Result<()> {
+        fs::write(&self.path, watermark.to_rfc3339())
+    }
+}
+
+/// The newest modification time among `members`, never moving back past `current`.
+pub fn advance(current: Option<DateTime<Utc>>, members: &Members) -> Option<DateTime<Utc>> {
+    members.members.iter().filter_map(|m| m.modified).chain(current).max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::members::Member;
+    use crate::parse::parse_date;
+    use crate::test_support::TempDir;
+
+    fn modified(dates: &[Option<&str>]) -> Members {
+        let mut members = Members::new();
+        for date in dates {
+            let mut member = Member::new();
+            member.modified = date.map(|d| parse_date(d).unwrap());
+            members.members.push(member);
+        }
+        members
+    }
+
+    #[test]
+    fn watermark_advances_to_the_newest_modification() {
+        let members = modified(&[Some("2019-12-13T00:00:00"), None, Some("2020-01-07T00:00:00")]);
+        let newest = parse_date("2020-01-07T00:00:00").ok();
+        assert_eq!(advance(None, &members), newest);
+        assert_eq!(advance(parse_date("2019-12-01T00:00:00").ok(), &members), newest);
+
+        let later = parse_date("2020-06-01T00:00:00").ok();
+        assert_eq!(advance(later, &members), later);
+        assert_eq!(advance(later, &modified(&[None])), later);
+        assert_eq!(advance(None, &modified(&[None])), None);
+    }
+
+    #[test]
+    fn watermark_round_trips_through_its_file() {
+        let dir = TempDir::new("watermark");
+        let watermark = Watermark::in_dir(dir.path(), &House::Commons);
+        assert_eq!(watermark.load(), None);
+
+        let t = parse_date("2020-01-07T12:30:00").unwrap();
+        watermark.save(t).unwrap();
+        assert_eq!(Watermark::in_dir(dir.path(), &House::Commons).load(), Some(t));
+        assert_eq!(Watermark::in_dir(dir.path(), &House::Lords).load(), None);
+    }
+}